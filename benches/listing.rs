@@ -0,0 +1,82 @@
+//! Collect, sort and format benchmarks over synthetic in-memory directories.
+
+use std::hint::black_box;
+use std::io;
+use std::path::Path;
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ls_rs::filesystem::{FileStat, MemoryFs, S_IFREG};
+use ls_rs::{collect_entries, print_long_format, print_single_column, sort_entries, Args, Config};
+
+const SIZES: &[usize] = &[1_000, 100_000, 1_000_000];
+
+/// A directory of `count` files with scattered names, sizes, times and owners
+fn synthetic_dir(count: usize) -> MemoryFs {
+    let mut fs = MemoryFs::new("/bench");
+    for i in 0..count as u64 {
+        // Scatter names so the sort has real work to do
+        let name = format!("/bench/file-{:08x}.dat", i.wrapping_mul(0x9E37_79B9) & 0xFFFF_FFFF);
+        fs.insert(name, FileStat {
+            mode: S_IFREG | 0o644,
+            size: (i % 4096) * 17,
+            uid: (i % 7) as u32,
+            gid: (i % 3) as u32,
+            nlink: 1,
+            ino: i + 2,
+            blocks: (i % 4096) / 8,
+            mtime: 1_600_000_000 + (i.wrapping_mul(7919) % 10_000_000) as i64,
+            ..FileStat::default()
+        });
+    }
+    fs
+}
+
+fn config(flags: &[&str]) -> Config {
+    let argv = std::iter::once("ls").chain(flags.iter().copied());
+    Config::from_args(&Args::parse_from(argv))
+}
+
+fn bench_listing(c: &mut Criterion) {
+    let dir = Path::new("/bench");
+    for &count in SIZES {
+        let fs = synthetic_dir(count);
+        let name_config = config(&["-1"]);
+        let entries = collect_entries(&fs, dir, &name_config).unwrap();
+
+        let mut group = c.benchmark_group(format!("{}_entries", count));
+        group.throughput(Throughput::Elements(count as u64));
+        if count > 100_000 {
+            group.sample_size(10);
+        }
+
+        group.bench_function("collect", |b| {
+            b.iter(|| collect_entries(&fs, black_box(dir), &name_config).unwrap())
+        });
+
+        for (label, flags) in [("name", &["-1"][..]), ("time", &["-1", "-t"]), ("size", &["-1", "-S"])] {
+            let sort_config = config(flags);
+            group.bench_with_input(BenchmarkId::new("sort", label), &entries, |b, entries| {
+                b.iter_batched(
+                    || entries.clone(),
+                    |mut entries| sort_entries(&mut entries, &sort_config),
+                    criterion::BatchSize::LargeInput,
+                )
+            });
+        }
+
+        // Numeric owners keep NSS lookups of the synthetic ids out of the measurement
+        let long_config = config(&["-l", "--no-lookup"]);
+        group.bench_function("format_long", |b| {
+            b.iter(|| print_long_format(&entries, &long_config, &mut io::sink(), false).unwrap())
+        });
+        group.bench_function("format_single_column", |b| {
+            b.iter(|| print_single_column(&entries, &name_config, &mut io::sink(), false).unwrap())
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_listing);
+criterion_main!(benches);
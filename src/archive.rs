@@ -0,0 +1,233 @@
+//! --archive: load a tar, tar.gz or zip operand into an in-memory tree so the
+//! normal collect/sort/format pipeline lists its members.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{Local, NaiveDate, TimeZone};
+
+use crate::backend::Backend;
+use crate::error::LsError;
+use crate::filesystem::{FileStat, Filesystem, MemoryFs, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG};
+
+enum Format {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".tar") {
+        Some(Format::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Format::TarGz)
+    } else if name.ends_with(".zip") {
+        Some(Format::Zip)
+    } else {
+        None
+    }
+}
+
+/// Whether `path` is named like an archive --archive can read
+fn is_archive(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+/// Archive files named on the command line, when --archive is given
+pub struct ArchiveBackend;
+
+impl Backend for ArchiveBackend {
+    fn accepts(&self, operand: &Path) -> bool {
+        is_archive(operand) && operand.is_file()
+    }
+
+    fn open(&self, operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        match open(operand) {
+            Ok(tree) => Ok(Box::new(tree)),
+            Err(source) => Err(LsError::CannotReadArchive { path: operand.to_path_buf(), source }),
+        }
+    }
+}
+
+/// One archive member, keyed by its path relative to the archive root
+struct Member {
+    stat: FileStat,
+    target: Option<PathBuf>,
+}
+
+/// Read the archive at `path` into a tree rooted at `path` itself
+fn open(path: &Path) -> io::Result<MemoryFs> {
+    let format = format_of(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown archive format"))?;
+    let file = File::open(path)?;
+    let members = match format {
+        Format::Tar => read_tar(file)?,
+        Format::TarGz => read_tar(flate2::read::GzDecoder::new(file))?,
+        Format::Zip => {
+            // Zip records no owners, so members belong to whoever owns the archive
+            use std::os::unix::fs::MetadataExt;
+            let owner = file.metadata()?;
+            read_zip(file, owner.uid(), owner.gid())?
+        }
+    };
+    Ok(build_tree(path, members))
+}
+
+fn read_tar(reader: impl Read) -> io::Result<Vec<(PathBuf, Member)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let kind = header.entry_type();
+        if kind.is_pax_global_extensions() || kind.is_pax_local_extensions() || kind.is_gnu_longname() || kind.is_gnu_longlink() {
+            continue;
+        }
+
+        let file_type = if kind.is_dir() {
+            S_IFDIR
+        } else if kind.is_symlink() {
+            S_IFLNK
+        } else if kind.is_character_special() {
+            S_IFCHR
+        } else if kind.is_block_special() {
+            S_IFBLK
+        } else if kind.is_fifo() {
+            S_IFIFO
+        } else {
+            S_IFREG
+        };
+        let size = entry.size();
+        let mtime = header.mtime().unwrap_or(0) as i64;
+        let major = header.device_major().ok().flatten().unwrap_or(0) as u64;
+        let minor = header.device_minor().ok().flatten().unwrap_or(0) as u64;
+
+        let stat = FileStat {
+            mode: file_type | (header.mode().unwrap_or(0o644) & 0o7777),
+            size,
+            uid: header.uid().unwrap_or(0) as u32,
+            gid: header.gid().unwrap_or(0) as u32,
+            nlink: 1,
+            rdev: (major << 24) | (minor & 0xFF_FFFF),
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            ..FileStat::default()
+        };
+        let target = if kind.is_symlink() { entry.link_name()?.map(|t| t.into_owned()) } else { None };
+        members.push((entry.path()?.into_owned(), Member { stat, target }));
+    }
+    Ok(members)
+}
+
+fn read_zip(file: File, uid: u32, gid: u32) -> io::Result<Vec<(PathBuf, Member)>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut members = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        // Skip names that would escape the archive root
+        let Some(path) = entry.enclosed_name() else { continue };
+
+        let default_mode = if entry.is_dir() { S_IFDIR | 0o755 } else { S_IFREG | 0o644 };
+        let mut mode = entry.unix_mode().unwrap_or(default_mode);
+        if mode & S_IFMT == 0 {
+            mode |= default_mode & S_IFMT;
+        }
+        // Zip timestamps are in the local time of whoever made the archive
+        let mtime = entry
+            .last_modified()
+            .and_then(|t| {
+                NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+                    .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())
+            })
+            .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+            .map_or(0, |t| t.timestamp());
+
+        let size = entry.size();
+        let target = if mode & S_IFMT == S_IFLNK {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            Some(PathBuf::from(target))
+        } else {
+            None
+        };
+        let stat = FileStat {
+            mode,
+            size,
+            uid,
+            gid,
+            nlink: 1,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            ..FileStat::default()
+        };
+        members.push((path, Member { stat, target }));
+    }
+    Ok(members)
+}
+
+/// Assemble members into a tree, inventing directories archives leave implicit
+fn build_tree(root: &Path, members: Vec<(PathBuf, Member)>) -> MemoryFs {
+    let implied_dir = || Member { stat: FileStat { mode: S_IFDIR | 0o755, nlink: 2, ..FileStat::default() }, target: None };
+
+    // Sorted so every parent is inserted before its children; later duplicates win like tar -x
+    let mut tree: BTreeMap<PathBuf, Member> = BTreeMap::new();
+    for (path, member) in members {
+        let relative: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        for ancestor in relative.ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()) {
+            tree.entry(ancestor.to_path_buf()).or_insert_with(implied_dir);
+        }
+        tree.insert(relative, member);
+    }
+
+    let mut fs = MemoryFs::new(root);
+    for (ino, (relative, member)) in tree.into_iter().enumerate() {
+        let path = root.join(&relative);
+        // A member replaced by a plain file can't hold the children recorded under it
+        if !path.parent().is_some_and(|parent| fs.symlink_metadata(parent).is_ok_and(|m| m.is_dir())) {
+            continue;
+        }
+        let stat = FileStat { ino: ino as u64 + 1, ..member.stat };
+        match member.target {
+            Some(target) => fs.insert_symlink(path, target, stat),
+            None => fs.insert(path, stat),
+        }
+    }
+    fs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tar_members_become_a_tree_with_implied_directories() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o640);
+        header.set_mtime(1_000_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, "./docs/readme.txt", &b"hello"[..]).unwrap();
+        let bytes = builder.into_inner().unwrap();
+
+        let members = read_tar(&bytes[..]).unwrap();
+        let fs = build_tree(Path::new("a.tar"), members);
+        let docs = fs.read_dir(Path::new("a.tar")).unwrap();
+        assert_eq!(docs.len(), 1);
+        assert!(fs.symlink_metadata(&docs[0].path).unwrap().is_dir());
+
+        let readme = fs.symlink_metadata(Path::new("a.tar/docs/readme.txt")).unwrap();
+        assert_eq!(readme.mode, S_IFREG | 0o640);
+        assert_eq!(readme.size, 5);
+        assert_eq!(readme.mtime, 1_000_000_000);
+    }
+}
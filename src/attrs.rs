@@ -0,0 +1,59 @@
+//! Rendering of file flags for the -O / --attrs long-format column.
+
+/// Inode attribute letters in lsattr(1) order
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+const FLAGS: &[(u32, char)] = &[
+    (0x0000_0001, 's'), // secure deletion
+    (0x0000_0002, 'u'), // undeletable
+    (0x0000_0008, 'S'), // synchronous updates
+    (0x0001_0000, 'D'), // synchronous directory updates
+    (0x0000_0010, 'i'), // immutable
+    (0x0000_0020, 'a'), // append only
+    (0x0000_0040, 'd'), // no dump
+    (0x0000_0080, 'A'), // no atime updates
+    (0x0000_0004, 'c'), // compressed
+    (0x0000_0800, 'E'), // encrypted
+    (0x0000_4000, 'j'), // data journaling
+    (0x0000_1000, 'I'), // indexed directory
+    (0x0000_8000, 't'), // no tail merging
+    (0x0002_0000, 'T'), // top of directory hierarchy
+    (0x0008_0000, 'e'), // extents
+    (0x0080_0000, 'C'), // no copy on write
+    (0x0200_0000, 'x'), // direct access
+    (0x4000_0000, 'F'), // casefolded directory
+    (0x1000_0000, 'N'), // inline data
+    (0x2000_0000, 'P'), // project hierarchy
+    (0x0010_0000, 'V'), // verity protected
+    (0x0000_0400, 'm'), // don't compress
+];
+
+/// Flag names as printed by BSD `ls -lO`
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+const FLAGS: &[(u32, &str)] = &[
+    (0x0000_0001, "nodump"),
+    (0x0000_0002, "uchg"),
+    (0x0000_0004, "uappnd"),
+    (0x0000_0008, "opaque"),
+    (0x0000_0020, "compressed"),
+    (0x0000_8000, "hidden"),
+    (0x0001_0000, "arch"),
+    (0x0002_0000, "schg"),
+    (0x0004_0000, "sappnd"),
+    (0x0008_0000, "restricted"),
+    (0x0010_0000, "sunlnk"),
+];
+
+/// Render flags lsattr-style, one position per attribute with `-` when unset
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+pub fn format_flags(flags: Option<u32>) -> String {
+    let flags = flags.unwrap_or(0);
+    FLAGS.iter().map(|&(bit, ch)| if flags & bit != 0 { ch } else { '-' }).collect()
+}
+
+/// Render flags as a comma-separated list of names, or `-` when none are set
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+pub fn format_flags(flags: Option<u32>) -> String {
+    let flags = flags.unwrap_or(0);
+    let names: Vec<&str> = FLAGS.iter().filter(|&&(bit, _)| flags & bit != 0).map(|&(_, name)| name).collect();
+    if names.is_empty() { "-".to_string() } else { names.join(",") }
+}
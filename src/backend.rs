@@ -0,0 +1,112 @@
+//! Operand dispatch. Each `Backend` claims the operands it understands (a URL
+//! scheme, an archive extension, ...) and opens them as a `Filesystem`, from
+//! which the shared pipeline collects, sorts and formats `Entry` values.
+//!
+//! Backends are tried in order and the local filesystem is always last, so
+//! third-party crates can put their own in front with `run_with_backends`.
+
+use std::path::Path;
+
+use crate::error::LsError;
+use crate::filesystem::Filesystem;
+use crate::Config;
+
+/// A source of listings selected per operand
+pub trait Backend: Sync {
+    /// Whether this backend handles `operand`
+    fn accepts(&self, operand: &Path) -> bool;
+
+    /// Open `operand`; it is then listed as a path within the returned tree
+    fn open(&self, operand: &Path) -> Result<Box<dyn Filesystem>, LsError>;
+}
+
+/// A backend shared by reference, as --serve does across requests
+impl<B: Backend + ?Sized> Backend for &B {
+    fn accepts(&self, operand: &Path) -> bool {
+        (**self).accepts(operand)
+    }
+
+    fn open(&self, operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        (**self).open(operand)
+    }
+}
+
+/// Plain paths on the local machine
+pub struct LocalBackend;
+
+impl Backend for LocalBackend {
+    fn accepts(&self, _operand: &Path) -> bool {
+        true
+    }
+
+    fn open(&self, _operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        return Ok(Box::new(crate::uring::UringFs));
+        #[cfg(not(all(feature = "uring", target_os = "linux")))]
+        Ok(Box::new(crate::filesystem::LocalFs))
+    }
+}
+
+/// The ordered set of backends consulted for each operand
+pub struct Backends {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Backends {
+    /// `extra` backends first, then the ones compiled in, then the local filesystem
+    pub(crate) fn new(extra: Vec<Box<dyn Backend>>, config: &Config) -> Backends {
+        let mut backends = extra;
+        #[cfg(feature = "ssh")]
+        backends.push(Box::new(crate::sftp::SftpBackend));
+        #[cfg(feature = "s3")]
+        backends.push(Box::new(crate::s3::S3Backend));
+        #[cfg(feature = "archive")]
+        if config.archive {
+            backends.push(Box::new(crate::archive::ArchiveBackend));
+        }
+        if let Some(options) = config.cache {
+            backends.push(Box::new(crate::cache::CachedBackend { options }));
+        }
+        backends.push(Box::new(LocalBackend));
+        Backends { backends }
+    }
+
+    /// Open `operand` with the first backend that accepts it
+    pub fn open(&self, operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        let backend = self.backends.iter().find(|b| b.accepts(operand)).expect("the local backend accepts every operand");
+        backend.open(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+    use crate::Args;
+    use clap::Parser;
+
+    struct MemoryBackend;
+
+    impl Backend for MemoryBackend {
+        fn accepts(&self, operand: &Path) -> bool {
+            operand.starts_with("mem:")
+        }
+
+        fn open(&self, _operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+            Ok(Box::new(MemoryFs::new("mem:")))
+        }
+    }
+
+    #[test]
+    fn extra_backends_are_consulted_before_the_local_filesystem() {
+        let config = Config::from_args(&Args::parse_from(["ls"]));
+        let backends = Backends::new(vec![Box::new(MemoryBackend)], &config);
+
+        let memory = backends.open(Path::new("mem:")).unwrap();
+        assert!(memory.symlink_metadata(Path::new("mem:")).unwrap().is_dir());
+        // Anything else falls through to the local filesystem
+        let local = backends.open(Path::new("/")).unwrap();
+        assert!(local.symlink_metadata(Path::new("/")).unwrap().is_dir());
+        assert!(local.symlink_metadata(Path::new("mem:")).is_err());
+    }
+}
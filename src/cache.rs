@@ -0,0 +1,211 @@
+//! --cached: an on-disk cache of directory listings under the XDG cache dir,
+//! keyed by the directory's (dev, ino, mtime), so unchanged directories on
+//! slow network filesystems are served without a round trip per entry.
+//!
+//! A directory's mtime only moves when entries are added, removed or renamed;
+//! edits to a file inside it are not noticed until the TTL runs out or
+//! --refresh rewrites the cache.
+//!
+//! Listings are also kept in memory, which only pays off in a process that
+//! lists the same directories again, such as --serve.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::error::LsError;
+use crate::filesystem::{DirEntry, FileStat, Filesystem, LocalFs, XattrSummary};
+
+/// How cached listings are used
+#[derive(Debug, Clone, Copy)]
+pub struct CacheOptions {
+    /// Listings older than this are read again
+    pub ttl: Duration,
+    /// Ignore existing listings and write fresh ones
+    pub refresh: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedListing {
+    /// Unix time the listing was read
+    written: u64,
+    entries: Vec<CachedEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedEntry {
+    name: String,
+    // The name and link target as bytes, since JSON strings can't hold names
+    // that aren't UTF-8 and `name` is only their lossy form
+    raw_name: Vec<u8>,
+    stat: FileStat,
+    target: Option<Vec<u8>>,
+}
+
+// Listings this process has read or written, by cache file
+static MEMORY: Lazy<DashMap<PathBuf, CachedListing>> = Lazy::new(DashMap::new);
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// `$XDG_CACHE_HOME/ls-rs/listings`, defaulting to `~/.cache/ls-rs/listings`
+fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("ls-rs/listings"))
+}
+
+/// Local paths, read through the listing cache
+pub struct CachedBackend {
+    pub options: CacheOptions,
+}
+
+impl Backend for CachedBackend {
+    fn accepts(&self, _operand: &Path) -> bool {
+        true
+    }
+
+    fn open(&self, _operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        Ok(Box::new(CachedFs::new(LocalFs, self.options)))
+    }
+}
+
+/// A filesystem whose directory listings are served from, and saved to, the cache
+pub struct CachedFs<F> {
+    inner: F,
+    dir: Option<PathBuf>,
+    options: CacheOptions,
+    // Stats and link targets of every entry listed so far
+    known: DashMap<PathBuf, (FileStat, Option<PathBuf>)>,
+}
+
+impl<F: Filesystem> CachedFs<F> {
+    pub fn new(inner: F, options: CacheOptions) -> CachedFs<F> {
+        CachedFs { inner, dir: cache_dir(), options, known: DashMap::new() }
+    }
+
+    fn load(&self, file: &Path) -> Option<Vec<CachedEntry>> {
+        let fresh = |listing: &CachedListing| now().saturating_sub(listing.written) <= self.options.ttl.as_secs();
+        if let Some(listing) = MEMORY.get(file)
+            && fresh(&listing)
+        {
+            return Some(listing.entries.clone());
+        }
+        let listing: CachedListing = serde_json::from_slice(&fs::read(file).ok()?).ok()?;
+        if !fresh(&listing) {
+            return None;
+        }
+        MEMORY.insert(file.to_path_buf(), listing.clone());
+        Some(listing.entries)
+    }
+
+    /// Read `path` from the underlying filesystem; None if any entry couldn't be stat'ed
+    fn read_fresh(&self, path: &Path) -> io::Result<Option<Vec<CachedEntry>>> {
+        let listing = self.inner.read_dir(path)?;
+        let entries: Option<Vec<CachedEntry>> = listing
+            .into_par_iter()
+            .map(|entry| {
+                let stat = self.inner.symlink_metadata(&entry.path).ok()?;
+                let target = if stat.is_symlink() { self.inner.read_link(&entry.path).ok() } else { None };
+                Some(CachedEntry {
+                    raw_name: entry.path.file_name()?.as_bytes().to_vec(),
+                    name: entry.name,
+                    stat,
+                    target: target.map(|target| target.into_os_string().into_vec()),
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn save(file: &Path, entries: &[CachedEntry]) -> io::Result<()> {
+        let listing = CachedListing { written: now(), entries: entries.to_vec() };
+        fs::create_dir_all(file.parent().expect("cache files live in a directory"))?;
+        // Write then rename so a concurrent ls never reads half a listing
+        let partial = file.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&partial, serde_json::to_vec(&listing)?)?;
+        fs::rename(&partial, file)?;
+        MEMORY.insert(file.to_path_buf(), listing);
+        Ok(())
+    }
+}
+
+impl<F: Filesystem> Filesystem for CachedFs<F> {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let Some(dir) = &self.dir else {
+            return self.inner.read_dir(path);
+        };
+        let stat = self.inner.symlink_metadata(path)?;
+        let file = dir.join(format!("{:x}-{:x}-{}.json", stat.dev, stat.ino, stat.mtime));
+
+        let cached = if self.options.refresh { None } else { self.load(&file) };
+        crate::stats::record_cache(cached.is_some());
+        let entries = match cached {
+            Some(entries) => entries,
+            None => match self.read_fresh(path)? {
+                Some(entries) => {
+                    // Like git's racy-timestamp rule: a directory changed within the last
+                    // second could change again without its mtime moving
+                    if now().saturating_sub(stat.mtime.max(0) as u64) > 1 {
+                        let _ = Self::save(&file, &entries);
+                    }
+                    entries
+                }
+                // Leave the failures for the normal pipeline to report
+                None => return self.inner.read_dir(path),
+            },
+        };
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let child = path.join(OsStr::from_bytes(&entry.raw_name));
+                let target = entry.target.map(|target| PathBuf::from(OsString::from_vec(target)));
+                self.known.insert(child.clone(), (entry.stat, target));
+                DirEntry { name: entry.name, path: child }
+            })
+            .collect())
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+        match self.known.get(path) {
+            Some(known) => Ok(known.0.clone()),
+            None => self.inner.symlink_metadata(path),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.known.get(path).and_then(|known| known.1.clone()) {
+            Some(target) => Ok(target),
+            None => self.inner.read_link(path),
+        }
+    }
+
+    fn file_flags(&self, path: &Path) -> io::Result<u32> {
+        self.inner.file_flags(path)
+    }
+
+    fn xattr_summary(&self, path: &Path) -> XattrSummary {
+        self.inner.xattr_summary(path)
+    }
+
+    fn fs_type(&self, path: &Path) -> Option<String> {
+        self.inner.fs_type(path)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read_file(path)
+    }
+}
@@ -0,0 +1,71 @@
+//! Custom long-format columns. A `Column` computes one cell per entry, and
+//! the cells are shown before the name like the --trash columns. Columns come
+//! from --column-cmd NAME=COMMAND or from programs embedding the listing via
+//! `run_with_columns`.
+
+use std::fmt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use rayon::prelude::*;
+
+use crate::{Config, Entry};
+
+/// A source of one extra cell per entry
+pub trait Column: Send + Sync + fmt::Debug {
+    /// The cell for the file at `path`; empty when there is nothing to show
+    fn value(&self, path: &Path) -> String;
+}
+
+/// --column-cmd: the first line a shell command prints for each entry. The
+/// command runs with `sh -c`, with the entry's path as `$1`; one that can't
+/// start or exits unsuccessfully gives `?`
+#[derive(Debug)]
+pub(crate) struct CommandColumn {
+    pub(crate) name: String,
+    pub(crate) command: String,
+}
+
+impl Column for CommandColumn {
+    fn value(&self, path: &Path) -> String {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .arg(&self.name)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim_end().to_string()
+            }
+            _ => "?".to_string(),
+        }
+    }
+}
+
+/// Fill the custom columns of the entries about to be printed, in parallel
+/// since each cell may run a command
+pub(crate) fn fill(entries: &mut [Entry], config: &Config) {
+    if config.columns.is_empty() || !config.long {
+        return;
+    }
+    entries.par_iter_mut().for_each(|entry| {
+        let cells: Vec<String> = config.columns.iter().map(|column| column.value(&entry.path)).collect();
+        entry.extra_columns.extend(cells);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_columns_show_the_first_line_or_a_question_mark() {
+        let column = |command: &str| CommandColumn { name: "test".to_string(), command: command.to_string() };
+        assert_eq!(column("printf '%s\\nmore\\n' \"$1\"").value(Path::new("/some/path")), "/some/path");
+        assert_eq!(column("true").value(Path::new("x")), "");
+        assert_eq!(column("exit 3").value(Path::new("x")), "?");
+    }
+}
@@ -0,0 +1,52 @@
+//! macOS extended attribute inspection: the `@` mode suffix, Finder color
+//! labels and Gatekeeper quarantine.
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::filesystem::XattrSummary;
+
+const FINDER_INFO: &std::ffi::CStr = c"com.apple.FinderInfo";
+const QUARANTINE: &std::ffi::CStr = c"com.apple.quarantine";
+
+/// Inspect the extended attributes of `path` without following a final symlink
+pub fn xattr_summary(path: &Path) -> XattrSummary {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return XattrSummary::default();
+    };
+
+    unsafe {
+        let names_len = libc::listxattr(c_path.as_ptr(), std::ptr::null_mut(), 0, libc::XATTR_NOFOLLOW);
+        if names_len <= 0 {
+            return XattrSummary::default();
+        }
+
+        let quarantined = libc::getxattr(
+            c_path.as_ptr(),
+            QUARANTINE.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            0,
+            libc::XATTR_NOFOLLOW,
+        ) >= 0;
+
+        // The legacy label color lives in bits 1-3 of byte 9 of the 32-byte FinderInfo
+        let mut finder_info = [0u8; 32];
+        let read = libc::getxattr(
+            c_path.as_ptr(),
+            FINDER_INFO.as_ptr(),
+            finder_info.as_mut_ptr() as *mut libc::c_void,
+            finder_info.len(),
+            0,
+            libc::XATTR_NOFOLLOW,
+        );
+        let label = if read >= 10 { (finder_info[9] >> 1) & 0x7 } else { 0 };
+
+        XattrSummary {
+            has_xattrs: true,
+            finder_label: if label == 0 { None } else { Some(label) },
+            quarantined,
+        }
+    }
+}
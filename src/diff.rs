@@ -0,0 +1,93 @@
+//! --diff A B: a metadata-only comparison of two directories, like an rsync
+//! dry run. Entries only in A are marked `-`, only in B `+`, and entries whose
+//! type, size, mode or mtime differ `~`; identical entries are not shown.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::LsError;
+use crate::filesystem::{FileStat, Filesystem};
+use crate::{collect_entries, compare_names, format_mode, format_time, use_color, Config, Entry};
+
+/// One side of the comparison
+pub struct Side<'a> {
+    pub fs: &'a dyn Filesystem,
+    pub path: &'a Path,
+}
+
+pub fn diff_directories(a: Side, b: Side, config: &Config, out: &mut dyn Write) -> Result<(), LsError> {
+    let color = use_color(config);
+    diff_level(&a, a.path, &b, b.path, Path::new(""), config, color, out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diff_level(
+    a: &Side,
+    a_dir: &Path,
+    b: &Side,
+    b_dir: &Path,
+    relative: &Path,
+    config: &Config,
+    color: bool,
+    out: &mut dyn Write,
+) -> Result<(), LsError> {
+    let left = collect_entries(a.fs, a_dir, config)?;
+    let right = collect_entries(b.fs, b_dir, config)?;
+    let left_by_name: HashMap<&str, &Entry> = left.iter().map(|e| (e.name.as_str(), e)).collect();
+    let right_by_name: HashMap<&str, &Entry> = right.iter().map(|e| (e.name.as_str(), e)).collect();
+
+    let mut names: Vec<&str> = left.iter().chain(&right).map(|e| e.name.as_str()).collect();
+    names.sort_by(|x, y| compare_names(x, y, config.sort_case).then_with(|| x.cmp(y)));
+    names.dedup();
+
+    for name in names {
+        if name == "." || name == ".." {
+            continue;
+        }
+        let shown = relative.join(name);
+        match (left_by_name.get(name), right_by_name.get(name)) {
+            (Some(_), None) => mark(out, '-', "31", &shown, "", color)?,
+            (None, Some(_)) => mark(out, '+', "32", &shown, "", color)?,
+            (Some(l), Some(r)) => {
+                let changes = changes(&l.metadata, &r.metadata, config);
+                if !changes.is_empty() {
+                    mark(out, '~', "33", &shown, &format!("  {}", changes.join(", ")), color)?;
+                }
+                if config.recursive && l.metadata.is_dir() && r.metadata.is_dir() {
+                    match diff_level(a, &l.path, b, &r.path, &shown, config, color, out) {
+                        Err(e @ LsError::Write(_)) => return Err(e),
+                        Err(e) => crate::error::report(&e, config.errors, false),
+                        Ok(()) => {}
+                    }
+                }
+            }
+            (None, None) => unreachable!("every name comes from one of the sides"),
+        }
+    }
+    Ok(())
+}
+
+/// Human-readable list of what differs between two entries of the same name
+fn changes(l: &FileStat, r: &FileStat, config: &Config) -> Vec<String> {
+    let mut changes = Vec::new();
+    if l.mode != r.mode {
+        changes.push(format!("mode {} -> {}", format_mode(l.mode), format_mode(r.mode)));
+    }
+    // Directory sizes say nothing about their contents
+    if l.size != r.size && !(l.is_dir() && r.is_dir()) {
+        changes.push(format!("size {} -> {}", l.size, r.size));
+    }
+    if l.mtime != r.mtime && !(l.is_dir() && r.is_dir()) {
+        changes.push(format!("mtime {} -> {}", format_time(l.mtime, config.time_style, config.time_zone), format_time(r.mtime, config.time_style, config.time_zone)));
+    }
+    changes
+}
+
+fn mark(out: &mut dyn Write, marker: char, sgr: &str, path: &Path, detail: &str, color: bool) -> std::io::Result<()> {
+    if color {
+        writeln!(out, "\x1b[{}m{} {}\x1b[0m{}", sgr, marker, path.display(), detail)
+    } else {
+        writeln!(out, "{} {}{}", marker, path.display(), detail)
+    }
+}
@@ -0,0 +1,82 @@
+//! --du: in long listings a directory's size is the total of everything
+//! beneath it, the directory itself included. Like du(1), a file with several
+//! hard links is counted once per run, under whichever name the walk reaches
+//! first, unless --count-links asks for every name to be counted.
+
+use std::path::Path;
+
+use dashmap::DashSet;
+use rayon::prelude::*;
+
+use crate::filesystem::{FileStat, Filesystem};
+
+/// Apparent bytes and 512-byte blocks, the two sizes `--size` chooses between
+pub(crate) type Usage = (u64, u64);
+
+#[derive(Debug)]
+pub(crate) struct DiskUsage {
+    // (dev, ino) of the multiply-linked files counted so far; None under --count-links
+    seen: Option<DashSet<(u64, u64)>>,
+}
+
+impl DiskUsage {
+    pub(crate) fn new(count_links: bool) -> DiskUsage {
+        DiskUsage { seen: (!count_links).then(DashSet::new) }
+    }
+
+    /// Whether `stat` still counts; a hard link to a file already counted elsewhere does not
+    fn first_sight(&self, stat: &FileStat) -> bool {
+        match &self.seen {
+            Some(seen) if stat.nlink > 1 && !stat.is_dir() => seen.insert((stat.dev, stat.ino)),
+            _ => true,
+        }
+    }
+
+    /// The usage of the directory at `path` and its whole subtree. Symlinks are
+    /// not followed, and unreadable parts count as empty
+    pub(crate) fn total(&self, fs: &dyn Filesystem, path: &Path, own: &FileStat) -> Usage {
+        let Ok(children) = fs.read_dir(path) else {
+            return (own.size, own.blocks);
+        };
+        let paths: Vec<&Path> = children
+            .iter()
+            .filter(|child| child.name != "." && child.name != "..")
+            .map(|child| child.path.as_path())
+            .collect();
+        let stats = fs.symlink_metadata_batch(&paths);
+        let (size, blocks) = paths
+            .into_par_iter()
+            .zip(stats)
+            .filter_map(|(path, stat)| {
+                let stat = stat.ok()?;
+                if stat.is_dir() {
+                    Some(self.total(fs, path, &stat))
+                } else {
+                    self.first_sight(&stat).then_some((stat.size, stat.blocks))
+                }
+            })
+            .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        (own.size + size, own.blocks + blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::LocalFs;
+
+    #[test]
+    fn hard_links_count_once_unless_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("file"), vec![b'x'; 1000]).unwrap();
+        std::fs::hard_link(dir.path().join("file"), dir.path().join("sub/link")).unwrap();
+        let own = LocalFs.symlink_metadata(dir.path()).unwrap();
+        let sub = LocalFs.symlink_metadata(&dir.path().join("sub")).unwrap();
+
+        let (once, _) = DiskUsage::new(false).total(&LocalFs, dir.path(), &own);
+        assert_eq!(once, own.size + sub.size + 1000);
+        let (twice, _) = DiskUsage::new(true).total(&LocalFs, dir.path(), &own);
+        assert_eq!(twice, own.size + sub.size + 2000);
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+// Exit status accumulated over the run: 1 for minor problems, 2 for serious trouble
+static EXIT_STATUS: AtomicI32 = AtomicI32::new(0);
+
+// While --serve answers a request, reports are collected here instead of going to stderr
+static CAPTURE: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Unwinding payload of a fatal error during a captured request, which ends
+/// the request instead of the process
+pub(crate) struct Fatal;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+pub enum LsError {
+    /// stat of an operand or directory entry failed
+    CannotAccess { path: PathBuf, source: io::Error },
+    /// read_dir of a directory failed
+    CannotOpenDir { path: PathBuf, source: io::Error },
+    /// An operand list given with --files-from could not be read
+    CannotReadOperands { path: PathBuf, source: io::Error },
+    /// An --archive operand could not be opened or parsed
+    CannotReadArchive { path: PathBuf, source: io::Error },
+    /// A --snapshot file could not be written, or a --since-snapshot file read
+    Snapshot { path: PathBuf, source: io::Error },
+    /// -L led back to a directory that is already being listed
+    DirectoryCycle { path: PathBuf },
+    /// --detect-case-collisions found names that differ only in case; a warning
+    CaseCollisions { path: PathBuf, count: usize },
+    /// The --serve socket could not be set up
+    Serve { path: PathBuf, source: io::Error },
+    /// A flag received a value outside its accepted set
+    InvalidArgument { option: &'static str, value: String, expected: &'static str },
+    /// Writing the listing itself failed
+    Write(io::Error),
+}
+
+impl LsError {
+    /// Stable identifier for the failure kind, used in JSON output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LsError::CannotAccess { .. } => "cannot_access",
+            LsError::CannotOpenDir { .. } => "cannot_open_directory",
+            LsError::CannotReadOperands { .. } => "cannot_read_operands",
+            LsError::CannotReadArchive { .. } => "cannot_read_archive",
+            LsError::Snapshot { .. } => "snapshot",
+            LsError::DirectoryCycle { .. } => "directory_cycle",
+            LsError::CaseCollisions { .. } => "case_collisions",
+            LsError::Serve { .. } => "serve",
+            LsError::InvalidArgument { .. } => "invalid_argument",
+            LsError::Write(_) => "write",
+        }
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            LsError::CannotAccess { path, .. }
+            | LsError::CannotOpenDir { path, .. }
+            | LsError::CannotReadOperands { path, .. }
+            | LsError::CannotReadArchive { path, .. }
+            | LsError::Snapshot { path, .. }
+            | LsError::DirectoryCycle { path }
+            | LsError::CaseCollisions { path, .. }
+            | LsError::Serve { path, .. } => Some(path),
+            LsError::InvalidArgument { .. } | LsError::Write(_) => None,
+        }
+    }
+
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            LsError::CannotAccess { source, .. }
+            | LsError::CannotOpenDir { source, .. }
+            | LsError::CannotReadOperands { source, .. }
+            | LsError::CannotReadArchive { source, .. }
+            | LsError::Snapshot { source, .. }
+            | LsError::Serve { source, .. } => Some(source),
+            LsError::Write(source) => Some(source),
+            LsError::DirectoryCycle { .. } | LsError::CaseCollisions { .. } | LsError::InvalidArgument { .. } => None,
+        }
+    }
+
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, LsError::Write(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for LsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LsError::CannotAccess { path, source } => {
+                write!(f, "cannot access '{}': {}", path.display(), describe(source))
+            }
+            LsError::CannotOpenDir { path, source } => {
+                write!(f, "cannot open directory '{}': {}", path.display(), describe(source))
+            }
+            LsError::CannotReadOperands { path, source } => {
+                write!(f, "cannot open '{}' for reading: {}", path.display(), describe(source))
+            }
+            LsError::CannotReadArchive { path, source } => {
+                write!(f, "cannot read archive '{}': {}", path.display(), describe(source))
+            }
+            LsError::Snapshot { path, source } => {
+                write!(f, "snapshot '{}': {}", path.display(), describe(source))
+            }
+            LsError::DirectoryCycle { path } => {
+                write!(f, "{}: not listing already-listed directory", path.display())
+            }
+            LsError::CaseCollisions { path, count } => {
+                write!(f, "'{}': {} names differ from another only in case", path.display(), count)
+            }
+            LsError::Serve { path, source } => {
+                write!(f, "cannot serve on '{}': {}", path.display(), describe(source))
+            }
+            LsError::InvalidArgument { option, value, expected } => {
+                write!(f, "invalid argument '{}' for '{}' (expected {})", value, option, expected)
+            }
+            LsError::Write(source) => write!(f, "write error: {}", describe(source)),
+        }
+    }
+}
+
+impl std::error::Error for LsError {}
+
+impl From<io::Error> for LsError {
+    fn from(e: io::Error) -> Self {
+        LsError::Write(e)
+    }
+}
+
+/// The OS message for an io::Error without std's "(os error N)" suffix
+fn describe(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.find(" (os error ") {
+        Some(idx) => message[..idx].to_string(),
+        None => message,
+    }
+}
+
+/// Print an error to stderr in the requested format and record its exit status
+pub fn report(err: &LsError, format: ErrorFormat, serious: bool) {
+    EXIT_STATUS.fetch_max(if serious { 2 } else { 1 }, Ordering::Relaxed);
+    print(err, format);
+}
+
+/// Print a notice to stderr like an error, leaving the exit status alone
+pub fn warn(err: &LsError, format: ErrorFormat) {
+    print(err, format);
+}
+
+fn print(err: &LsError, format: ErrorFormat) {
+    let line = match format {
+        ErrorFormat::Text => format!("ls: {}", err),
+        ErrorFormat::Json => serde_json::json!({
+            "kind": err.kind(),
+            "path": err.path().map(|p| p.to_string_lossy().to_string()),
+            "errno": err.io_error().and_then(|e| e.raw_os_error()),
+            "message": err.to_string(),
+        })
+        .to_string(),
+    };
+    if let Some(captured) = CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        captured.extend_from_slice(line.as_bytes());
+        captured.push(b'\n');
+        return;
+    }
+    let _quiet = crate::progress::suspend();
+    eprintln!("{}", line);
+}
+
+/// Collect reports instead of printing them, starting from a clean exit status
+pub(crate) fn begin_capture() {
+    EXIT_STATUS.store(0, Ordering::Relaxed);
+    *CAPTURE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+}
+
+/// Stop collecting and return what was reported since `begin_capture`
+pub(crate) fn end_capture() -> Vec<u8> {
+    CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default()
+}
+
+fn capturing() -> bool {
+    CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+pub fn exit_status() -> i32 {
+    EXIT_STATUS.load(Ordering::Relaxed)
+}
+
+/// Report an error that prevents listing anything and exit with status 2
+pub fn fatal(err: &LsError, format: ErrorFormat) -> ! {
+    report(err, format, true);
+    if capturing() {
+        // resume_unwind skips the panic hook, so nothing is printed
+        std::panic::resume_unwind(Box::new(Fatal));
+    }
+    std::process::exit(2)
+}
@@ -0,0 +1,724 @@
+//! The filesystem operations the listing pipeline needs, behind a trait so
+//! tests and benchmarks can substitute an in-memory tree.
+
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFSOCK: u32 = 0o140000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFIFO: u32 = 0o010000;
+/// Solaris and illumos doors
+pub const S_IFDOOR: u32 = 0o150000;
+/// BSD union-mount whiteouts
+pub const S_IFWHT: u32 = 0o160000;
+
+/// What kind of file a mode describes. Doors and whiteouts only exist on the
+/// systems that have them; elsewhere their type bits decode as `Unknown`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Regular,
+    Directory,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    Door,
+    Whiteout,
+    Unknown,
+}
+
+const HAS_DOORS: bool = cfg!(any(target_os = "solaris", target_os = "illumos"));
+const HAS_WHITEOUTS: bool =
+    cfg!(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"));
+
+impl FileKind {
+    pub fn from_mode(mode: u32) -> FileKind {
+        match mode & S_IFMT {
+            S_IFREG => FileKind::Regular,
+            S_IFDIR => FileKind::Directory,
+            S_IFLNK => FileKind::Symlink,
+            S_IFIFO => FileKind::Fifo,
+            S_IFSOCK => FileKind::Socket,
+            S_IFBLK => FileKind::BlockDevice,
+            S_IFCHR => FileKind::CharDevice,
+            S_IFDOOR if HAS_DOORS => FileKind::Door,
+            S_IFWHT if HAS_WHITEOUTS => FileKind::Whiteout,
+            _ => FileKind::Unknown,
+        }
+    }
+
+    pub fn is_device(self) -> bool {
+        matches!(self, FileKind::BlockDevice | FileKind::CharDevice)
+    }
+}
+
+/// Symlinks resolved in a row before giving up with ELOOP, Linux's MAXSYMLINKS
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// The stat fields ls displays, independent of where they came from.
+///
+/// Entries keep this rather than the platform's `Metadata`, which carries
+/// fields ls never reads and can't be serialized for snapshots or the cache.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub mode: u32,
+    pub size: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub ino: u64,
+    pub dev: u64,
+    pub rdev: u64,
+    /// Allocated 512-byte blocks
+    pub blocks: u64,
+    /// Preferred I/O size; 0 where the source doesn't say
+    #[serde(default)]
+    pub blksize: u64,
+    pub atime: i64,
+    pub mtime: i64,
+    pub ctime: i64,
+    /// Nanoseconds past the whole seconds above; 0 where only seconds are known
+    #[serde(default)]
+    pub atime_nsec: u32,
+    #[serde(default)]
+    pub mtime_nsec: u32,
+    #[serde(default)]
+    pub ctime_nsec: u32,
+    /// Creation time, where the filesystem records one
+    #[serde(default)]
+    pub btime: Option<i64>,
+    #[serde(default)]
+    pub btime_nsec: u32,
+}
+
+impl FileStat {
+    pub fn file_type(&self) -> FileKind {
+        FileKind::from_mode(self.mode)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == FileKind::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == FileKind::Regular
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == FileKind::Symlink
+    }
+}
+
+impl From<&Metadata> for FileStat {
+    fn from(metadata: &Metadata) -> FileStat {
+        let birth = metadata.created().ok().map(|created| match created.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                // Whole seconds before the epoch, then forward by the nanoseconds
+                let secs = -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0);
+                (secs, (1_000_000_000 - before.subsec_nanos()) % 1_000_000_000)
+            }
+        });
+        FileStat {
+            mode: metadata.mode(),
+            size: metadata.len(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            nlink: metadata.nlink(),
+            ino: metadata.ino(),
+            dev: metadata.dev(),
+            rdev: metadata.rdev(),
+            blocks: metadata.blocks(),
+            blksize: metadata.blksize(),
+            atime: metadata.atime(),
+            mtime: metadata.mtime(),
+            ctime: metadata.ctime(),
+            atime_nsec: metadata.atime_nsec() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            btime: birth.map(|(secs, _)| secs),
+            btime_nsec: birth.map_or(0, |(_, nsec)| nsec),
+        }
+    }
+}
+
+/// What ls shows about a file's extended attributes (macOS)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct XattrSummary {
+    /// Any extended attribute is set, shown as `@` after the mode
+    pub has_xattrs: bool,
+    /// Finder color label, 1 (gray) through 7 (orange)
+    pub finder_label: Option<u8>,
+    /// Carries the Gatekeeper quarantine attribute
+    pub quarantined: bool,
+}
+
+/// Where a symlink ends up, for --check-links
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LinkStatus {
+    /// Reaches something after following `hops` links, the entry itself included
+    Resolves { hops: usize },
+    /// A link in the chain names nothing
+    Dangling,
+    /// The chain runs past `MAX_SYMLINK_DEPTH` links
+    Loop,
+}
+
+/// Follow the symlink at `path` as `Filesystem::metadata` does, counting the links on the way
+pub fn check_link(fs: &dyn Filesystem, path: &Path) -> LinkStatus {
+    let mut path = path.to_path_buf();
+    for hops in 0..=MAX_SYMLINK_DEPTH {
+        match fs.symlink_metadata(&path) {
+            Ok(stat) if stat.is_symlink() => {}
+            Ok(_) => return LinkStatus::Resolves { hops },
+            Err(e) if e.raw_os_error() == Some(libc::ELOOP) => return LinkStatus::Loop,
+            Err(_) => return LinkStatus::Dangling,
+        }
+        let Ok(target) = fs.read_link(&path) else {
+            return LinkStatus::Dangling;
+        };
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    LinkStatus::Loop
+}
+
+/// Directory reading with raw getdents64 calls into a large buffer, so a
+/// directory of hundreds of thousands of entries takes a few dozen syscalls
+/// instead of the thousands std's 32 KiB readdir buffer needs
+#[cfg(target_os = "linux")]
+mod getdents {
+    use std::cell::RefCell;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::DirEntry;
+
+    const BUFFER_SIZE: usize = 1 << 20;
+
+    // Offsets into struct linux_dirent64: d_ino (8), d_off (8), d_reclen (2), d_type (1), d_name
+    const RECLEN_OFFSET: usize = 16;
+    const NAME_OFFSET: usize = 19;
+
+    thread_local! {
+        // Reused by every directory a thread reads, so small directories don't pay for it
+        static BUFFER: RefCell<Vec<u8>> = RefCell::new(vec![0; BUFFER_SIZE]);
+    }
+
+    pub fn read_dir(path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        read_dir_each(path, &mut |entry| entries.push(entry))?;
+        Ok(entries)
+    }
+
+    /// Hand the entries of each getdents64 call to `each` before making the next
+    pub fn read_dir_each(path: &Path, each: &mut dyn FnMut(DirEntry)) -> io::Result<()> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let dir = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        loop {
+            // The buffer is released before `each` runs, which may read directories itself
+            let batch = BUFFER.with(|buffer| {
+                let mut buffer = buffer.borrow_mut();
+                let mut entries = Vec::new();
+                loop {
+                    let read = unsafe {
+                        libc::syscall(libc::SYS_getdents64, dir.as_raw_fd(), buffer.as_mut_ptr(), buffer.len())
+                    };
+                    match read {
+                        0 => return Ok(None),
+                        n if n < 0 => {
+                            let e = io::Error::last_os_error();
+                            if e.kind() != io::ErrorKind::Interrupted {
+                                return Err(e);
+                            }
+                        }
+                        n => {
+                            parse(&buffer[..n as usize], path, &mut entries);
+                            return Ok(Some(entries));
+                        }
+                    }
+                }
+            })?;
+            let Some(entries) = batch else {
+                return Ok(());
+            };
+            entries.into_iter().for_each(&mut *each);
+        }
+    }
+
+    fn parse(records: &[u8], dir: &Path, entries: &mut Vec<DirEntry>) {
+        let mut offset = 0;
+        while offset < records.len() {
+            let record = &records[offset..];
+            let reclen = u16::from_ne_bytes([record[RECLEN_OFFSET], record[RECLEN_OFFSET + 1]]) as usize;
+            let name = &record[NAME_OFFSET..reclen];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            offset += reclen;
+
+            if name == b"." || name == b".." {
+                continue;
+            }
+            let name = OsStr::from_bytes(name);
+            entries.push(DirEntry { name: name.to_string_lossy().into_owned(), path: dir.join(name) });
+        }
+    }
+}
+
+/// A name found while reading a directory
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub trait Filesystem: Sync {
+    /// Names in a directory, in the order the filesystem returns them
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// `read_dir`, handing names to `each` as they are read; by default all at
+    /// once at the end, so only filesystems that override it read in bounded memory
+    fn read_dir_each(&self, path: &Path, each: &mut dyn FnMut(DirEntry)) -> io::Result<()> {
+        self.read_dir(path)?.into_iter().for_each(each);
+        Ok(())
+    }
+
+    /// Stat without following a final symlink
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat>;
+
+    /// Stat following symlinks. By default each link is read and resolved
+    /// against its parent, so only a final link (not one in a leading
+    /// component) is followed; ELOOP after `MAX_SYMLINK_DEPTH` links
+    fn metadata(&self, path: &Path) -> io::Result<FileStat> {
+        let mut path = path.to_path_buf();
+        for _ in 0..=MAX_SYMLINK_DEPTH {
+            let stat = self.symlink_metadata(&path)?;
+            if !stat.is_symlink() {
+                return Ok(stat);
+            }
+            let target = self.read_link(&path)?;
+            // An absolute target replaces the whole path
+            path = match path.parent() {
+                Some(parent) => parent.join(target),
+                None => target,
+            };
+        }
+        Err(io::Error::from_raw_os_error(libc::ELOOP))
+    }
+
+    /// `symlink_metadata` of every path, in order; by default one call per
+    /// path, spread over the rayon pool when there are many
+    fn symlink_metadata_batch(&self, paths: &[&Path]) -> Vec<io::Result<FileStat>> {
+        if paths.len() < crate::PARALLEL_THRESHOLD {
+            paths.iter().map(|path| self.symlink_metadata(path)).collect()
+        } else {
+            paths.par_iter().map(|path| self.symlink_metadata(path)).collect()
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// BSD st_flags, or the Linux inode attribute flags read with FS_IOC_GETFLAGS
+    fn file_flags(&self, path: &Path) -> io::Result<u32>;
+
+    /// Extended attribute summary; empty on platforms where ls doesn't show it
+    fn xattr_summary(&self, path: &Path) -> XattrSummary;
+
+    /// Type of the filesystem `path` is on (ext4, nfs, tmpfs, ...), for --fs-info;
+    /// None where the source has no such notion
+    fn fs_type(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    /// Contents of a small file such as a directory's `.hidden` list;
+    /// unsupported unless the source can read files
+    fn read_file(&self, _path: &Path) -> io::Result<Vec<u8>> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+/// The real filesystem, via std::fs
+pub struct LocalFs;
+
+impl Filesystem for LocalFs {
+    #[cfg(target_os = "linux")]
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        getdents::read_dir(path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_dir_each(&self, path: &Path, each: &mut dyn FnMut(DirEntry)) -> io::Result<()> {
+        getdents::read_dir_each(path, each)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(DirEntry {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    path: entry.path(),
+                })
+            })
+            .collect()
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+        fs::symlink_metadata(path).map(|m| FileStat::from(&m))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileStat> {
+        fs::metadata(path).map(|m| FileStat::from(&m))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn file_flags(&self, path: &Path) -> io::Result<u32> {
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::AsRawFd;
+
+        // Same open mode as lsattr: never block on fifos or follow a final symlink
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+            .open(path)?;
+        let mut flags: libc::c_long = 0;
+        if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(flags as u32)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+    fn file_flags(&self, path: &Path) -> io::Result<u32> {
+        #[cfg(target_os = "macos")]
+        use std::os::macos::fs::MetadataExt;
+        #[cfg(target_os = "freebsd")]
+        use std::os::freebsd::fs::MetadataExt;
+        #[cfg(target_os = "netbsd")]
+        use std::os::netbsd::fs::MetadataExt;
+        #[cfg(target_os = "openbsd")]
+        use std::os::openbsd::fs::MetadataExt;
+        #[cfg(target_os = "dragonfly")]
+        use std::os::dragonfly::fs::MetadataExt;
+
+        fs::symlink_metadata(path).map(|m| m.st_flags())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+    fn file_flags(&self, _path: &Path) -> io::Result<u32> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn xattr_summary(&self, path: &Path) -> XattrSummary {
+        crate::darwin::xattr_summary(path)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn xattr_summary(&self, _path: &Path) -> XattrSummary {
+        XattrSummary::default()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fs_type(&self, path: &Path) -> Option<String> {
+        // Signed on some targets, but every magic fits in 32 bits
+        let magic = statfs(path)?.f_type as u32;
+        Some(linux_fs_name(magic).map_or_else(|| format!("0x{:x}", magic), str::to_string))
+    }
+
+    // getmntinfo's source: the BSDs name the type in the statfs record itself
+    #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+    fn fs_type(&self, path: &Path) -> Option<String> {
+        let stat = statfs(path)?;
+        let name: Vec<u8> = stat.f_fstypename.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+        Some(String::from_utf8_lossy(&name).into_owned())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+fn statfs(path: &Path) -> Option<libc::statfs> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    (unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } == 0).then_some(stat)
+}
+
+/// The name of a Linux statfs f_type magic number, as /proc/mounts would show it.
+/// The ext2, ext3 and ext4 drivers share one magic, so all three read as ext4
+#[cfg(target_os = "linux")]
+fn linux_fs_name(magic: u32) -> Option<&'static str> {
+    Some(match magic {
+        0xef53 => "ext4",
+        0x5846_5342 => "xfs",
+        0x9123_683e => "btrfs",
+        0x2fc1_2fc1 => "zfs",
+        0xf2f5_2010 => "f2fs",
+        0x0102_1994 => "tmpfs",
+        0x8584_58f6 => "ramfs",
+        0x794c_7630 => "overlay",
+        0x7371_7368 => "squashfs",
+        0x6969 => "nfs",
+        0xff53_4d42 => "cifs",
+        0xfe53_4d42 => "smb2",
+        0x0102_1997 => "9p",
+        0x00c3_6400 => "ceph",
+        0x6573_5546 => "fuse",
+        0x4d44 => "vfat",
+        0x2011_bab0 => "exfat",
+        0x5346_544e => "ntfs",
+        0x9660 => "iso9660",
+        0x9fa0 => "proc",
+        0x6265_6572 => "sysfs",
+        0x1cd1 => "devpts",
+        0x6367_7270 => "cgroup2",
+        0x0187 => "autofs",
+        _ => return None,
+    })
+}
+
+struct MemoryNode {
+    stat: FileStat,
+    flags: u32,
+    xattrs: XattrSummary,
+    target: Option<PathBuf>,
+    children: Vec<String>,
+}
+
+/// An in-memory tree with arbitrary metadata, for tests and benchmarks.
+///
+/// Nodes are keyed by path exactly as inserted; the parent of every inserted
+/// path must already exist as a directory.
+pub struct MemoryFs {
+    nodes: HashMap<PathBuf, MemoryNode>,
+}
+
+impl MemoryFs {
+    /// An empty tree containing only the directory `root`
+    pub fn new(root: impl Into<PathBuf>) -> MemoryFs {
+        let mut nodes = HashMap::new();
+        let stat = FileStat { mode: S_IFDIR | 0o755, nlink: 2, ..FileStat::default() };
+        nodes.insert(root.into(), MemoryNode { stat, flags: 0, xattrs: XattrSummary::default(), target: None, children: Vec::new() });
+        MemoryFs { nodes }
+    }
+
+    pub fn insert(&mut self, path: impl Into<PathBuf>, stat: FileStat) {
+        self.insert_node(path.into(), stat, None);
+    }
+
+    pub fn insert_symlink(&mut self, path: impl Into<PathBuf>, target: impl Into<PathBuf>, stat: FileStat) {
+        let stat = FileStat { mode: S_IFLNK | (stat.mode & !S_IFMT), ..stat };
+        self.insert_node(path.into(), stat, Some(target.into()));
+    }
+
+    fn insert_node(&mut self, path: PathBuf, stat: FileStat, target: Option<PathBuf>) {
+        let parent = path.parent().expect("inserted path has a parent");
+        let name = path.file_name().expect("inserted path has a file name").to_string_lossy().to_string();
+        let parent_node = self.nodes.get_mut(parent).expect("parent directory exists");
+        assert!(parent_node.stat.is_dir(), "parent is a directory");
+        parent_node.children.push(name);
+        self.nodes.insert(path, MemoryNode { stat, flags: 0, xattrs: XattrSummary::default(), target, children: Vec::new() });
+    }
+
+    /// Set the flags reported by `file_flags` for an inserted path
+    pub fn set_flags(&mut self, path: &Path, flags: u32) {
+        self.nodes.get_mut(path).expect("path exists").flags = flags;
+    }
+
+    /// Set the summary reported by `xattr_summary` for an inserted path
+    pub fn set_xattrs(&mut self, path: &Path, xattrs: XattrSummary) {
+        self.nodes.get_mut(path).expect("path exists").xattrs = xattrs;
+    }
+
+    fn node(&self, path: &Path) -> io::Result<&MemoryNode> {
+        self.nodes.get(path).ok_or_else(|| io::Error::from_raw_os_error(libc::ENOENT))
+    }
+}
+
+impl Filesystem for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let node = self.node(path)?;
+        if !node.stat.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        Ok(node.children.iter()
+            .map(|name| DirEntry { name: name.clone(), path: path.join(name) })
+            .collect())
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+        self.node(path).map(|node| node.stat.clone())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.node(path)?.target.clone().ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))
+    }
+
+    fn file_flags(&self, path: &Path) -> io::Result<u32> {
+        self.node(path).map(|node| node.flags)
+    }
+
+    fn xattr_summary(&self, path: &Path) -> XattrSummary {
+        self.node(path).map(|node| node.xattrs).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect_entries, list_directory, print_long_format, sort_entries, Args, Config};
+    use clap::Parser;
+
+    fn config(flags: &[&str]) -> Config {
+        Config::from_args(&Args::parse_from(std::iter::once("ls").chain(flags.iter().copied())))
+    }
+
+    fn long_listing(fs: &MemoryFs, path: &str, flags: &[&str]) -> String {
+        let config = config(flags);
+        let mut entries = collect_entries(fs, Path::new(path), &config).unwrap();
+        sort_entries(&mut entries, &config);
+        let mut out = Vec::new();
+        print_long_format(&entries, &config, &mut out, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn device_tree() -> MemoryFs {
+        let mut fs = MemoryFs::new("/dev");
+        fs.insert("/dev/sda", FileStat { mode: S_IFBLK | 0o660, rdev: (8 << 24) | 1, nlink: 1, ..FileStat::default() });
+        fs.insert("/dev/tty", FileStat { mode: S_IFCHR | 0o666, rdev: (5 << 24), nlink: 1, ..FileStat::default() });
+        fs.insert("/dev/huge", FileStat { mode: S_IFREG | 0o644, ino: u64::MAX, nlink: 1, ..FileStat::default() });
+        fs.insert_symlink("/dev/stdin", "/proc/self/fd/0", FileStat { nlink: 1, ..FileStat::default() });
+        fs
+    }
+
+    #[test]
+    fn device_files_show_major_minor() {
+        let out = long_listing(&device_tree(), "/dev", &["-l", "--no-lookup"]);
+        assert!(out.lines().any(|l| l.starts_with("brw-rw----") && l.contains("8, 1") && l.ends_with(" sda")));
+        assert!(out.lines().any(|l| l.starts_with("crw-rw-rw-") && l.contains("5, 0") && l.ends_with(" tty")));
+    }
+
+    #[test]
+    fn huge_inodes_are_aligned() {
+        let out = long_listing(&device_tree(), "/dev", &["-l", "-i", "--no-lookup"]);
+        let width = u64::MAX.to_string().len();
+        for line in out.lines() {
+            let (inode, rest) = line.split_at(width);
+            assert!(inode.trim_start().parse::<u64>().is_ok() && rest.starts_with(' '), "misaligned inode column in {:?}", line);
+        }
+    }
+
+    #[test]
+    fn symlinks_show_their_target() {
+        let out = long_listing(&device_tree(), "/dev", &["-l", "--no-lookup"]);
+        assert!(out.lines().any(|l| l.starts_with('l') && l.ends_with("stdin -> /proc/self/fd/0")));
+    }
+
+    #[test]
+    fn future_mtimes_show_the_year() {
+        let mut fs = MemoryFs::new("/d");
+        // 2100-01-01
+        fs.insert("/d/later", FileStat { mode: S_IFREG | 0o644, mtime: 4_102_444_800, nlink: 1, ..FileStat::default() });
+        let out = long_listing(&fs, "/d", &["-l", "--no-lookup"]);
+        assert!(out.contains(" 2100 later"), "{}", out);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn attrs_column_shows_inode_flags() {
+        let mut fs = MemoryFs::new("/d");
+        fs.insert("/d/locked", FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+        // FS_IMMUTABLE_FL | FS_APPEND_FL
+        fs.set_flags(Path::new("/d/locked"), 0x10 | 0x20);
+        let out = long_listing(&fs, "/d", &["-l", "--attrs", "--no-lookup"]);
+        assert!(out.contains(" ----ia---------------- "), "{}", out);
+    }
+
+    #[test]
+    fn xattr_annotations() {
+        let mut fs = MemoryFs::new("/d");
+        fs.insert("/d/download.dmg", FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+        fs.insert("/d/plain", FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+        fs.set_xattrs(Path::new("/d/download.dmg"), XattrSummary { has_xattrs: true, finder_label: Some(6), quarantined: true });
+        let out = long_listing(&fs, "/d", &["-l", "--tags", "--quarantine", "--no-lookup"]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("-rw-r--r--@ ") && lines[0].ends_with("download.dmg [red] [quarantined]"), "{}", out);
+        assert!(lines[1].starts_with("-rw-r--r--  ") && lines[1].ends_with(" plain"), "{}", out);
+    }
+
+    #[test]
+    fn missing_paths_report_cannot_access() {
+        let fs = MemoryFs::new("/d");
+        let err = collect_entries(&fs, Path::new("/d/missing"), &config(&[])).err().unwrap();
+        assert_eq!(err.to_string(), "cannot access '/d/missing': No such file or directory");
+    }
+
+    #[test]
+    fn metadata_follows_links_and_stops_at_loops() {
+        let mut fs = MemoryFs::new("/d");
+        fs.insert("/d/file", FileStat { mode: S_IFREG | 0o644, size: 7, ..FileStat::default() });
+        fs.insert_symlink("/d/one", "file", FileStat::default());
+        fs.insert_symlink("/d/two", "/d/one", FileStat::default());
+        fs.insert_symlink("/d/loop", "loop", FileStat::default());
+        fs.insert_symlink("/d/dangling", "gone", FileStat::default());
+
+        assert_eq!(fs.metadata(Path::new("/d/two")).unwrap().size, 7);
+        assert_eq!(fs.metadata(Path::new("/d/loop")).unwrap_err().raw_os_error(), Some(libc::ELOOP));
+        assert_eq!(fs.metadata(Path::new("/d/dangling")).unwrap_err().raw_os_error(), Some(libc::ENOENT));
+
+        assert_eq!(check_link(&fs, Path::new("/d/two")), LinkStatus::Resolves { hops: 2 });
+        assert_eq!(check_link(&fs, Path::new("/d/loop")), LinkStatus::Loop);
+        assert_eq!(check_link(&fs, Path::new("/d/dangling")), LinkStatus::Dangling);
+        let mut out = Vec::new();
+        list_directory(&fs, Path::new("/d"), &config(&["-1", "--check-links=chain"]), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "dangling [dangling]\nfile\nloop [loop]\none [1 link]\ntwo [2 links]\n");
+    }
+
+    #[test]
+    fn local_read_dir_returns_every_entry_once() {
+        let dir = tempfile::tempdir().unwrap();
+        // Enough long names to need many reads with a small buffer
+        let mut expected: Vec<String> = (0..3000).map(|i| format!("{:0>200}", i)).collect();
+        for name in &expected {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let entries = LocalFs.read_dir(dir.path()).unwrap();
+        assert!(entries.iter().all(|e| e.path == dir.path().join(&e.name)));
+        let mut names: Vec<String> = entries.into_iter().map(|e| e.name).collect();
+        names.sort();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+}
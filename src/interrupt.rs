@@ -0,0 +1,131 @@
+//! Ctrl-C handling for listings written to stdout. The signal handler only
+//! writes a byte to a pipe; a watcher thread does the rest outside signal
+//! context. It waits for the line being written to end, resets colors,
+//! flushes and exits with 130, so a listing never stops mid-line or leaves
+//! the terminal painted.
+//!
+//! With --finish-on-interrupt the watcher only records the request, and -R
+//! stops at the next directory boundary instead; a second Ctrl-C stops at
+//! once as usual.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::progress;
+
+// Write end of the pipe the handler signals through
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+// Whether the last byte written to stdout didn't end a line
+static MID_LINE: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+// How long a stop waits for the current line to be finished before ending it itself
+const LINE_GRACE: Duration = Duration::from_secs(1);
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    let byte = 1u8;
+    // write(2) is async-signal-safe; a full pipe already holds a pending signal
+    unsafe { libc::write(PIPE.load(Ordering::Relaxed), (&byte as *const u8).cast(), 1) };
+}
+
+/// Handle SIGINT for the rest of the process. `color` says whether the
+/// listing may leave an SGR sequence open; `finish` defers the first Ctrl-C
+/// to `stop_if_requested`
+pub fn install(color: bool, finish: bool) {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+    COLOR.store(color, Ordering::Relaxed);
+    if INSTALLED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return;
+    }
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    unsafe { libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK) };
+    PIPE.store(fds[1], Ordering::Relaxed);
+
+    thread::spawn(move || {
+        let mut byte = 0u8;
+        while unsafe { libc::read(fds[0], (&mut byte as *mut u8).cast(), 1) } == 1 {
+            if SIGNALS.fetch_add(1, Ordering::Relaxed) > 0 || !finish {
+                stop_after_line();
+            }
+        }
+    });
+
+    let handler: extern "C" fn(libc::c_int) = on_sigint;
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+/// Whether Ctrl-C has been pressed
+pub fn requested() -> bool {
+    SIGNALS.load(Ordering::Relaxed) > 0
+}
+
+/// With --finish-on-interrupt, called between directories: once Ctrl-C has
+/// been pressed, end the listing here
+pub fn stop_if_requested(out: &mut dyn Write) {
+    if requested() {
+        let _ = out.flush();
+        let _quiet = progress::suspend();
+        exit(&mut io::stdout().lock());
+    }
+}
+
+/// Wait for stdout to be between lines, then exit while holding it so nothing else gets written
+fn stop_after_line() -> ! {
+    let deadline = Instant::now() + LINE_GRACE;
+    loop {
+        {
+            // The same lock order as the progress indicator: its state, then stdout
+            let _quiet = progress::suspend();
+            let mut out = io::stdout().lock();
+            if !MID_LINE.load(Ordering::Relaxed) {
+                exit(&mut out);
+            }
+            if Instant::now() >= deadline {
+                let _ = out.write_all(b"\n");
+                exit(&mut out);
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn exit(out: &mut dyn Write) -> ! {
+    if COLOR.load(Ordering::Relaxed) {
+        let _ = out.write_all(b"\x1b[0m");
+    }
+    let _ = out.flush();
+    std::process::exit(130)
+}
+
+/// Stdout, keeping track of whether a line is in progress for `stop_after_line`
+pub struct TrackedStdout;
+
+impl Write for TrackedStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = io::stdout().lock();
+        let written = out.write(buf)?;
+        if let Some(&last) = buf[..written].last() {
+            MID_LINE.store(last != b'\n', Ordering::Relaxed);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
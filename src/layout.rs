@@ -0,0 +1,157 @@
+//! Grid layout for the multi-column output modes (-C and -x), and line
+//! wrapping for the comma-separated stream format (-m).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Fill each column top to bottom before moving right (-C)
+    Down,
+    /// Fill each row left to right before moving down (-x)
+    Across,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Grid {
+    /// Width of every column, including the gap after each cell
+    pub col_width: usize,
+    /// Entry indices to print on each line, left to right
+    pub rows: Vec<Vec<usize>>,
+}
+
+/// Arrange cells of the given display widths into a grid that fits `term_width`.
+///
+/// Every column is as wide as the widest cell plus a `gap` of that many
+/// columns; a cell wider than the terminal still gets a column of its own.
+pub fn grid(widths: &[usize], term_width: usize, gap: usize, direction: Direction) -> Grid {
+    let max_len = widths.iter().copied().max().unwrap_or(0);
+    let col_width = (max_len + gap).max(1);
+    let count = widths.len();
+
+    if count == 0 {
+        return Grid { col_width, rows: Vec::new() };
+    }
+
+    let num_cols = (term_width / col_width).max(1);
+    let num_rows = count.div_ceil(num_cols);
+
+    let rows = match direction {
+        Direction::Down => (0..num_rows)
+            .map(|row| {
+                (0..num_cols)
+                    .map(|col| col * num_rows + row)
+                    .filter(|&idx| idx < count)
+                    .collect()
+            })
+            .collect(),
+        Direction::Across => (0..count)
+            .collect::<Vec<_>>()
+            .chunks(num_cols)
+            .map(|chunk| chunk.to_vec())
+            .collect(),
+    };
+
+    Grid { col_width, rows }
+}
+
+/// Split cells of the given widths into lines of `name, name, ...` that fit
+/// `term_width`, breaking only between names.
+///
+/// Like GNU ls, a name stays on the current line only if it, its `, ` and the
+/// comma after it all fit; a name wider than the terminal gets a line of its own.
+pub fn stream(widths: &[usize], term_width: usize) -> Vec<Vec<usize>> {
+    let mut lines: Vec<Vec<usize>> = Vec::new();
+    let mut pos = 0;
+    for (idx, &width) in widths.iter().enumerate() {
+        match lines.last_mut() {
+            Some(line) if pos + 2 + width < term_width => {
+                line.push(idx);
+                pos += 2 + width;
+            }
+            _ => {
+                lines.push(vec![idx]);
+                pos = width;
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn widths_and_term() -> impl Strategy<Value = (Vec<usize>, usize)> {
+        (prop::collection::vec(0usize..60, 0..200), 1usize..300)
+    }
+
+    proptest! {
+        #[test]
+        fn every_entry_appears_exactly_once((widths, term) in widths_and_term(), across in any::<bool>()) {
+            let direction = if across { Direction::Across } else { Direction::Down };
+            let grid = grid(&widths, term, 2, direction);
+            let mut seen: Vec<usize> = grid.rows.iter().flatten().copied().collect();
+            seen.sort_unstable();
+            prop_assert_eq!(seen, (0..widths.len()).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn rows_fit_the_terminal((widths, term) in widths_and_term(), across in any::<bool>()) {
+            let direction = if across { Direction::Across } else { Direction::Down };
+            let grid = grid(&widths, term, 2, direction);
+            for row in &grid.rows {
+                prop_assert!(!row.is_empty());
+                if grid.col_width <= term {
+                    prop_assert!(row.len() * grid.col_width <= term);
+                } else {
+                    prop_assert_eq!(row.len(), 1);
+                }
+            }
+        }
+
+        #[test]
+        fn across_reads_in_order_by_rows((widths, term) in widths_and_term()) {
+            let grid = grid(&widths, term, 2, Direction::Across);
+            let order: Vec<usize> = grid.rows.iter().flatten().copied().collect();
+            prop_assert_eq!(order, (0..widths.len()).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn down_reads_in_order_by_columns((widths, term) in widths_and_term()) {
+            let grid = grid(&widths, term, 2, Direction::Down);
+            let num_cols = grid.rows.iter().map(|r| r.len()).max().unwrap_or(0);
+            let mut order: Vec<usize> = Vec::new();
+            for col in 0..num_cols {
+                order.extend(grid.rows.iter().filter_map(|row| row.get(col)));
+            }
+            prop_assert_eq!(order, (0..widths.len()).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn stream_breaks_between_names() {
+        // "aaa, bbb," is 9 columns; adding ", ccc," would need 15
+        assert_eq!(stream(&[3, 3, 3], 12), vec![vec![0, 1], vec![2]]);
+        assert_eq!(stream(&[3, 3, 3], 80), vec![vec![0, 1, 2]]);
+        assert_eq!(stream(&[20, 3], 10), vec![vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn empty_input_has_no_rows() {
+        assert!(grid(&[], 80, 2, Direction::Down).rows.is_empty());
+    }
+
+    #[test]
+    fn down_fills_columns_first() {
+        let grid = grid(&[3, 3, 3, 3, 3], 15, 2, Direction::Down);
+        assert_eq!(grid.col_width, 5);
+        assert_eq!(grid.rows, vec![vec![0, 2, 4], vec![1, 3]]);
+    }
+
+    #[test]
+    fn the_gap_widens_every_column() {
+        let grid = grid(&[3, 3, 3, 3, 3], 15, 0, Direction::Across);
+        assert_eq!(grid.col_width, 3);
+        assert_eq!(grid.rows, vec![vec![0, 1, 2, 3, 4]]);
+        assert_eq!(super::grid(&[0, 0], 80, 0, Direction::Across).col_width, 1);
+    }
+}
@@ -0,0 +1,3565 @@
+mod access;
+#[cfg(feature = "archive")]
+mod archive;
+mod attrs;
+pub mod backend;
+mod cache;
+pub mod columns;
+mod diff;
+mod du;
+#[cfg(target_os = "macos")]
+mod darwin;
+pub mod error;
+pub mod filesystem;
+mod interrupt;
+mod layout;
+mod porcelain;
+pub mod pipeline;
+mod progress;
+mod resolve;
+mod serve;
+mod side_by_side;
+mod snapshot;
+mod stats;
+mod termcaps;
+mod spill;
+mod stat_block;
+mod structured;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "ssh")]
+mod sftp;
+mod theme;
+mod trash;
+mod truncate;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+
+use clap::Parser;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use backend::{Backend, Backends};
+use error::{ErrorFormat, LsError};
+use filesystem::{DirEntry, FileKind, FileStat, Filesystem, LinkStatus, LocalFs, XattrSummary};
+use layout::Direction;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+
+// Global thread-safe caches of the names NSS gave user and group ids. Names
+// are interned, so a row takes a reference count instead of allocating a copy
+// of the name. Each lookup source has its own: ids shown as numbers share
+// NUMERIC_NAMES, and --passwd-file and --group-file names live in their table
+static USER_CACHE: Lazy<DashMap<u32, Arc<str>>> = Lazy::new(DashMap::new);
+static GROUP_CACHE: Lazy<DashMap<u32, Arc<str>>> = Lazy::new(DashMap::new);
+static NUMERIC_NAMES: Lazy<DashMap<u32, Arc<str>>> = Lazy::new(DashMap::new);
+
+// Listings smaller than this are stat'ed on the calling thread; handing a few
+// entries to the pool costs more than it saves
+pub(crate) const PARALLEL_THRESHOLD: usize = 64;
+
+// Worker threads used without --threads; more rarely help even on big machines
+const MAX_DEFAULT_THREADS: usize = 16;
+
+// How long output may stall before the progress indicator appears
+const PROGRESS_DELAY: Duration = Duration::from_millis(500);
+
+// Set once an NSS lookup has timed out; later lookups go straight to numeric ids
+static NSS_UNRESPONSIVE: AtomicBool = AtomicBool::new(false);
+
+/// Get user name with caching - thread-safe
+fn get_user_name_cached(uid: u32, lookup: &NameLookup) -> Arc<str> {
+    lookup_name(uid, lookup, &USER_CACHE, get_user_name)
+}
+
+/// Get group name with caching - thread-safe
+fn get_group_name_cached(gid: u32, lookup: &NameLookup) -> Arc<str> {
+    lookup_name(gid, lookup, &GROUP_CACHE, get_group_name)
+}
+
+/// The name `lookup` gives `id`, with NSS answers kept in `cache`
+fn lookup_name(id: u32, lookup: &NameLookup, cache: &DashMap<u32, Arc<str>>, resolve: fn(u32) -> String) -> Arc<str> {
+    let timeout = match lookup {
+        NameLookup::Numeric => return numeric_name(id),
+        NameLookup::Table(names) => return names.get(&id).cloned().unwrap_or_else(|| numeric_name(id)),
+        NameLookup::Resolve { timeout } => *timeout,
+    };
+    if let Some(name) = cache.get(&id) {
+        return name.clone();
+    }
+    // Resolve outside the shard lock so slow NSS lookups don't block other readers
+    match resolve_name(id, timeout, resolve) {
+        Some(name) => cache.entry(id).or_insert(name.into()).clone(),
+        None => numeric_name(id),
+    }
+}
+
+/// `id` written out, for ids shown without a name
+fn numeric_name(id: u32) -> Arc<str> {
+    if let Some(name) = NUMERIC_NAMES.get(&id) {
+        return name.clone();
+    }
+    NUMERIC_NAMES.entry(id).or_insert(id.to_string().into()).clone()
+}
+
+/// Resolve all distinct, not-yet-cached uids and gids of a listing in one parallel batch
+fn warm_name_caches(entries: &[Entry], users: &NameLookup, groups: &NameLookup) {
+    use std::collections::HashSet;
+
+    let warm = |ids: HashSet<u32>, lookup: &NameLookup, cache: &DashMap<u32, Arc<str>>, resolve: fn(u32) -> String| {
+        // Numbers and --passwd-file names cost nothing to look up
+        let NameLookup::Resolve { timeout } = *lookup else {
+            return;
+        };
+        let names: Vec<(u32, String)> = ids.into_par_iter()
+            .filter(|id| !cache.contains_key(id))
+            .filter_map(|id| resolve_name(id, timeout, resolve).map(|name| (id, name)))
+            .collect();
+        for (id, name) in names {
+            cache.insert(id, name.into());
+        }
+    };
+    warm(entries.iter().map(|e| e.metadata.uid).collect(), users, &USER_CACHE, get_user_name);
+    warm(entries.iter().map(|e| e.metadata.gid).collect(), groups, &GROUP_CACHE, get_group_name);
+}
+
+/// Forget that NSS stopped answering, so a timeout in one --serve request
+/// doesn't leave every later request with numeric owners
+pub(crate) fn reset_name_lookups() {
+    NSS_UNRESPONSIVE.store(false, Ordering::Relaxed);
+}
+
+/// Read the names from a passwd(5) or group(5) file; both keep the name in
+/// the first field and the numeric id in the third
+fn read_id_file(path: &Path) -> io::Result<HashMap<u32, Arc<str>>> {
+    let mut names = HashMap::new();
+    for line in fs::read_to_string(path)?.lines().filter(|line| !line.starts_with('#')) {
+        let fields: Vec<&str> = line.split(':').collect();
+        if let Some(id) = fields.get(2).and_then(|id| id.parse().ok()) {
+            // Like getpwuid, the first entry for an id wins
+            names.entry(id).or_insert_with(|| fields[0].into());
+        }
+    }
+    Ok(names)
+}
+
+/// The lookup for the ids listed in `file`, read afresh for every run
+fn id_file_lookup(file: &Path, errors: ErrorFormat) -> NameLookup {
+    match read_id_file(file) {
+        Ok(names) => NameLookup::Table(Arc::new(names)),
+        Err(source) => error::fatal(&LsError::CannotAccess { path: file.to_path_buf(), source }, errors),
+    }
+}
+
+/// Run an NSS lookup on a helper thread. None if it doesn't answer within
+/// `timeout`; the id is then shown as a number, but not cached as the name
+fn resolve_name(id: u32, timeout: Duration, resolve: fn(u32) -> String) -> Option<String> {
+    if NSS_UNRESPONSIVE.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(resolve(id));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(name) => Some(name),
+        Err(_) => {
+            NSS_UNRESPONSIVE.store(true, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "ls")]
+#[command(about = "List directory contents")]
+// Defaults from LS_RS_OPTIONS come first, so a later repeat of a flag must win.
+// Flags that pick between the same settings override each other the same way;
+// resolve.rs turns whichever one is left into the setting
+#[command(args_override_self = true)]
+pub struct Args {
+    #[arg(short = 'a', long, overrides_with = "almost_all", help = "Include directory entries whose names begin with a dot")]
+    all: bool,
+
+    #[arg(short = 'A', long, overrides_with = "all", help = "List all entries except . and ..")]
+    almost_all: bool,
+
+    #[arg(short = 'B', long = "ignore-backups", help = "Do not list entries ending with ~")]
+    ignore_backups: bool,
+
+    #[arg(long = "ignore-glob", value_name = "PATTERN", help = "Do not list entries matching the shell PATTERN, even with -a (may be repeated)")]
+    ignore_glob: Vec<String>,
+
+    #[arg(long = "regex", value_name = "PATTERN", help = "List only entries whose name matches the regular expression PATTERN")]
+    regex: Option<String>,
+
+    #[arg(long = "find", value_name = "TEXT", help = "List only entries whose name contains TEXT, ignoring case")]
+    find: Option<String>,
+
+    #[arg(long = "owner", value_name = "USER", help = "List only entries owned by USER, a name or a numeric id")]
+    owner: Option<String>,
+
+    #[arg(long = "group", value_name = "GROUP", help = "List only entries whose group is GROUP, a name or a numeric id")]
+    group: Option<String>,
+
+    #[arg(long = "perm", value_name = "MODE", allow_hyphen_values = true, help = "List only entries whose permission bits are exactly the octal MODE, as in find: -MODE for all of its bits set, /MODE for any of them")]
+    perm: Option<String>,
+
+    #[arg(short = 'l', overrides_with_all = ["format", "one", "multi_column_down", "multi_column_across", "stream_format", "long_grid", "compact", "stat"], help = "List in long format")]
+    long: bool,
+
+    #[arg(short = '1', overrides_with_all = ["format", "long", "multi_column_down", "multi_column_across", "stream_format", "long_grid", "compact", "stat"], help = "Force output to be one entry per line")]
+    one: bool,
+
+    #[arg(short = 't', overrides_with = "sort_size", help = "Sort by modification time")]
+    sort_time: bool,
+
+    #[arg(short = 'S', overrides_with = "sort_time", help = "Sort by file size")]
+    sort_size: bool,
+
+    #[arg(short = 'r', help = "Reverse sort order")]
+    reverse: bool,
+
+    #[arg(short = 'f', help = "Do not sort, list entries in directory order")]
+    no_sort: bool,
+
+    #[arg(short = 'q', long = "hide-control-chars", overrides_with = "show_control_chars", help = "Print ? in place of control characters in names (default on a terminal)")]
+    hide_control_chars: bool,
+
+    #[arg(long = "show-control-chars", overrides_with = "hide_control_chars", help = "Print control characters in names as they are (default when not on a terminal)")]
+    show_control_chars: bool,
+
+    #[arg(long = "normalize", value_name = "FORM", help = "Unicode-normalize names for display and sorting: nfc, nfd or none (default)")]
+    normalize: Option<String>,
+
+    #[arg(long = "sort-case", value_name = "MODE", help = "Name comparison: sensitive (byte order), insensitive (default) or natural")]
+    sort_case: Option<String>,
+
+    #[arg(long = "sort-args", help = "List directory operands in the active sort order instead of as given")]
+    sort_args: bool,
+
+    #[arg(long = "head", value_name = "N", conflicts_with = "tail", help = "Show only the first N entries of each listing, after sorting")]
+    head: Option<usize>,
+
+    #[arg(long = "tail", value_name = "N", help = "Show only the last N entries of each listing, after sorting")]
+    tail: Option<usize>,
+
+    #[arg(short = 'F', overrides_with = "slash", help = "Append indicator (/, *, =, @, |) to entries")]
+    classify: bool,
+
+    #[arg(short = 'p', overrides_with = "classify", help = "Append / to directories")]
+    slash: bool,
+
+    #[arg(long = "human-readable", help = "Human readable sizes")]
+    human_readable: bool,
+
+    #[arg(short = 'G', overrides_with = "color_when", help = "Enable colorized output")]
+    color_flag: bool,
+
+    #[arg(long = "color", overrides_with = "color_flag", value_name = "WHEN", help = "Color mode: auto, always, never")]
+    color_when: Option<String>,
+
+    #[arg(long = "size", value_name = "MODE", default_value = "apparent", help = "Size column shows apparent length or allocated disk usage: apparent, allocated")]
+    size_mode: String,
+
+    #[arg(long = "block-size", value_name = "SIZE", help = "Scale sizes by SIZE (e.g. K, 1M); a leading ' groups digits")]
+    block_size: Option<String>,
+
+    #[arg(long = "size-grouping", help = "Group digits of byte sizes with the locale's thousands separator")]
+    size_grouping: bool,
+
+    #[arg(long = "show-hardlinks", help = "Flag entries that share an inode with another entry in the listing")]
+    show_hardlinks: bool,
+
+    #[arg(long = "truncate-owner", value_name = "N", help = "Cut owner and group names longer than N characters, ending them with …")]
+    truncate_owner: Option<usize>,
+
+    #[arg(long = "color-scale", value_name = "SCALE", help = "Color names by something other than file type: owner (a hue per uid) or risk (setuid, world-writable, root-owned in home directories)")]
+    color_scale: Option<String>,
+
+    #[arg(long = "heat", value_name = "KEY", help = "In long format, tint each line from gray to a hot color by relative age or size: time, size")]
+    heat: Option<String>,
+
+    #[arg(long = "size-bars", help = "In long format, draw a bar after each size showing it relative to the largest entry")]
+    size_bars: bool,
+
+    #[arg(long = "collapse-uniform-columns", help = "In long format, leave out the owner or group column when every entry shares it, naming it once above the entries")]
+    collapse_uniform_columns: bool,
+
+    #[arg(long = "octal-permissions", value_name = "HOW", num_args = 0..=1, require_equals = true, default_missing_value = "both", help = "In long format, show the numeric mode (e.g. 0644) before the rwx string (both) or in its place (only)")]
+    octal_permissions: Option<String>,
+
+    #[arg(long = "access", help = "In long format, show what you may do with each entry as rwx")]
+    access: bool,
+
+    #[arg(long = "header-stats", help = "Follow each directory's header with how many entries it lists and their total size")]
+    header_stats: bool,
+
+    #[arg(long = "dir-counts", help = "In long format, show how many entries each directory contains")]
+    dir_counts: bool,
+
+    #[arg(long = "dirs-show", value_name = "WHAT", help = "What the size column shows for directories: size, blocks (the space allocated to them) or entries (how many they contain)")]
+    dirs_show: Option<String>,
+
+    #[arg(long = "du", help = "In long format, show each directory's size as the total of everything beneath it")]
+    du: bool,
+
+    #[arg(long = "count-links", requires = "du", help = "With --du, count a hard-linked file once per name instead of once per run")]
+    count_links: bool,
+
+    #[arg(long = "uniform-widths", help = "In long format, give every listed directory the same column widths (reads the tree twice with -R)")]
+    uniform_widths: bool,
+
+    #[arg(long = "max-name-width", value_name = "N", help = "In grid output, shorten names longer than N characters with … in the middle")]
+    max_name_width: Option<usize>,
+
+    #[arg(long = "grid-gap", value_name = "N", default_value_t = 2, help = "In grid output, leave N spaces between columns")]
+    grid_gap: usize,
+
+    #[arg(long = "grid-separator", value_name = "STR", conflicts_with = "grid_gap", help = "In grid output, write STR between columns instead of spaces")]
+    grid_separator: Option<String>,
+
+    #[arg(long = "trim-grid", help = "In -C and -x output, don't pad the last cell of each row")]
+    trim_grid: bool,
+
+    #[arg(short = 'O', long = "attrs", help = "Show file flags in long format (BSD st_flags, Linux inode attributes)")]
+    file_flags: bool,
+
+    #[arg(long = "tags", help = "Show Finder color tags as a colored dot (macOS)")]
+    tags: bool,
+
+    #[arg(long = "quarantine", help = "Flag files carrying the Gatekeeper quarantine attribute (macOS)")]
+    quarantine: bool,
+
+    #[arg(long = "no-progress", help = "Never show the scanned-entries indicator on stderr")]
+    no_progress: bool,
+
+    #[arg(long = "finish-on-interrupt", help = "On Ctrl-C, finish the directory being listed before stopping; a second Ctrl-C stops at once")]
+    finish_on_interrupt: bool,
+
+    #[arg(long = "detect-case-collisions", help = "Mark names that differ from another in the same directory only in case, and warn about each such directory on stderr")]
+    detect_case_collisions: bool,
+
+    #[arg(long = "mark-foreign-owners", help = "Mark entries owned by someone other than their directory's owner with [owner:NAME], such as files left in a home directory by sudo")]
+    mark_foreign_owners: bool,
+
+    #[arg(long = "stats", help = "Print to stderr how many entries were scanned, the time spent reading, stating, sorting and formatting, and --cached hit rates")]
+    stats: bool,
+
+    #[arg(long = "no-headers", help = "Do not print a path: header before each directory operand")]
+    no_headers: bool,
+
+    #[arg(long = "fs-info", value_name = "WHAT", num_args = 0..=1, require_equals = true, default_missing_value = "types", help = "Name the filesystem type in each directory operand's header (types), and also mark entries on another device than their directory with [mount] (mounts)")]
+    fs_info: Option<String>,
+
+    #[arg(long = "check-links", value_name = "WHAT", num_args = 0..=1, require_equals = true, default_missing_value = "dangling", help = "Mark symlinks whose target is missing with [dangling] and those that loop with [loop] (dangling), and also how many links each resolves through (chain)")]
+    check_links: Option<String>,
+
+    #[cfg(feature = "archive")]
+    #[arg(long = "archive", help = "List the contents of .tar, .tar.gz and .zip operands as directories")]
+    archive: bool,
+
+    #[arg(long = "cached", help = "Serve unchanged directories from an on-disk listing cache")]
+    cached: bool,
+
+    #[arg(long = "refresh", help = "With --cached, ignore cached listings and rewrite them")]
+    refresh: bool,
+
+    #[arg(long = "cache-ttl", value_name = "SECONDS", default_value_t = 3600, help = "With --cached, reread listings older than this")]
+    cache_ttl: u64,
+
+    #[arg(long = "diff", help = "Compare two directories: - only in the first, + only in the second, ~ changed")]
+    diff: bool,
+
+    #[arg(long = "side-by-side", conflicts_with = "diff", help = "List two or more directories in adjacent columns, one row per name")]
+    side_by_side: bool,
+
+    #[arg(long = "snapshot", value_name = "FILE", help = "Record the listing of the directory operand in FILE")]
+    snapshot: Option<PathBuf>,
+
+    #[arg(long = "since-snapshot", value_name = "FILE", conflicts_with = "snapshot", help = "Report entries added (+), removed (-) or changed (~) since the snapshot in FILE")]
+    since_snapshot: Option<PathBuf>,
+
+    #[arg(long = "trash", help = "List the XDG trash with each file's original path and deletion date")]
+    trash: bool,
+
+    #[arg(long = "print-theme", help = "Show every file class with the style LS_COLORS and the built-in theme give it, then exit")]
+    print_theme: bool,
+
+    #[arg(long = "threads", value_name = "N", help = "Use at most N worker threads (default: one per CPU, up to 16)")]
+    threads: Option<usize>,
+
+    #[arg(long = "low-memory", value_name = "ENTRIES", num_args = 0..=1, require_equals = true, default_missing_value = "65536", conflicts_with_all = ["unordered", "recurse_order", "header_stats"], help = "Sort directories of more than ENTRIES (default 65536) entries on disk, ENTRIES at a time; -R lists one directory at a time")]
+    low_memory: Option<usize>,
+
+    #[arg(long = "unordered", help = "With -R, print each directory as soon as it is read instead of in sorted order")]
+    unordered: bool,
+
+    #[arg(long = "recurse-order", value_name = "ORDER", conflicts_with = "unordered", help = "With -R, list parents before children (pre, default), children first (post) or level by level (breadth)")]
+    recurse_order: Option<String>,
+
+    #[arg(short = 'i', long, help = "Print the inode number of each file")]
+    inode: bool,
+
+    #[arg(short = 's', long, help = "Print the allocated size of each file in blocks, and the directory total")]
+    blocks: bool,
+
+    #[arg(short = 'R', long, help = "Recursively list subdirectories")]
+    recursive: bool,
+
+    #[arg(long = "prune", value_name = "GLOB", requires = "recursive", help = "With -R, list directories matching the shell pattern GLOB but don't descend into them (may be repeated)")]
+    prune: Vec<String>,
+
+    #[arg(short = 'L', overrides_with_all = ["no_follow_symlinks", "follow_cli_symlinks"], help = "Follow all symlinks to final target")]
+    follow_symlinks: bool,
+
+    #[arg(short = 'P', overrides_with_all = ["follow_symlinks", "follow_cli_symlinks"], help = "Never follow symlinks")]
+    no_follow_symlinks: bool,
+
+    #[arg(short = 'H', overrides_with_all = ["follow_symlinks", "no_follow_symlinks"], help = "Follow symlinks on command line only")]
+    follow_cli_symlinks: bool,
+
+    #[arg(long = "dereference-both", conflicts_with = "follow_symlinks", help = "With -l, follow each symlink line with the mode, size and time of its target")]
+    dereference_both: bool,
+
+    #[arg(short = 'c', overrides_with_all = ["atime", "birthtime"], help = "Use status change time for sorting")]
+    ctime: bool,
+
+    #[arg(short = 'u', overrides_with_all = ["ctime", "birthtime"], help = "Use access time for sorting")]
+    atime: bool,
+
+    #[arg(short = 'U', overrides_with_all = ["ctime", "atime"], help = "Use creation time for sorting")]
+    birthtime: bool,
+
+    #[arg(long = "time-style", value_name = "STYLE", help = "Date format in long listings: default (English month names), locale (month names from LC_TIME), iso (sortable YYYY-MM-DD HH:MM:SS) or auto (iso when stdout is not a terminal, else default)")]
+    time_style: Option<String>,
+
+    #[arg(long = "time-zone", value_name = "TZ", help = "Show times in the zone TZ instead of the local one: a name such as UTC or Europe/Paris, an offset such as +05:30, or local")]
+    time_zone: Option<String>,
+
+    #[arg(short = 'C', overrides_with_all = ["format", "long", "one", "multi_column_across", "stream_format", "long_grid", "compact", "stat"], help = "Force multi-column output (down columns)")]
+    multi_column_down: bool,
+
+    #[arg(short = 'x', overrides_with_all = ["format", "long", "one", "multi_column_down", "stream_format", "long_grid", "compact", "stat"], help = "Force multi-column output (across columns)")]
+    multi_column_across: bool,
+
+    #[arg(short = 'm', overrides_with_all = ["format", "long", "one", "multi_column_down", "multi_column_across", "long_grid", "compact", "stat"], help = "Stream format (comma-separated)")]
+    stream_format: bool,
+
+    #[arg(short = 'w', long = "width", value_name = "COLS", help = "Lay out columns for a COLS-wide screen instead of the terminal's width")]
+    width: Option<usize>,
+
+    #[arg(long = "porcelain", value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "1", overrides_with_all = ["yaml", "json", "format"], help = "Print one tab-separated record per entry in a versioned format that scripts can rely on: 1 (default) or 2 (every stat field)")]
+    porcelain: Option<String>,
+
+    #[arg(long = "yaml", overrides_with_all = ["porcelain", "json", "format"], help = "Print the entries as a YAML sequence of mappings")]
+    yaml: bool,
+
+    #[arg(long = "json", overrides_with_all = ["porcelain", "yaml", "format"], help = "Print each entry as a JSON object on a line of its own")]
+    json: bool,
+
+    #[arg(long = "format", value_name = "WORD", overrides_with_all = ["long", "one", "multi_column_down", "multi_column_across", "stream_format", "long_grid", "compact", "stat", "porcelain", "yaml", "json"], help = "Output format: across or horizontal (-x), commas (-m), long or verbose (-l), single-column (-1), vertical (-C), or cbor to write each entry as a CBOR map, one data item after another (RFC 8742 sequence)")]
+    format: Option<String>,
+
+    #[arg(long = "long-grid", overrides_with_all = ["format", "long", "one", "multi_column_down", "multi_column_across", "stream_format", "compact", "stat"], help = "Show size, time and name for each entry, in as many columns as fit")]
+    long_grid: bool,
+
+    #[arg(long = "compact", overrides_with_all = ["format", "long", "one", "multi_column_down", "multi_column_across", "stream_format", "long_grid", "stat"], help = "Show only a human-readable size, the age of the modification time (e.g. 5m, 3d) and the name, one entry per line with single spaces")]
+    compact: bool,
+
+    #[arg(long = "stat", overrides_with_all = ["format", "long", "one", "multi_column_down", "multi_column_across", "stream_format", "long_grid", "compact"], help = "Show each entry as stat(1) does: device, inode, links, times to the nanosecond, birth time and mount point")]
+    stat: bool,
+
+    #[arg(long = "truncate", help = "In long format, clip lines wider than the terminal with an ellipsis instead of letting them wrap")]
+    truncate: bool,
+
+    #[arg(long = "no-lookup", help = "Show numeric user and group ids instead of resolving names")]
+    no_lookup: bool,
+
+    #[arg(long = "lookup-timeout", value_name = "MS", default_value_t = 1000, help = "Give up on owner/group name resolution after MS milliseconds")]
+    lookup_timeout: u64,
+
+    #[arg(long = "passwd-file", value_name = "FILE", help = "Resolve owner names from FILE (passwd format) instead of the system databases")]
+    passwd_file: Option<PathBuf>,
+
+    #[arg(long = "group-file", value_name = "FILE", help = "Resolve group names from FILE (group format) instead of the system databases")]
+    group_file: Option<PathBuf>,
+
+    #[arg(long = "errors", value_name = "FORMAT", default_value = "text", help = "Error report format on stderr: text, json")]
+    errors: String,
+
+    #[arg(long = "files-from", value_name = "FILE", help = "Read NUL- or newline-separated operands from FILE (- for stdin)")]
+    files_from: Option<PathBuf>,
+
+    #[arg(long = "column-cmd", value_name = "NAME=COMMAND", help = "With -l, add a column showing the first line COMMAND prints for each entry, whose path is $1 (may be repeated)")]
+    column_cmd: Vec<String>,
+
+    #[arg(long = "serve", value_name = "SOCKET", help = "Stay running and answer listing requests on the Unix socket SOCKET, keeping caches warm")]
+    serve: Option<PathBuf>,
+
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    name: String,
+    path: PathBuf,
+    metadata: FileStat,
+    symlink_target: Option<PathBuf>,
+    // What the symlink resolves to, for --dereference-both; None when it doesn't
+    target_metadata: Option<FileStat>,
+    link_group: Option<usize>,
+    file_flags: Option<u32>,
+    xattrs: XattrSummary,
+    // Where a symlink leads, for --check-links
+    link_status: Option<LinkStatus>,
+    // Mode-specific long-format columns shown before the name (e.g. --trash)
+    extra_columns: Vec<String>,
+    // On another device than the directory listing it, for --fs-info=mounts
+    other_device: bool,
+    // Another name in the same directory differs only in case, for --detect-case-collisions
+    case_collision: bool,
+    // Owned by someone other than the directory listing it, for --mark-foreign-owners
+    foreign_owner: bool,
+    // Entries directly inside a directory, for --dir-counts; None when unread
+    child_count: Option<usize>,
+    display: DisplayCache,
+    // The name as stored on disk when it isn't valid UTF-8; `name` then holds
+    // a lossy copy for sorting and widths, and this is what gets written
+    raw_name: Option<OsString>,
+}
+
+impl Entry {
+    /// The name as listed
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The entry's own metadata, or its target's when links are followed
+    pub fn metadata(&self) -> &FileStat {
+        &self.metadata
+    }
+
+    /// Where the entry points, when it is a symlink
+    pub fn symlink_target(&self) -> Option<&Path> {
+        self.symlink_target.as_deref()
+    }
+
+    /// Bytes of the name to write and to compare under --sort-case=sensitive
+    fn name_bytes(&self) -> &[u8] {
+        use std::os::unix::ffi::OsStrExt;
+
+        match &self.raw_name {
+            Some(raw) => raw.as_bytes(),
+            None => self.name.as_bytes(),
+        }
+    }
+}
+
+/// Strings the sort and the printers need from an entry, worked out once on
+/// the rayon worker that stats it instead of again by every comparison and pass
+#[derive(Clone, Default)]
+struct DisplayCache {
+    /// Lowercased name for --sort-case=insensitive; empty under other modes
+    folded_name: String,
+    /// Size column text; empty unless a long format will print it
+    size: String,
+    /// -F or -p indicator
+    indicator: &'static str,
+}
+
+impl DisplayCache {
+    fn new(name: &str, metadata: &FileStat, child_count: Option<usize>, config: &Config) -> DisplayCache {
+        DisplayCache {
+            folded_name: if matches!(config.sort_case, SortCase::Insensitive) { name.to_lowercase() } else { String::new() },
+            size: if config.long || config.format == OutputFormat::LongGrid {
+                match child_count {
+                    Some(count) if config.dirs_show == DirsShow::Entries => count.to_string(),
+                    None if config.dirs_show == DirsShow::Entries && metadata.is_dir() => "?".to_string(),
+                    _ => format_size_field(metadata, config),
+                }
+            } else {
+                String::new()
+            },
+            indicator: if config.classify || config.slash { get_indicator(metadata, config.classify) } else { "" },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    all: bool,
+    almost_all: bool,
+    // --ignore-glob patterns, plus *~ for -B
+    ignore: Vec<CString>,
+    // --prune patterns: directories -R lists but doesn't enter
+    prune: Vec<CString>,
+    // --column-cmd columns, then any registered through run_with_columns
+    columns: Vec<Arc<dyn columns::Column>>,
+    // Filters and comparator registered through run_with_pipeline
+    pipeline: pipeline::Pipeline,
+    // --regex, and --find in lowercase; entries must match both
+    regex: Option<regex::Regex>,
+    find: Option<String>,
+    // --owner and --group, resolved to ids
+    owner: Option<u32>,
+    group: Option<u32>,
+    perm: Option<PermFilter>,
+    long: bool,
+    // Output for programs: one record per entry, without headers or totals
+    records: Option<RecordFormat>,
+    sort: SortBy,
+    // Screen width the grid formats lay out for
+    width: usize,
+    sort_case: SortCase,
+    sort_args: bool,
+    limit: Option<Limit>,
+    normalize: Option<Normalization>,
+    hide_control_chars: bool,
+    reverse: bool,
+    classify: bool,
+    slash: bool,
+    human_readable: bool,
+    color: ColorMode,
+    inode: bool,
+    blocks: bool,
+    recursive: bool,
+    follow_symlinks: FollowSymlinks,
+    dereference_both: bool,
+    time_field: TimeField,
+    time_style: TimeStyle,
+    time_zone: Zone,
+    format: OutputFormat,
+    user_lookup: NameLookup,
+    group_lookup: NameLookup,
+    block_size: u64,
+    size_mode: SizeMode,
+    show_hardlinks: bool,
+    errors: ErrorFormat,
+    truncate_owner: Option<usize>,
+    max_name_width: Option<usize>,
+    // Between grid columns: --grid-separator, or --grid-gap spaces
+    grid_gutter: String,
+    trim_grid: bool,
+    size_unit: Option<SizeUnit>,
+    size_grouping: bool,
+    file_flags: bool,
+    tags: bool,
+    quarantine: bool,
+    unordered: bool,
+    recurse_order: RecurseOrder,
+    no_headers: bool,
+    fs_info: Option<FsInfo>,
+    check_links: Option<CheckLinks>,
+    #[cfg(feature = "archive")]
+    archive: bool,
+    trash: bool,
+    print_theme: bool,
+    diff: bool,
+    side_by_side: bool,
+    snapshot: Option<PathBuf>,
+    since_snapshot: Option<PathBuf>,
+    cache: Option<cache::CacheOptions>,
+    progress: bool,
+    stats: bool,
+    finish_on_interrupt: bool,
+    detect_case_collisions: bool,
+    mark_foreign_owners: bool,
+    truncate: bool,
+    threads: usize,
+    low_memory: Option<usize>,
+    theme: theme::Theme,
+    // What the terminal can show; colors the theme computes degrade to it
+    term: termcaps::TermCaps,
+    heat: Option<Heat>,
+    color_scale: Option<ColorScale>,
+    size_bars: bool,
+    collapse_uniform_columns: bool,
+    header_stats: bool,
+    dir_counts: bool,
+    dirs_show: DirsShow,
+    // --du; one per run so every directory shares the set of hard links already counted
+    du: Option<Arc<du::DiskUsage>>,
+    octal_permissions: Option<OctalPermissions>,
+    access: bool,
+    uniform_widths: bool,
+    // Columns are at least this wide; raised to the widest in the tree with --uniform-widths
+    long_widths: LongWidths,
+}
+
+/// Widths of the right-aligned numeric columns of the long format
+#[derive(Debug, Clone, Copy, Default)]
+struct LongWidths {
+    inode: usize,
+    blocks: usize,
+    link: usize,
+    flags: usize,
+    count: usize,
+    size: usize,
+}
+
+impl LongWidths {
+    /// The widths `entries` need on their own
+    fn of(entries: &[Entry], config: &Config) -> LongWidths {
+        let widest = |width: &dyn Fn(&Entry) -> usize| entries.iter().map(width).max().unwrap_or(0);
+        LongWidths {
+            inode: if config.inode { widest(&|e| e.metadata.ino.to_string().len()) } else { 0 },
+            blocks: if config.blocks { widest(&|e| display_blocks(&e.metadata, config.block_size).to_string().len()) } else { 0 },
+            link: widest(&|e| e.metadata.nlink.to_string().len()),
+            flags: if config.file_flags { widest(&|e| attrs::format_flags(e.file_flags).len()) } else { 0 },
+            count: if config.dir_counts { widest(&|e| dir_count(e).len()) } else { 0 },
+            size: widest(&|e| e.display.size.len()),
+        }
+    }
+
+    fn max(self, other: LongWidths) -> LongWidths {
+        LongWidths {
+            inode: self.inode.max(other.inode),
+            blocks: self.blocks.max(other.blocks),
+            link: self.link.max(other.link),
+            flags: self.flags.max(other.flags),
+            count: self.count.max(other.count),
+            size: self.size.max(other.size),
+        }
+    }
+}
+
+/// Machine-readable output formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RecordFormat {
+    /// --porcelain tab-separated lines, in the record version asked for
+    Porcelain(u32),
+    /// --yaml, from the serde model in structured.rs
+    Yaml,
+    /// --json, the same model as one object per line
+    Json,
+    /// --format=cbor, the same model as a stream of binary items
+    Cbor,
+}
+
+/// --head or --tail: the part of each sorted listing that gets printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Limit {
+    Head(usize),
+    Tail(usize),
+}
+
+impl Limit {
+    /// Positions kept out of `count` sorted entries
+    fn range(self, count: usize) -> std::ops::Range<usize> {
+        match self {
+            Limit::Head(n) => 0..n.min(count),
+            Limit::Tail(n) => count.saturating_sub(n)..count,
+        }
+    }
+}
+
+/// The entries of a sorted listing that --head or --tail keep
+fn shown(entries: &mut [Entry], limit: Option<Limit>) -> &mut [Entry] {
+    let count = entries.len();
+    match limit {
+        Some(limit) => &mut entries[limit.range(count)],
+        None => entries,
+    }
+}
+
+/// The order -R writes directory sections in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RecurseOrder {
+    /// Each directory, then the subtree of each of its subdirectories
+    Pre,
+    /// The subtrees of a directory's subdirectories, then the directory
+    Post,
+    /// Every directory of one depth before any of the next
+    Breadth,
+}
+
+/// What --fs-info adds to a listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FsInfo {
+    /// The filesystem type after each directory operand's header
+    Types,
+    /// Types, and a marker on entries that are mount points
+    Mounts,
+}
+
+/// What --check-links marks on symlinks
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CheckLinks {
+    /// Links that don't resolve
+    Dangling,
+    /// Those, and how many links the others resolve through
+    Chain,
+}
+
+/// How --octal-permissions shows the numeric mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OctalPermissions {
+    Both,
+    Only,
+}
+
+/// Unicode normalization form applied to names with --normalize
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Normalization {
+    Nfc,
+    Nfd,
+}
+
+/// What --heat grades long-format lines by
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Heat {
+    Time,
+    Size,
+}
+
+/// What --color-scale colors names by instead of the theme
+#[derive(Debug, Clone, PartialEq)]
+enum ColorScale {
+    Owner,
+    /// Root-owned files strictly inside these directories are flagged
+    Risk { homes: Vec<PathBuf> },
+}
+
+/// --perm, compared against the permission bits (setuid, setgid and sticky included)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PermFilter {
+    /// MODE: exactly these bits
+    Exact(u32),
+    /// -MODE: at least these bits
+    All(u32),
+    /// /MODE: any of these bits
+    Any(u32),
+}
+
+impl PermFilter {
+    fn parse(spec: &str) -> Option<PermFilter> {
+        let (make, digits): (fn(u32) -> PermFilter, &str) = match spec.as_bytes().first()? {
+            b'-' => (PermFilter::All, &spec[1..]),
+            b'/' => (PermFilter::Any, &spec[1..]),
+            _ => (PermFilter::Exact, spec),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        u32::from_str_radix(digits, 8).ok().filter(|&bits| bits <= 0o7777).map(make)
+    }
+
+    fn matches(self, mode: u32) -> bool {
+        let perms = mode & 0o7777;
+        match self {
+            PermFilter::Exact(bits) => perms == bits,
+            PermFilter::All(bits) => perms & bits == bits,
+            // Like find, /000 matches everything
+            PermFilter::Any(bits) => bits == 0 || perms & bits != 0,
+        }
+    }
+}
+
+/// Unit the size column is scaled to with --block-size
+#[derive(Debug, Clone)]
+struct SizeUnit {
+    bytes: u64,
+    /// Printed after each size when the unit was given without a count, e.g. `K`
+    suffix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeMode {
+    Apparent,
+    Allocated,
+}
+
+/// What the size column shows for a directory, with --dirs-show
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DirsShow {
+    /// Whatever it shows for files
+    Size,
+    /// The space allocated to the directory itself
+    Blocks,
+    /// How many entries it contains
+    Entries,
+}
+
+#[derive(Debug, Clone)]
+enum NameLookup {
+    Numeric,
+    /// Names from a --passwd-file or --group-file; unlisted ids stay numeric
+    Table(Arc<HashMap<u32, Arc<str>>>),
+    Resolve { timeout: Duration },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    SingleColumn,
+    MultiColumnDown,
+    MultiColumnAcross,
+    Stream,
+    LongGrid,
+    /// --compact: size, age and name, for narrow panes
+    Compact,
+    /// --stat: a stat(1) block per entry
+    Stat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FollowSymlinks {
+    Never,
+    CommandLine,
+    Always,
+}
+
+/// The zone times are shown in: the local one, or the one --time-zone names
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Local,
+    Fixed(chrono::FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl Zone {
+    /// A --time-zone argument: `local`, UTC, an IANA name or an offset east
+    /// of UTC as +HH, +HHMM or +HH:MM. None when it is none of those
+    fn parse(spec: &str) -> Option<Zone> {
+        match spec {
+            "local" => return Some(Zone::Local),
+            "UTC" | "utc" | "Z" => return Some(Zone::Fixed(chrono::FixedOffset::east_opt(0)?)),
+            _ => {}
+        }
+        let (sign, rest) = match spec.as_bytes().first() {
+            Some(b'+') => (1, &spec[1..]),
+            Some(b'-') => (-1, &spec[1..]),
+            _ => return spec.parse().ok().map(Zone::Named),
+        };
+        // Checked before measuring or slicing, which count bytes
+        if !rest.bytes().all(|b| b.is_ascii_digit() || b == b':') {
+            return None;
+        }
+        let digits = rest.replace(':', "");
+        let (hours, minutes): (i32, i32) = match digits.len() {
+            2 => (digits.parse().ok()?, 0),
+            4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+            _ => return None,
+        };
+        if minutes > 59 {
+            return None;
+        }
+        chrono::FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Zone::Fixed)
+    }
+
+    /// `secs` and `nsec` past the epoch as a time in this zone, or None for
+    /// times chrono can't represent
+    fn datetime(self, secs: i64, nsec: u32) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        use chrono::TimeZone;
+
+        match self {
+            Zone::Local => chrono::Local.timestamp_opt(secs, nsec).earliest().map(|datetime| datetime.fixed_offset()),
+            Zone::Fixed(offset) => offset.timestamp_opt(secs, nsec).earliest(),
+            Zone::Named(zone) => zone.timestamp_opt(secs, nsec).earliest().map(|datetime| datetime.fixed_offset()),
+        }
+    }
+}
+
+/// How dates are written in long listings
+#[derive(Debug, Clone, Copy)]
+enum TimeStyle {
+    Default,
+    /// --time-style=locale; months are padded to the widest name so columns line up
+    Locale { locale: chrono::Locale, month_width: usize },
+    /// Fixed-width local time, so piped listings sort as plain text
+    Iso,
+}
+
+impl TimeStyle {
+    /// The locale LC_ALL, LC_TIME or LANG names, falling back to POSIX like libc does
+    fn from_env() -> TimeStyle {
+        let name = ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        // de_DE.UTF-8@euro -> de_DE
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let locale = chrono::Locale::try_from(name).unwrap_or(chrono::Locale::POSIX);
+        let month_width = (1..=12)
+            .filter_map(|month| chrono::NaiveDate::from_ymd_opt(2000, month, 1))
+            .map(|date| date.format_localized("%b", locale).to_string().chars().count())
+            .max()
+            .unwrap_or(3);
+        TimeStyle::Locale { locale, month_width }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TimeField {
+    Modify,
+    Change,
+    Access,
+    Birth,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SortBy {
+    Name,
+    Time,
+    Size,
+    Unsorted,
+}
+
+/// How names compare when sorting and breaking ties
+#[derive(Debug, Clone, Copy)]
+enum SortCase {
+    Sensitive,
+    Insensitive,
+    Natural,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Config {
+    /// Resolve parsed flags into the settings the listing pipeline uses
+    pub fn from_args(args: &Args) -> Config {
+        // Determine how errors are reported
+        let errors = match args.errors.as_str() {
+            "text" => ErrorFormat::Text,
+            "json" => ErrorFormat::Json,
+            other => error::fatal(&LsError::InvalidArgument {
+                option: "--errors",
+                value: other.to_string(),
+                expected: "text or json",
+            }, ErrorFormat::Text),
+        };
+
+        let color = resolve::color(args, errors);
+        let sort = resolve::sort(args);
+        let follow_symlinks = resolve::follow_symlinks(args);
+        let time_field = resolve::time_field(args);
+        let format_word = resolve::format_word(args, errors);
+        let format = resolve::output_format(args, format_word);
+
+        let globs = |patterns: Vec<String>, option: &'static str| -> Vec<CString> {
+            patterns.into_iter()
+                .map(|pattern| CString::new(pattern).unwrap_or_else(|e| error::fatal(&LsError::InvalidArgument {
+                    option,
+                    value: String::from_utf8_lossy(&e.into_vec()).into_owned(),
+                    expected: "a pattern without NUL bytes",
+                }, errors)))
+                .collect()
+        };
+        let backups = args.ignore_backups.then(|| "*~".to_string());
+        let ignore = globs(args.ignore_glob.iter().cloned().chain(backups).collect(), "--ignore-glob");
+        let prune = globs(args.prune.clone(), "--prune");
+        let columns = args.column_cmd.iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((name, command)) if !name.is_empty() => {
+                    Arc::new(columns::CommandColumn { name: name.to_string(), command: command.to_string() }) as Arc<dyn columns::Column>
+                }
+                _ => error::fatal(&LsError::InvalidArgument {
+                    option: "--column-cmd",
+                    value: spec.clone(),
+                    expected: "NAME=COMMAND",
+                }, errors),
+            })
+            .collect();
+        let regex = args.regex.as_deref().map(|pattern| {
+            regex::Regex::new(pattern).unwrap_or_else(|_| error::fatal(&LsError::InvalidArgument {
+                option: "--regex",
+                value: pattern.to_string(),
+                expected: "a regular expression",
+            }, errors))
+        });
+
+        let sort_case = match args.sort_case.as_deref() {
+            None | Some("insensitive") => SortCase::Insensitive,
+            Some("sensitive") => SortCase::Sensitive,
+            Some("natural") => SortCase::Natural,
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--sort-case",
+                value: other.to_string(),
+                expected: "sensitive, insensitive or natural",
+            }, errors),
+        };
+
+        let threads = match args.threads {
+            None => num_cpus::get().min(MAX_DEFAULT_THREADS),
+            Some(0) => error::fatal(&LsError::InvalidArgument {
+                option: "--threads",
+                value: "0".to_string(),
+                expected: "a positive number",
+            }, errors),
+            Some(n) => n,
+        };
+        if args.low_memory == Some(0) {
+            error::fatal(&LsError::InvalidArgument {
+                option: "--low-memory",
+                value: "0".to_string(),
+                expected: "a positive number",
+            }, errors);
+        }
+
+        let heat = match args.heat.as_deref() {
+            None => None,
+            Some("time") => Some(Heat::Time),
+            Some("size") => Some(Heat::Size),
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--heat",
+                value: other.to_string(),
+                expected: "time or size",
+            }, errors),
+        };
+
+        let color_scale = match args.color_scale.as_deref() {
+            None => None,
+            Some("owner") => Some(ColorScale::Owner),
+            Some("risk") => {
+                let home = std::env::var_os("HOME").filter(|home| !home.is_empty()).map(PathBuf::from);
+                Some(ColorScale::Risk { homes: home.into_iter().chain(["/home".into(), "/Users".into()]).collect() })
+            }
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--color-scale",
+                value: other.to_string(),
+                expected: "owner or risk",
+            }, errors),
+        };
+
+        let octal_permissions = match args.octal_permissions.as_deref() {
+            None => None,
+            Some("both") => Some(OctalPermissions::Both),
+            Some("only") => Some(OctalPermissions::Only),
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--octal-permissions",
+                value: other.to_string(),
+                expected: "both or only",
+            }, errors),
+        };
+
+        let fs_info = match args.fs_info.as_deref() {
+            None => None,
+            Some("types") => Some(FsInfo::Types),
+            Some("mounts") => Some(FsInfo::Mounts),
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--fs-info",
+                value: other.to_string(),
+                expected: "types or mounts",
+            }, errors),
+        };
+        let check_links = match args.check_links.as_deref() {
+            None => None,
+            Some("dangling") => Some(CheckLinks::Dangling),
+            Some("chain") => Some(CheckLinks::Chain),
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--check-links",
+                value: other.to_string(),
+                expected: "dangling or chain",
+            }, errors),
+        };
+
+        let normalize = match args.normalize.as_deref() {
+            None | Some("none") => None,
+            Some("nfc") => Some(Normalization::Nfc),
+            Some("nfd") => Some(Normalization::Nfd),
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--normalize",
+                value: other.to_string(),
+                expected: "nfc, nfd or none",
+            }, errors),
+        };
+
+        let recurse_order = match args.recurse_order.as_deref() {
+            None | Some("pre") => RecurseOrder::Pre,
+            Some("post") => RecurseOrder::Post,
+            Some("breadth") => RecurseOrder::Breadth,
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--recurse-order",
+                value: other.to_string(),
+                expected: "pre, post or breadth",
+            }, errors),
+        };
+
+        let time_style = match args.time_style.as_deref() {
+            None | Some("default") => TimeStyle::Default,
+            Some("locale") => TimeStyle::from_env(),
+            Some("iso") => TimeStyle::Iso,
+            // Set in LS_RS_OPTIONS this makes every pipe get ISO dates by default
+            Some("auto") if is_tty() => TimeStyle::Default,
+            Some("auto") => TimeStyle::Iso,
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--time-style",
+                value: other.to_string(),
+                expected: "default, locale, iso or auto",
+            }, errors),
+        };
+        let time_zone = match args.time_zone.as_deref() {
+            None => Zone::Local,
+            Some(spec) => Zone::parse(spec).unwrap_or_else(|| error::fatal(&LsError::InvalidArgument {
+                option: "--time-zone",
+                value: spec.to_string(),
+                expected: "a zone name such as UTC or Europe/Paris, an offset such as +05:30, or local",
+            }, errors)),
+        };
+
+        let width = resolve::width(args);
+
+        let name_lookup = if args.no_lookup {
+            NameLookup::Numeric
+        } else {
+            NameLookup::Resolve { timeout: Duration::from_millis(args.lookup_timeout) }
+        };
+        let user_lookup = match &args.passwd_file {
+            Some(file) if !args.no_lookup => id_file_lookup(file, errors),
+            _ => name_lookup.clone(),
+        };
+        let group_lookup = match &args.group_file {
+            Some(file) if !args.no_lookup => id_file_lookup(file, errors),
+            _ => name_lookup,
+        };
+
+        // Determine what the size column measures
+        let size_mode = match args.size_mode.as_str() {
+            "allocated" => SizeMode::Allocated,
+            "apparent" => SizeMode::Apparent,
+            other => error::fatal(&LsError::InvalidArgument {
+                option: "--size",
+                value: other.to_string(),
+                expected: "apparent or allocated",
+            }, errors),
+        };
+
+        let dirs_show = match args.dirs_show.as_deref() {
+            None | Some("size") => DirsShow::Size,
+            Some("blocks") => DirsShow::Blocks,
+            Some("entries") => DirsShow::Entries,
+            Some(other) => error::fatal(&LsError::InvalidArgument {
+                option: "--dirs-show",
+                value: other.to_string(),
+                expected: "size, blocks or entries",
+            }, errors),
+        };
+
+        // An explicit --block-size overrides the environment; a leading ' requests digit grouping
+        let mut size_grouping = false;
+        let size_unit = args.block_size.as_deref().map(|spec| {
+            let unit = match spec.strip_prefix('\'') {
+                Some(rest) => {
+                    size_grouping = true;
+                    rest
+                }
+                None => spec,
+            };
+            match parse_block_size(unit) {
+                Some(bytes) => SizeUnit {
+                    bytes,
+                    suffix: if unit.starts_with(|c: char| c.is_ascii_digit()) { String::new() } else { unit.to_string() },
+                },
+                None => error::fatal(&LsError::InvalidArgument {
+                    option: "--block-size",
+                    value: spec.to_string(),
+                    expected: "a size such as 1024, K, 1M or '1",
+                }, errors),
+            }
+        });
+
+        Config {
+            all: args.all || args.no_sort,
+            almost_all: args.almost_all,
+            ignore,
+            prune,
+            columns,
+            pipeline: pipeline::Pipeline::default(),
+            regex,
+            find: args.find.as_deref().map(str::to_lowercase),
+            owner: args.owner.as_deref().map(|spec| owner_id(spec, &user_lookup, get_user_id).unwrap_or_else(|| error::fatal(&LsError::InvalidArgument {
+                option: "--owner",
+                value: spec.to_string(),
+                expected: "a numeric id or the name of an existing user",
+            }, errors))),
+            group: args.group.as_deref().map(|spec| owner_id(spec, &group_lookup, get_group_id).unwrap_or_else(|| error::fatal(&LsError::InvalidArgument {
+                option: "--group",
+                value: spec.to_string(),
+                expected: "a numeric id or the name of an existing group",
+            }, errors))),
+            perm: args.perm.as_deref().map(|spec| PermFilter::parse(spec).unwrap_or_else(|| error::fatal(&LsError::InvalidArgument {
+                option: "--perm",
+                value: spec.to_string(),
+                expected: "an octal mode such as 755, -0002 or /111",
+            }, errors))),
+            long: args.long || args.trash || format_word == Some(resolve::FormatWord::Long),
+            records: resolve::records(args, format_word, errors),
+            width,
+            sort,
+            sort_case,
+            sort_args: args.sort_args,
+            limit: args.head.map(Limit::Head).or(args.tail.map(Limit::Tail)),
+            normalize,
+            hide_control_chars: args.hide_control_chars || (!args.show_control_chars && is_tty()),
+            reverse: args.reverse,
+            classify: args.classify,
+            slash: args.slash,
+            human_readable: args.human_readable,
+            color,
+            inode: args.inode,
+            blocks: args.blocks,
+            recursive: args.recursive,
+            follow_symlinks,
+            dereference_both: args.dereference_both,
+            time_field,
+            time_style,
+            time_zone,
+            format,
+            user_lookup,
+            group_lookup,
+            block_size: size_unit.as_ref().map_or_else(resolve_block_size, |unit| unit.bytes),
+            size_mode,
+            show_hardlinks: args.show_hardlinks,
+            errors,
+            truncate_owner: args.truncate_owner,
+            max_name_width: args.max_name_width,
+            grid_gutter: args.grid_separator.clone().unwrap_or_else(|| " ".repeat(args.grid_gap)),
+            trim_grid: args.trim_grid,
+            size_unit: size_unit.filter(|unit| unit.bytes > 1 || !unit.suffix.is_empty()),
+            size_grouping: args.size_grouping || size_grouping,
+            file_flags: args.file_flags,
+            tags: args.tags,
+            quarantine: args.quarantine,
+            unordered: args.unordered,
+            recurse_order,
+            no_headers: args.no_headers,
+            fs_info,
+            check_links,
+            #[cfg(feature = "archive")]
+            archive: args.archive,
+            trash: args.trash,
+            print_theme: args.print_theme,
+            diff: args.diff,
+            side_by_side: args.side_by_side,
+            snapshot: args.snapshot.clone(),
+            since_snapshot: args.since_snapshot.clone(),
+            cache: (args.cached || args.refresh).then(|| cache::CacheOptions {
+                ttl: Duration::from_secs(args.cache_ttl),
+                refresh: args.refresh,
+            }),
+            progress: !args.no_progress,
+            stats: args.stats,
+            finish_on_interrupt: args.finish_on_interrupt,
+            detect_case_collisions: args.detect_case_collisions,
+            mark_foreign_owners: args.mark_foreign_owners,
+            truncate: args.truncate,
+            threads,
+            low_memory: args.low_memory,
+            heat,
+            color_scale,
+            size_bars: args.size_bars,
+            collapse_uniform_columns: args.collapse_uniform_columns,
+            header_stats: args.header_stats,
+            dir_counts: args.dir_counts,
+            dirs_show,
+            du: args.du.then(|| Arc::new(du::DiskUsage::new(args.count_links))),
+            octal_permissions,
+            access: args.access,
+            uniform_widths: args.uniform_widths,
+            long_widths: LongWidths::default(),
+            theme: theme::Theme::from_env(),
+            term: termcaps::TermCaps::detect(),
+        }
+    }
+}
+
+/// The command line with the flags from LS_RS_OPTIONS inserted after the
+/// program name, so anything given explicitly overrides them
+pub fn argv_with_env_defaults(argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut argv = argv.into_iter();
+    let mut merged: Vec<OsString> = argv.next().into_iter().collect();
+    if let Ok(options) = std::env::var("LS_RS_OPTIONS") {
+        merged.extend(split_options(&options).into_iter().map(OsString::from));
+    }
+    merged.extend(argv);
+    merged
+}
+
+/// Split an options string into words the way a shell would for simple cases:
+/// whitespace separates, quotes group and a backslash escapes the next character
+fn split_options(options: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = options.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (q, '\\') if q != Some('\'') => word.get_or_insert_with(String::new).extend(chars.next()),
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Size rayon's global pool. Called only once there is a directory to read,
+/// so listing a single file never starts any worker threads
+fn init_thread_pool(threads: usize) {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        // Fails only if the pool already exists, e.g. when embedded in a larger program
+        let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+    });
+}
+
+/// Take LC_NUMERIC from the environment. setlocale isn't thread-safe, so this
+/// runs before any worker thread exists
+fn init_locale() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| unsafe {
+        libc::setlocale(libc::LC_NUMERIC, c"".as_ptr());
+    });
+}
+
+/// List every operand named by `args` and return the exit status
+pub fn run(args: Args) -> i32 {
+    run_with_backends(args, Vec::new())
+}
+
+/// Like `run`, consulting `backends` before the built-in ones for each operand
+pub fn run_with_backends(args: Args, backends: Vec<Box<dyn Backend>>) -> i32 {
+    run_with_columns(args, backends, Vec::new())
+}
+
+/// Like `run_with_backends`, with `columns` shown after any --column-cmd ones in long listings
+pub fn run_with_columns(args: Args, backends: Vec<Box<dyn Backend>>, columns: Vec<Arc<dyn columns::Column>>) -> i32 {
+    run_with_pipeline(args, backends, columns, pipeline::Pipeline::default())
+}
+
+/// Like `run_with_columns`, listing only the entries every filter of
+/// `pipeline` keeps, in the order of its comparator when it has one
+pub fn run_with_pipeline(args: Args, backends: Vec<Box<dyn Backend>>, columns: Vec<Arc<dyn columns::Column>>, pipeline: pipeline::Pipeline) -> i32 {
+    init_locale();
+    if let Some(socket) = &args.serve {
+        let errors = Config::from_args(&args).errors;
+        if let Err(source) = serve::serve(socket, backends, columns, pipeline) {
+            error::report(&LsError::Serve { path: socket.clone(), source }, errors, true);
+        }
+        return error::exit_status();
+    }
+    run_with_output(args, backends, columns, pipeline, None)
+}
+
+/// The listing `args` asks for, written to `out` when given (as --serve does)
+/// and otherwise to stdout
+fn run_with_output(
+    args: Args,
+    backends: Vec<Box<dyn Backend>>,
+    columns: Vec<Arc<dyn columns::Column>>,
+    pipeline: pipeline::Pipeline,
+    out: Option<&mut dyn Write>,
+) -> i32 {
+    let mut config = Config::from_args(&args);
+    config.columns.extend(columns);
+    config.pipeline = pipeline;
+    let errors = config.errors;
+
+    let mut paths = args.paths;
+    if let Some(ref source) = args.files_from {
+        match read_files_from(source) {
+            Ok(extra) => paths.extend(extra),
+            Err(e) => error::fatal(&LsError::CannotReadOperands { path: source.clone(), source: e }, errors),
+        }
+    }
+    if paths.is_empty() && args.files_from.is_none() {
+        paths.push(PathBuf::from("."));
+    }
+
+    // Declared first so the report comes after everything else, the indicator included
+    let _stats = config.stats.then(stats::Report::start);
+    // Only an interactive stderr gets the indicator; it must never leak into captured output
+    let spinner = (out.is_none() && config.progress && unsafe { libc::isatty(2) == 1 })
+        .then(|| progress::Spinner::start(PROGRESS_DELAY));
+    if out.is_none() {
+        interrupt::install(use_color(&config), config.finish_on_interrupt);
+    }
+    let mut stdout: Box<dyn Write + '_> = match out {
+        Some(out) => Box::new(out),
+        None if spinner.is_some() => Box::new(progress::ClearingWriter(interrupt::TrackedStdout)),
+        None => Box::new(interrupt::TrackedStdout),
+    };
+    if config.print_theme {
+        let preview = config.theme.preview(&mut stdout, use_color(&config)).and_then(|()| writeln!(stdout, "\nterminal: {}", config.term));
+        if let Err(e) = preview
+            && e.kind() != io::ErrorKind::BrokenPipe
+        {
+            error::report(&LsError::Write(e), config.errors, true);
+        }
+        drop(spinner);
+        return error::exit_status();
+    }
+    if config.trash {
+        init_thread_pool(config.threads);
+        if let Err(e) = trash::list_trash(&LocalFs, &config, &mut stdout)
+            && !e.is_broken_pipe()
+        {
+            error::report(&e, config.errors, true);
+        }
+        drop(spinner);
+        return error::exit_status();
+    }
+
+    // Like coreutils: missing operands are reported first, then the files named
+    // on the command line are listed together, then each directory in turn
+    let mut file_operands = Vec::new();
+    let mut dir_operands: Vec<(Entry, Box<dyn Filesystem>)> = Vec::new();
+    let backends = Backends::new(backends, &config);
+
+    if config.snapshot.is_some() || config.since_snapshot.is_some() {
+        init_thread_pool(config.threads);
+        let [dir] = paths.as_slice() else {
+            error::fatal(&LsError::InvalidArgument {
+                option: if config.snapshot.is_some() { "--snapshot" } else { "--since-snapshot" },
+                value: format!("{} operands", paths.len()),
+                expected: "one directory",
+            }, errors);
+        };
+        let result = backends.open(dir).and_then(|fs| match (&config.snapshot, &config.since_snapshot) {
+            (Some(file), _) => snapshot::write_snapshot(fs.as_ref(), dir, &config, file),
+            (None, Some(file)) => snapshot::compare_with_snapshot(fs.as_ref(), dir, &config, file, &mut stdout),
+            (None, None) => unreachable!(),
+        });
+        if let Err(e) = result
+            && !e.is_broken_pipe()
+        {
+            error::report(&e, config.errors, true);
+        }
+        drop(spinner);
+        return error::exit_status();
+    }
+
+    if config.diff {
+        init_thread_pool(config.threads);
+        let [a, b] = paths.as_slice() else {
+            error::fatal(&LsError::InvalidArgument {
+                option: "--diff",
+                value: format!("{} operands", paths.len()),
+                expected: "two directories",
+            }, errors);
+        };
+        let result = backends.open(a).and_then(|a_fs| {
+            let b_fs = backends.open(b)?;
+            let (a, b) = (diff::Side { fs: a_fs.as_ref(), path: a }, diff::Side { fs: b_fs.as_ref(), path: b });
+            diff::diff_directories(a, b, &config, &mut stdout)
+        });
+        if let Err(e) = result
+            && !e.is_broken_pipe()
+        {
+            error::report(&e, config.errors, true);
+        }
+        drop(spinner);
+        return error::exit_status();
+    }
+
+    if config.side_by_side {
+        init_thread_pool(config.threads);
+        if paths.len() < 2 {
+            error::fatal(&LsError::InvalidArgument {
+                option: "--side-by-side",
+                value: format!("{} operands", paths.len()),
+                expected: "two or more directories",
+            }, errors);
+        }
+        let result = paths.iter().map(|path| backends.open(path)).collect::<Result<Vec<_>, _>>().and_then(|filesystems| {
+            let sides: Vec<diff::Side> = filesystems.iter().zip(&paths).map(|(fs, path)| diff::Side { fs: fs.as_ref(), path }).collect();
+            side_by_side::list_side_by_side(&sides, &config, &mut stdout)
+        });
+        if let Err(e) = result
+            && !e.is_broken_pipe()
+        {
+            error::report(&e, config.errors, true);
+        }
+        drop(spinner);
+        return error::exit_status();
+    }
+    for path in &paths {
+        let fs = match backends.open(path) {
+            Ok(fs) => fs,
+            Err(e) => {
+                error::report(&e, config.errors, true);
+                continue;
+            }
+        };
+        match stat(fs.as_ref(), path, config.follow_symlinks != FollowSymlinks::Never) {
+            Ok(metadata) if metadata.is_dir() => dir_operands.push((operand_entry(path, metadata, &config), fs)),
+            Ok(_) => match collect_entries(fs.as_ref(), path, &config) {
+                Ok(entries) => file_operands.extend(entries),
+                Err(e) => error::report(&e, config.errors, true),
+            },
+            Err(source) => error::report(&LsError::CannotAccess { path: path.clone(), source }, config.errors, true),
+        }
+    }
+
+    if config.sort_args {
+        dir_operands.sort_by(|(a, _), (b, _)| compare_entries(a, b, &config));
+    }
+
+    if !dir_operands.is_empty() || file_operands.len() >= PARALLEL_THRESHOLD {
+        init_thread_pool(config.threads);
+    }
+
+    // A first pass over everything that will be listed; errors are left for the second
+    if config.uniform_widths && config.long {
+        let widths = dir_operands
+            .par_iter()
+            .map(|(operand, fs)| tree_widths(fs.as_ref(), &operand.path, &config))
+            .reduce(|| LongWidths::of(&file_operands, &config), LongWidths::max);
+        config.long_widths = widths;
+    }
+
+    // -R always names the directory it starts from, like coreutils
+    // Record formats carry whole paths instead
+    let headers = (paths.len() > 1 || config.recursive || config.fs_info.is_some()) && !config.no_headers && config.records.is_none();
+    let mut printed = false;
+
+    if !file_operands.is_empty() {
+        sort_entries(&mut file_operands, &config);
+        let file_operands = shown(&mut file_operands, config.limit);
+        columns::fill(file_operands, &config);
+        if config.show_hardlinks {
+            assign_link_groups(file_operands);
+        }
+        if let Err(e) = print_entries(file_operands, &config, &mut stdout) {
+            if e.kind() == io::ErrorKind::BrokenPipe {
+                return error::exit_status();
+            }
+            error::report(&LsError::Write(e), config.errors, true);
+        }
+        printed = true;
+    }
+
+    for (operand, fs) in dir_operands {
+        interrupt::stop_if_requested(&mut stdout);
+        let path = &operand.path;
+        let mut list = || -> Result<(), LsError> {
+            if headers && printed {
+                writeln!(stdout)?;
+            }
+            if config.recursive && config.recurse_order != RecurseOrder::Pre {
+                return list_tree_reordered(fs.as_ref(), path, &config, headers, &mut stdout);
+            }
+            if config.recursive && config.unordered {
+                return list_tree_unordered(fs.as_ref(), path, &config, headers, &mut stdout);
+            }
+            if headers && config.header_stats {
+                // The stats come from the listing, so it is rendered before its header
+                let mut listing = Vec::new();
+                let rendered = render_directory(fs.as_ref(), path, &config, &mut listing)?;
+                write_operand_header(&mut stdout, fs.as_ref(), path, &config, rendered.summary.for_header(&config))?;
+                stdout.write_all(&listing)?;
+                return write_subtrees(fs.as_ref(), rendered.children, &config, &mut stdout);
+            }
+            if headers {
+                write_operand_header(&mut stdout, fs.as_ref(), path, &config, None)?;
+            }
+            list_directory(fs.as_ref(), path, &config, &mut stdout)
+        };
+        let result = list();
+        printed = true;
+
+        if let Err(e) = result {
+            if e.is_broken_pipe() {
+                break;
+            }
+            error::report(&e, config.errors, true);
+        }
+    }
+
+    interrupt::stop_if_requested(&mut stdout);
+    drop(spinner);
+    error::exit_status()
+}
+
+/// Read operands from a file (or stdin for `-`), split on NUL if present, else on newlines
+fn read_files_from(source: &Path) -> io::Result<Vec<PathBuf>> {
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut data = Vec::new();
+    if source == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut data)?;
+    } else {
+        fs::File::open(source)?.read_to_end(&mut data)?;
+    }
+
+    let separator = if data.contains(&0) { b'\0' } else { b'\n' };
+    Ok(data
+        .split(|&b| b == separator)
+        .filter(|chunk| !chunk.is_empty())
+        .map(|chunk| PathBuf::from(std::ffi::OsStr::from_bytes(chunk)))
+        .collect())
+}
+
+/// The `path:` line above a directory's listing, with the path's bytes as given
+/// and, with --header-stats, what the listing holds
+fn write_header(out: &mut dyn Write, path: &Path, stats: Option<DirSummary>) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    out.write_all(path.as_os_str().as_bytes())?;
+    write!(out, ":")?;
+    if let Some(stats) = stats {
+        write!(out, " {}", stats)?;
+    }
+    writeln!(out)
+}
+
+/// `write_header` for a directory operand, naming its filesystem type with --fs-info
+fn write_operand_header(out: &mut dyn Write, fs: &dyn Filesystem, path: &Path, config: &Config, stats: Option<DirSummary>) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    match config.fs_info.and_then(|_| fs.fs_type(path)) {
+        Some(fs_type) => {
+            out.write_all(path.as_os_str().as_bytes())?;
+            write!(out, ": ({})", fs_type)?;
+            if let Some(stats) = stats {
+                write!(out, " {}", stats)?;
+            }
+            writeln!(out)
+        }
+        None => write_header(out, path, stats),
+    }
+}
+
+/// How many entries a directory's listing holds and their total apparent size
+#[derive(Debug, Clone, Copy, Default)]
+struct DirSummary {
+    entries: usize,
+    bytes: u64,
+}
+
+impl DirSummary {
+    fn of(entries: &[Entry]) -> DirSummary {
+        DirSummary { entries: entries.len(), bytes: entries.iter().map(|e| e.metadata.size).sum() }
+    }
+
+    /// The summary to put in a header, if --header-stats asks for one
+    fn for_header(self, config: &Config) -> Option<DirSummary> {
+        config.header_stats.then_some(self)
+    }
+}
+
+impl std::fmt::Display for DirSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let noun = if self.entries == 1 { "file" } else { "files" };
+        write!(f, "{} {}, {}", self.entries, noun, format_size(self.bytes, true))
+    }
+}
+
+/// What rendering a directory found: the subdirectories -R should descend
+/// into, and a summary of the entries for --header-stats
+#[derive(Default)]
+struct Rendered {
+    children: Vec<PathBuf>,
+    summary: DirSummary,
+}
+
+pub fn list_directory(fs: &dyn Filesystem, path: &Path, config: &Config, stdout: &mut dyn Write) -> Result<(), LsError> {
+    if config.recursive && config.unordered {
+        return list_tree_unordered(fs, path, config, false, stdout);
+    }
+
+    let rendered = render_directory(fs, path, config, stdout)?;
+    write_subtrees(fs, rendered.children, config, stdout)
+}
+
+/// The listing `list_directory` would write for `path`, as bytes
+pub fn render_listing(fs: &dyn Filesystem, path: &Path, config: &Config) -> Result<Vec<u8>, LsError> {
+    let mut buf = Vec::new();
+    list_directory(fs, path, config, &mut buf)?;
+    Ok(buf)
+}
+
+/// A directory -R has yet to write, read ahead of time or not
+enum Pending {
+    Unread(PathBuf),
+    Read(Section, Vec<PathBuf>),
+}
+
+/// Write the subtrees below `children` in pre-order. Directories are written as
+/// soon as everything before them has been, while the next few in line are read
+/// in parallel; only those read-ahead listings are held in memory. With
+/// --low-memory nothing is read ahead and each listing goes straight to `out`
+fn write_subtrees(fs: &dyn Filesystem, children: Vec<PathBuf>, config: &Config, out: &mut dyn Write) -> Result<(), LsError> {
+    let window = rayon::current_num_threads() * 2;
+    let mut queue: VecDeque<Pending> = children.into_iter().map(Pending::Unread).collect();
+    while let Some(next) = queue.front() {
+        // --finish-on-interrupt: the directory already written is the last one
+        if interrupt::requested() {
+            break;
+        }
+        if config.low_memory.is_some() {
+            let Some(Pending::Unread(child)) = queue.pop_front() else {
+                unreachable!("nothing is read ahead with --low-memory");
+            };
+            if config.records.is_none() {
+                writeln!(out)?;
+                write_header(out, &child, None)?;
+            }
+            match render_directory(fs, &child, config, out) {
+                Ok(rendered) => {
+                    for grandchild in rendered.children.into_iter().rev() {
+                        queue.push_front(Pending::Unread(grandchild));
+                    }
+                }
+                Err(e) if e.is_broken_pipe() => return Err(e),
+                Err(e) => error::report(&e, config.errors, false),
+            }
+            continue;
+        }
+        if let Pending::Unread(_) = next {
+            let unread: Vec<&mut Pending> = queue.iter_mut().take(window).filter(|slot| matches!(slot, Pending::Unread(_))).collect();
+            unread.into_par_iter().for_each(|slot| {
+                if let Pending::Unread(path) = slot {
+                    let (section, children) = render_section(fs, path, config);
+                    *slot = Pending::Read(section, children);
+                }
+            });
+        }
+        let Some(Pending::Read((child, listing), children)) = queue.pop_front() else {
+            unreachable!("the head of the queue was just read");
+        };
+        if config.records.is_none() {
+            writeln!(out)?;
+        }
+        match listing {
+            Ok((buf, summary)) => {
+                if config.records.is_none() {
+                    write_header(out, &child, summary.for_header(config))?;
+                }
+                out.write_all(&buf)?
+            }
+            Err(e) => {
+                if config.records.is_none() {
+                    write_header(out, &child, None)?;
+                }
+                error::report(&e, config.errors, false)
+            }
+        }
+        // A directory's subtrees come before its later siblings
+        for grandchild in children.into_iter().rev() {
+            queue.push_front(Pending::Unread(grandchild));
+        }
+    }
+    Ok(())
+}
+
+/// A directory's rendered listing and its summary, or why it couldn't be read
+type Section = (PathBuf, Result<(Vec<u8>, DirSummary), LsError>);
+
+/// -R --recurse-order=post|breadth: the whole tree is read, then its sections are
+/// written in that order. `header` says whether `path` itself gets one
+fn list_tree_reordered(fs: &dyn Filesystem, path: &Path, config: &Config, header: bool, out: &mut dyn Write) -> Result<(), LsError> {
+    let sections = match config.recurse_order {
+        RecurseOrder::Post => post_order_sections(fs, path, config),
+        RecurseOrder::Breadth => breadth_first_sections(fs, path, config),
+        RecurseOrder::Pre => unreachable!("pre-order listings are streamed by list_directory"),
+    };
+    for (i, (dir, listing)) in sections.into_iter().enumerate() {
+        if interrupt::requested() {
+            break;
+        }
+        let top = dir == path;
+        if i > 0 && config.records.is_none() {
+            writeln!(out)?;
+        }
+        let stats = listing.as_ref().ok().and_then(|(_, summary)| summary.for_header(config));
+        if header && top && config.records.is_none() {
+            write_operand_header(out, fs, &dir, config, stats)?;
+        } else if !top && config.records.is_none() {
+            write_header(out, &dir, stats)?;
+        }
+        match listing {
+            Ok((buf, _)) => out.write_all(&buf)?,
+            Err(e) if top => return Err(e),
+            Err(e) => error::report(&e, config.errors, false),
+        }
+    }
+    Ok(())
+}
+
+fn render_section(fs: &dyn Filesystem, path: &Path, config: &Config) -> (Section, Vec<PathBuf>) {
+    let mut buf = Vec::new();
+    match render_directory(fs, path, config, &mut buf) {
+        Ok(rendered) => ((path.to_path_buf(), Ok((buf, rendered.summary))), rendered.children),
+        Err(e) => ((path.to_path_buf(), Err(e)), Vec::new()),
+    }
+}
+
+fn post_order_sections(fs: &dyn Filesystem, path: &Path, config: &Config) -> Vec<Section> {
+    let (section, children) = render_section(fs, path, config);
+    let subtrees: Vec<Vec<Section>> = children.par_iter().map(|child| post_order_sections(fs, child, config)).collect();
+    let mut sections: Vec<Section> = subtrees.into_iter().flatten().collect();
+    sections.push(section);
+    sections
+}
+
+fn breadth_first_sections(fs: &dyn Filesystem, path: &Path, config: &Config) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut level = vec![path.to_path_buf()];
+    while !level.is_empty() {
+        let rendered: Vec<(Section, Vec<PathBuf>)> = level.par_iter().map(|dir| render_section(fs, dir, config)).collect();
+        level = Vec::new();
+        for (section, children) in rendered {
+            sections.push(section);
+            level.extend(children);
+        }
+    }
+    sections
+}
+
+/// -R --unordered: each directory is written with its header as soon as it is read.
+/// `header` says whether `path` itself gets one
+fn list_tree_unordered(fs: &dyn Filesystem, path: &Path, config: &Config, header: bool, stdout: &mut dyn Write) -> Result<(), LsError> {
+    let mut buf = Vec::new();
+    let Rendered { children, summary } = render_directory(fs, path, config, &mut buf)?;
+    if header {
+        write_operand_header(stdout, fs, path, config, summary.for_header(config))?;
+    }
+    stdout.write_all(&buf)?;
+
+    let (tx, rx) = crossbeam::channel::unbounded::<Vec<u8>>();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            children.into_par_iter().for_each_with(tx, |tx, child| walk_unordered(fs, &child, config, tx));
+        });
+        // Dropping the receiver on a write error makes the walkers stop sending and descending
+        for section in rx {
+            stdout.write_all(&section)?;
+        }
+        Ok(())
+    })
+}
+
+fn walk_unordered(fs: &dyn Filesystem, path: &Path, config: &Config, tx: &mut crossbeam::channel::Sender<Vec<u8>>) {
+    if interrupt::requested() {
+        return;
+    }
+    let mut listing = Vec::new();
+    let rendered = render_directory(fs, path, config, &mut listing);
+    let mut section = Vec::new();
+    if config.records.is_none() {
+        let stats = rendered.as_ref().ok().and_then(|rendered| rendered.summary.for_header(config));
+        section.push(b'\n');
+        write_header(&mut section, path, stats).expect("writing to a Vec cannot fail");
+    }
+    match rendered {
+        Ok(Rendered { children, .. }) => {
+            section.extend_from_slice(&listing);
+            if tx.send(section).is_ok() {
+                children.into_par_iter().for_each_with(tx.clone(), |tx, child| walk_unordered(fs, &child, config, tx));
+            }
+        }
+        Err(e) => {
+            // The header still goes out so the error can be matched to its directory
+            let _ = tx.send(section);
+            error::report(&e, config.errors, false);
+        }
+    }
+}
+
+/// Print one directory's listing to `out` and return the subdirectories -R should descend into
+fn render_directory(fs: &dyn Filesystem, path: &Path, config: &Config, out: &mut dyn Write) -> Result<Rendered, LsError> {
+    if let Some(threshold) = config.low_memory {
+        return spill::render_directory(fs, path, config, threshold, out);
+    }
+    let entries = collect_entries(fs, path, config)?;
+    render_entries(fs, path, entries, config, out)
+}
+
+/// Sort and print the entries read from `path`, returning the subdirectories -R
+/// should descend into and the entries' summary. The listing is rendered whole and then written with one
+/// `write_all`, so sections produced in parallel can never interleave
+fn render_entries(fs: &dyn Filesystem, path: &Path, mut entries: Vec<Entry>, config: &Config, out: &mut dyn Write) -> Result<Rendered, LsError> {
+    sort_entries(&mut entries, config);
+    if config.detect_case_collisions {
+        let count = mark_case_collisions(&mut entries);
+        if count > 0 {
+            error::warn(&LsError::CaseCollisions { path: path.to_path_buf(), count }, config.errors);
+        }
+    }
+
+    let mut buf = Vec::new();
+    // The total still covers the whole directory, and -R still descends into all of it
+    if (config.long || config.blocks) && config.records.is_none() && stat(fs, path, config.follow_symlinks != FollowSymlinks::Never).is_ok_and(|m| m.is_dir()) {
+        write_total(entries.iter().map(|e| e.metadata.blocks * 512).sum(), config, &mut buf)?;
+    }
+    let shown = shown(&mut entries, config.limit);
+    columns::fill(shown, config);
+    if config.show_hardlinks {
+        assign_link_groups(shown);
+    }
+    print_entries(shown, config, &mut buf)?;
+    out.write_all(&buf)?;
+
+    let summary = DirSummary::of(&entries);
+    if !config.recursive {
+        return Ok(Rendered { children: Vec::new(), summary });
+    }
+    Ok(Rendered { children: descend(fs, path, &entries, config), summary })
+}
+
+/// Mark the entries whose names differ from another's only in case, the
+/// ones a case-insensitive filesystem can't hold side by side, and count them
+fn mark_case_collisions(entries: &mut [Entry]) -> usize {
+    let mut folded: HashMap<String, usize> = HashMap::new();
+    for entry in entries.iter() {
+        *folded.entry(entry.name.to_lowercase()).or_default() += 1;
+    }
+    let mut count = 0;
+    for entry in entries.iter_mut() {
+        if folded[&entry.name.to_lowercase()] > 1 {
+            entry.case_collision = true;
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The `total` line of -l and -s for `total_bytes` of allocated space
+fn write_total(total_bytes: u64, config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    if config.human_readable {
+        writeln!(out, "total {}", format_size(total_bytes, true))
+    } else {
+        writeln!(out, "total {}", total_bytes.div_ceil(config.block_size))
+    }
+}
+
+/// `subdirectories`, reporting the cycles among them
+fn descend(fs: &dyn Filesystem, path: &Path, entries: &[Entry], config: &Config) -> Vec<PathBuf> {
+    let (children, cycles): (Vec<_>, Vec<_>) = subdirectories(fs, path, entries, config).into_iter().partition(Result::is_ok);
+    for cycle in cycles {
+        error::report(&cycle.unwrap_err(), config.errors, true);
+    }
+    children.into_iter().map(Result::unwrap).collect()
+}
+
+/// The directories among `entries` that -R descends into. With -L, one that
+/// is also `path` or one of its ancestors would be listed forever, so it is a
+/// `DirectoryCycle` instead
+fn subdirectories(fs: &dyn Filesystem, path: &Path, entries: &[Entry], config: &Config) -> Vec<Result<PathBuf, LsError>> {
+    let mut dirs = entries
+        .iter()
+        .filter(|entry| entry.metadata.is_dir() && entry.name != "." && entry.name != "..")
+        // Pruned before anything is read from them, so their size doesn't matter
+        .filter(|entry| !is_ignored(&entry.path, &config.prune))
+        .peekable();
+    // Only links can close a loop, and only -L follows links below the operands
+    let ancestors: HashSet<(u64, u64)> = if config.follow_symlinks == FollowSymlinks::Always && dirs.peek().is_some() {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .filter_map(|ancestor| fs.metadata(ancestor).ok())
+            .map(|stat| (stat.dev, stat.ino))
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    dirs.map(|entry| {
+        let child = child_path(path, entry);
+        if ancestors.contains(&(entry.metadata.dev, entry.metadata.ino)) {
+            Err(LsError::DirectoryCycle { path: child })
+        } else {
+            Ok(child)
+        }
+    })
+    .collect()
+}
+
+/// The long-format widths needed by `path` and, with -R, every directory below it
+fn tree_widths(fs: &dyn Filesystem, path: &Path, config: &Config) -> LongWidths {
+    let Ok(entries) = collect_entries(fs, path, config) else {
+        return LongWidths::default();
+    };
+    let widths = LongWidths::of(&entries, config);
+    if !config.recursive {
+        return widths;
+    }
+    subdirectories(fs, path, &entries, config)
+        .into_par_iter()
+        .filter_map(Result::ok)
+        .map(|child| tree_widths(fs, &child, config))
+        .reduce(|| widths, LongWidths::max)
+}
+
+/// Whether names get colored under the --color mode
+fn use_color(config: &Config) -> bool {
+    match config.color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => is_tty(),
+    }
+}
+
+/// Print already sorted entries in the output format selected by `config`
+fn print_entries(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    stats::time(stats::Phase::Format, || write_entries(entries, config, out))
+}
+
+fn write_entries(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    let use_color = use_color(config);
+
+    if let Some(records) = config.records {
+        match records {
+            RecordFormat::Porcelain(version) => porcelain::print_porcelain(entries, version, out),
+            RecordFormat::Yaml => structured::print_yaml(entries, config, out),
+            RecordFormat::Json => structured::print_json(entries, config, out),
+            RecordFormat::Cbor => structured::print_cbor(entries, config, out),
+        }
+    } else if config.format == OutputFormat::LongGrid {
+        print_long_grid(entries, config, out, use_color)
+    } else if config.long && config.truncate {
+        let mut clipped = truncate::TruncatingWriter::new(out, config.width);
+        print_long_format(entries, config, &mut clipped, use_color)?;
+        clipped.flush()
+    } else if config.long {
+        print_long_format(entries, config, out, use_color)
+    } else {
+        match config.format {
+            OutputFormat::SingleColumn => print_single_column(entries, config, out, use_color),
+            OutputFormat::Stream => print_stream_format(entries, config, out, use_color),
+            OutputFormat::MultiColumnAcross => print_multi_column_across(entries, config, out, use_color),
+            OutputFormat::MultiColumnDown => print_multi_column_down(entries, config, out, use_color),
+            OutputFormat::Compact => print_compact(entries, config, out, use_color),
+            OutputFormat::Stat => stat_block::print_stat(entries, config, out, use_color),
+            OutputFormat::LongGrid => unreachable!("handled above"),
+        }
+    }
+}
+
+/// Path of a subdirectory for -R, built from the parent as the user spelled it
+/// so headers keep the operand's prefix and never gain a doubled slash
+fn child_path(parent: &Path, entry: &Entry) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = entry.path.file_name().unwrap_or(entry.path.as_os_str());
+    let mut child = parent.as_os_str().to_owned();
+    if !child.as_bytes().ends_with(b"/") {
+        child.push("/");
+    }
+    child.push(name);
+    PathBuf::from(child)
+}
+
+/// Compare the names of two entries under the --sort-case mode, using the
+/// folded names cached at collection time for the default insensitive mode
+fn compare_entry_names(a: &Entry, b: &Entry, mode: SortCase) -> std::cmp::Ordering {
+    match mode {
+        SortCase::Insensitive => a.display.folded_name.cmp(&b.display.folded_name),
+        SortCase::Sensitive => a.name_bytes().cmp(b.name_bytes()),
+        SortCase::Natural => compare_natural(&a.name, &b.name),
+    }
+}
+
+/// Compare two names under the --sort-case mode
+fn compare_names(a: &str, b: &str, mode: SortCase) -> std::cmp::Ordering {
+    match mode {
+        SortCase::Sensitive => a.as_bytes().cmp(b.as_bytes()),
+        SortCase::Insensitive => a.to_lowercase().cmp(&b.to_lowercase()),
+        SortCase::Natural => compare_natural(a, b),
+    }
+}
+
+/// Case-folded comparison where digit runs compare by numeric value, so
+/// `file9` sorts before `file10`; exact ties fall back to byte order
+fn compare_natural(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let (mut left, mut right) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let (l, r) = match (left.peek(), right.peek()) {
+            (None, None) => return a.as_bytes().cmp(b.as_bytes()),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&l), Some(&r)) => (l, r),
+        };
+        if l.is_ascii_digit() && r.is_ascii_digit() {
+            let take_digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                digits
+            };
+            let (l_digits, r_digits) = (take_digits(&mut left), take_digits(&mut right));
+            let (l_num, r_num) = (l_digits.trim_start_matches('0'), r_digits.trim_start_matches('0'));
+            let cmp = l_num.len().cmp(&r_num.len()).then_with(|| l_num.cmp(r_num));
+            if cmp != Ordering::Equal {
+                return cmp;
+            }
+        } else {
+            let cmp = l.to_lowercase().cmp(r.to_lowercase());
+            if cmp != Ordering::Equal {
+                return cmp;
+            }
+            left.next();
+            right.next();
+        }
+    }
+}
+
+pub fn sort_entries(entries: &mut [Entry], config: &Config) {
+    // Apply sorting (use parallel sort for large directories)
+    const PARALLEL_SORT_THRESHOLD: usize = 1000;
+
+    if matches!(config.sort, SortBy::Unsorted) {
+        return;
+    }
+    stats::time(stats::Phase::Sort, || {
+        if entries.len() > PARALLEL_SORT_THRESHOLD {
+            entries.par_sort_by(|a, b| compare_entries(a, b, config));
+        } else {
+            entries.sort_by(|a, b| compare_entries(a, b, config));
+        }
+    })
+}
+
+/// Entries compare as (key, name): the --sort key first, newest or largest
+/// first, then the name. -r reverses the whole comparison once, name included,
+/// as GNU ls does by swapping the operands of its comparators
+fn compare_entries(a: &Entry, b: &Entry, config: &Config) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let key = match (&config.pipeline.comparator, config.sort) {
+        (_, SortBy::Unsorted) | (None, SortBy::Name) => Ordering::Equal,
+        (Some(comparator), _) => comparator.compare(a, b),
+        (None, SortBy::Time) => sort_time(&b.metadata, config.time_field).cmp(&sort_time(&a.metadata, config.time_field)),
+        (None, SortBy::Size) => b.metadata.size.cmp(&a.metadata.size),
+    };
+    let cmp = key.then_with(|| compare_entry_names(a, b, config.sort_case));
+    if config.reverse { cmp.reverse() } else { cmp }
+}
+
+/// The time -t sorts by, to the nanosecond, so changes within one second
+/// still come newest first
+fn sort_time(metadata: &FileStat, field: TimeField) -> (i64, u32) {
+    match field {
+        TimeField::Modify => (metadata.mtime, metadata.mtime_nsec),
+        TimeField::Change => (metadata.ctime, metadata.ctime_nsec),
+        TimeField::Access => (metadata.atime, metadata.atime_nsec),
+        TimeField::Birth => match metadata.btime {
+            Some(btime) => (btime, metadata.btime_nsec),
+            None => (metadata.ctime, metadata.ctime_nsec),
+        },
+    }
+}
+
+/// A command-line operand as an entry, named the way the user spelled it
+fn operand_entry(path: &Path, metadata: FileStat, config: &Config) -> Entry {
+    let raw_name = path.to_str().is_none().then(|| path.as_os_str().to_owned());
+    let name = normalize_name(path.to_string_lossy().to_string(), config.normalize);
+    let display = DisplayCache::new(&name, &metadata, None, config);
+    Entry {
+        name,
+        path: path.to_path_buf(),
+        metadata,
+        symlink_target: None,
+        target_metadata: None,
+        link_group: None,
+        file_flags: None,
+        xattrs: XattrSummary::default(),
+        link_status: None,
+        extra_columns: Vec::new(),
+        other_device: false,
+        case_collision: false,
+        foreign_owner: false,
+        child_count: None,
+        display,
+        raw_name,
+    }
+}
+
+/// `path`'s own metadata, or that of what it finally points to when `follow`
+fn stat(fs: &dyn Filesystem, path: &Path, follow: bool) -> io::Result<FileStat> {
+    if follow {
+        fs.metadata(path)
+    } else {
+        fs.symlink_metadata(path)
+    }
+}
+
+pub fn collect_entries(fs: &dyn Filesystem, path: &Path, config: &Config) -> Result<Vec<Entry>, LsError> {
+    // Operands are followed by -H as well as -L
+    let metadata = stat(fs, path, config.follow_symlinks != FollowSymlinks::Never)
+        .map_err(|source| LsError::CannotAccess { path: path.to_path_buf(), source })?;
+
+    // Handle single file case (no parallelism needed)
+    if !metadata.is_dir() {
+        let mut entry = operand_entry(path, metadata, config);
+        if entry.metadata.is_symlink() {
+            entry.symlink_target = fs.read_link(path).ok();
+            entry.target_metadata = read_target_metadata(fs, path, config);
+        }
+        entry.file_flags = read_file_flags(fs, path, &entry.metadata, config);
+        entry.xattrs = read_xattrs(fs, path, config);
+        entry.link_status = read_link_status(fs, path, &entry.metadata, config);
+        entry.child_count = read_child_count(fs, path, &entry.metadata, config);
+        return Ok(vec![entry]);
+    }
+
+    // Collect directory entries first (read_dir is sequential)
+    let dir_entries = stats::time(stats::Phase::Read, || fs.read_dir(path))
+        .map_err(|source| LsError::CannotOpenDir { path: path.to_path_buf(), source })?;
+    Ok(stats::time(stats::Phase::Stat, || build_entries(fs, dir_entries, listed_dir(&metadata, config), config)))
+}
+
+/// What entries are compared against about the directory listing them
+#[derive(Debug, Clone, Copy, Default)]
+struct ListedDir {
+    /// Its device, for --fs-info=mounts
+    dev: Option<u64>,
+    /// Its owner, for --mark-foreign-owners
+    uid: Option<u32>,
+}
+
+impl ListedDir {
+    /// Whether anything about the directory is needed at all
+    fn wanted(config: &Config) -> bool {
+        config.fs_info == Some(FsInfo::Mounts) || config.mark_foreign_owners
+    }
+}
+
+fn listed_dir(dir: &FileStat, config: &Config) -> ListedDir {
+    ListedDir {
+        dev: (config.fs_info == Some(FsInfo::Mounts)).then_some(dir.dev),
+        uid: config.mark_foreign_owners.then_some(dir.uid),
+    }
+}
+
+/// Filter the names read from a directory and stat what's left into entries
+fn build_entries(fs: &dyn Filesystem, dir_entries: Vec<DirEntry>, dir: ListedDir, config: &Config) -> Vec<Entry> {
+    let hidden = hidden_names(fs, &dir_entries, config);
+    // Prepare entry data without metadata
+    let entry_data: Vec<_> = dir_entries
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.name;
+            
+            // Filter dotfiles based on flags
+            if name.starts_with('.') {
+                if config.all {
+                    // -a: show everything including . and ..
+                } else if config.almost_all {
+                    // -A: show dotfiles except . and ..
+                    if name == "." || name == ".." {
+                        return None;
+                    }
+                } else {
+                    // default: hide all dotfiles
+                    return None;
+                }
+            } else if hidden.contains(&name) {
+                return None;
+            }
+            
+            if is_ignored(&entry.path, &config.ignore) || !matches_name_filters(&name, config) {
+                return None;
+            }
+
+            Some((name, entry.path))
+        })
+        .collect();
+    
+    // Stat everything in one batch, then finish each entry (in parallel for big directories)
+    let paths: Vec<&Path> = entry_data.iter().map(|(_, path)| path.as_path()).collect();
+    let stats = fs.symlink_metadata_batch(&paths);
+    let finish = |((name, path), stat): ((String, PathBuf), io::Result<FileStat>)| {
+        let mut metadata = match stat {
+            Ok(metadata) => metadata,
+            Err(source) => {
+                error::report(&LsError::CannotAccess { path, source }, config.errors, false);
+                return None;
+            }
+        };
+        // -L: show what the link points to; a dangling or looping link is
+        // reported and listed as the link itself
+        if config.follow_symlinks == FollowSymlinks::Always && metadata.is_symlink() {
+            match fs.metadata(&path) {
+                Ok(target) => metadata = target,
+                Err(source) => error::report(&LsError::CannotAccess { path: path.clone(), source }, config.errors, false),
+            }
+        }
+        if !matches_owners(&metadata, config) || config.perm.is_some_and(|perm| !perm.matches(metadata.mode)) {
+            return None;
+        }
+        if let Some(du) = &config.du
+            && config.long
+            && metadata.is_dir()
+            && name != "."
+            && name != ".."
+        {
+            (metadata.size, metadata.blocks) = du.total(fs, &path, &metadata);
+        }
+        let (symlink_target, target_metadata) = if metadata.is_symlink() {
+            (fs.read_link(&path).ok(), read_target_metadata(fs, &path, config))
+        } else {
+            (None, None)
+        };
+        
+        let file_flags = read_file_flags(fs, &path, &metadata, config);
+        let xattrs = read_xattrs(fs, &path, config);
+        let link_status = read_link_status(fs, &path, &metadata, config);
+        let child_count = read_child_count(fs, &path, &metadata, config);
+        let other_device = dir.dev.is_some_and(|dev| dev != metadata.dev);
+        // `..` belongs to the parent, not to the directory being audited
+        let foreign_owner = name != ".." && dir.uid.is_some_and(|uid| uid != metadata.uid);
+        progress::record_scanned(1);
+
+        let raw_name = path.file_name().filter(|raw| raw.to_str().is_none()).map(|raw| raw.to_owned());
+        let name = normalize_name(name, config.normalize);
+        let display = DisplayCache::new(&name, &metadata, child_count, config);
+        Some(Entry {
+            name,
+            path,
+            metadata,
+            symlink_target,
+            target_metadata,
+            link_group: None,
+            file_flags,
+            xattrs,
+            link_status,
+            extra_columns: Vec::new(),
+            other_device,
+            case_collision: false,
+            foreign_owner,
+            child_count,
+            display,
+            raw_name,
+        })
+        .filter(|entry| config.pipeline.keeps(entry))
+    };
+    if entry_data.len() < PARALLEL_THRESHOLD {
+        entry_data.into_iter().zip(stats).filter_map(finish).collect()
+    } else {
+        entry_data.into_par_iter().zip(stats).filter_map(finish).collect()
+    }
+}
+
+/// Names the directory's `.hidden` file asks to hide, the freedesktop.org
+/// convention file managers follow: one name per line. -a and -A show them
+fn hidden_names(fs: &dyn Filesystem, dir_entries: &[DirEntry], config: &Config) -> HashSet<String> {
+    if config.all || config.almost_all {
+        return HashSet::new();
+    }
+    // Only a directory that has the file pays for reading it
+    let Some(list) = dir_entries.iter().find(|entry| entry.name == ".hidden") else {
+        return HashSet::new();
+    };
+    let Ok(contents) = fs.read_file(&list.path) else {
+        return HashSet::new();
+    };
+    String::from_utf8_lossy(&contents)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether the file name of `path` matches one of the --ignore-glob patterns
+fn is_ignored(path: &Path, patterns: &[CString]) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    if patterns.is_empty() {
+        return false;
+    }
+    let Some(name) = path.file_name().and_then(|name| CString::new(name.as_bytes()).ok()) else {
+        return false;
+    };
+    // Without FNM_PERIOD, * also matches a leading dot, so -B hides .bashrc~ too
+    patterns.iter().any(|pattern| unsafe { libc::fnmatch(pattern.as_ptr(), name.as_ptr(), 0) } == 0)
+}
+
+/// Whether `name` passes --regex and --find
+fn matches_name_filters(name: &str, config: &Config) -> bool {
+    config.regex.as_ref().is_none_or(|regex| regex.is_match(name))
+        && config.find.as_ref().is_none_or(|find| name.to_lowercase().contains(find.as_str()))
+}
+
+/// Whether the entry passes --owner and --group
+fn matches_owners(metadata: &FileStat, config: &Config) -> bool {
+    config.owner.is_none_or(|uid| uid == metadata.uid) && config.group.is_none_or(|gid| gid == metadata.gid)
+}
+
+/// The id an --owner or --group argument names: a number is the id itself,
+/// anything else a name looked up where `lookup` finds names, or in the
+/// system databases under --no-lookup
+fn owner_id(spec: &str, lookup: &NameLookup, by_name: fn(&str) -> Option<u32>) -> Option<u32> {
+    if let Ok(id) = spec.parse() {
+        return Some(id);
+    }
+    match lookup {
+        // Like getpwnam, the lowest id wins when a file lists a name twice
+        NameLookup::Table(names) => names.iter().filter(|(_, name)| ***name == *spec).map(|(&id, _)| id).min(),
+        NameLookup::Numeric | NameLookup::Resolve { .. } => by_name(spec),
+    }
+}
+
+/// `name` in the --normalize form; the entry's path keeps the bytes on disk
+fn normalize_name(name: String, form: Option<Normalization>) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    match form {
+        // Most names are ASCII, which every form leaves alone
+        _ if name.is_ascii() => name,
+        None => name,
+        Some(Normalization::Nfc) => name.nfc().collect(),
+        Some(Normalization::Nfd) => name.nfd().collect(),
+    }
+}
+
+/// File flags for -O, skipping file types lsattr refuses to open
+fn read_file_flags(fs: &dyn Filesystem, path: &Path, metadata: &FileStat, config: &Config) -> Option<u32> {
+    if config.file_flags && (metadata.is_file() || metadata.is_dir()) {
+        fs.file_flags(path).ok()
+    } else {
+        None
+    }
+}
+
+/// How a symlink resolves, for --check-links
+fn read_link_status(fs: &dyn Filesystem, path: &Path, metadata: &FileStat, config: &Config) -> Option<LinkStatus> {
+    (config.check_links.is_some() && metadata.is_symlink()).then(|| filesystem::check_link(fs, path))
+}
+
+/// The metadata of a symlink's final target, for --dereference-both
+fn read_target_metadata(fs: &dyn Filesystem, path: &Path, config: &Config) -> Option<FileStat> {
+    if config.dereference_both && config.long {
+        fs.metadata(path).ok()
+    } else {
+        None
+    }
+}
+
+/// Number of entries in a directory for --dir-counts; runs on the same rayon
+/// workers as the stat calls, so large listings count their children in parallel
+fn read_child_count(fs: &dyn Filesystem, path: &Path, metadata: &FileStat, config: &Config) -> Option<usize> {
+    let sized = config.long || config.format == OutputFormat::LongGrid;
+    if ((config.dir_counts && config.long) || (config.dirs_show == DirsShow::Entries && sized)) && metadata.is_dir() {
+        fs.read_dir(path).ok().map(|children| children.len())
+    } else {
+        None
+    }
+}
+
+/// Extended attribute summary, only fetched when something will show it
+fn read_xattrs(fs: &dyn Filesystem, path: &Path, config: &Config) -> XattrSummary {
+    if config.long || config.tags || config.quarantine {
+        fs.xattr_summary(path)
+    } else {
+        XattrSummary::default()
+    }
+}
+
+/// Entry name decorated with its indicator, color and annotations, as the
+/// bytes to write; names that aren't valid UTF-8 pass through unchanged
+fn display_name(entry: &Entry, config: &Config, use_color: bool) -> Vec<u8> {
+    decorate_name(entry.name_bytes().to_vec(), entry, config, use_color)
+}
+
+/// Apply indicator, color and annotations of `entry` to a (possibly shortened) name
+fn decorate_name(mut name: Vec<u8>, entry: &Entry, config: &Config, use_color: bool) -> Vec<u8> {
+    if config.hide_control_chars {
+        name = hide_control_chars(&String::from_utf8_lossy(&name)).into_bytes();
+    }
+    if config.classify || config.slash {
+        name.extend_from_slice(entry.display.indicator.as_bytes());
+    }
+    if use_color && let Some(style) = name_style(entry, config) {
+        name = theme::paint(&name, &style);
+    }
+    if let Some(label) = entry.xattrs.finder_label.filter(|_| config.tags) {
+        let (label_name, sgr) = finder_label_style(label, config.term.colors);
+        if use_color {
+            push_marker(&mut name, "●", &sgr, true);
+        } else {
+            push_marker(&mut name, &format!("[{}]", label_name), &sgr, false);
+        }
+    }
+    if config.quarantine && entry.xattrs.quarantined {
+        push_marker(&mut name, "[quarantined]", "\x1b[33m", use_color); // yellow for quarantine
+    }
+    if let Some(group) = entry.link_group {
+        push_marker(&mut name, &format!("[link#{}]", group), "\x1b[35m", use_color); // magenta for hardlink groups
+    }
+    if entry.other_device {
+        push_marker(&mut name, "[mount]", "\x1b[36m", use_color); // cyan for mount points
+    }
+    if entry.case_collision {
+        push_marker(&mut name, "[case]", "\x1b[33m", use_color); // yellow for case collisions
+    }
+    match (entry.link_status, config.check_links) {
+        (Some(LinkStatus::Dangling), _) => push_marker(&mut name, "[dangling]", "\x1b[31m", use_color), // red for broken links
+        (Some(LinkStatus::Loop), _) => push_marker(&mut name, "[loop]", "\x1b[31m", use_color),
+        (Some(LinkStatus::Resolves { hops }), Some(CheckLinks::Chain)) => {
+            push_marker(&mut name, &format!("[{} link{}]", hops, if hops == 1 { "" } else { "s" }), "\x1b[34m", use_color); // blue for chain lengths
+        }
+        _ => {}
+    }
+    if entry.foreign_owner {
+        let owner = get_user_name_cached(entry.metadata.uid, &config.user_lookup);
+        push_marker(&mut name, &format!("[owner:{}]", owner), "\x1b[31m", use_color); // red for foreign owners
+    }
+    name
+}
+
+/// The SGR style of a name: from --color-scale when given, else from the theme
+fn name_style<'a>(entry: &Entry, config: &'a Config) -> Option<std::borrow::Cow<'a, str>> {
+    use std::borrow::Cow;
+
+    match &config.color_scale {
+        None => config.theme.style(&entry.name, &entry.metadata).map(Cow::Borrowed),
+        Some(ColorScale::Owner) => Some(Cow::Owned(theme::owner_style(entry.metadata.uid, config.term.colors))),
+        Some(ColorScale::Risk { homes }) => {
+            let in_home = || {
+                std::path::absolute(&entry.path)
+                    .is_ok_and(|path| homes.iter().any(|home| path != *home && path.starts_with(home)))
+            };
+            theme::risk_style(&entry.metadata, in_home).map(Cow::Borrowed)
+        }
+    }
+}
+
+/// `text` with each control character (and each byte that wasn't UTF-8)
+/// replaced by `?`, so names can't move the cursor or recolor the terminal
+/// and every column keeps its width
+fn hide_control_chars(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() || c == char::REPLACEMENT_CHARACTER { '?' } else { c }).collect()
+}
+
+/// Append a space and an annotation, colored with `sgr` when color is on
+fn push_marker(name: &mut Vec<u8>, marker: &str, sgr: &str, use_color: bool) {
+    name.push(b' ');
+    if use_color {
+        name.extend_from_slice(format!("{}{}\x1b[0m", sgr, marker).as_bytes());
+    } else {
+        name.extend_from_slice(marker.as_bytes());
+    }
+}
+
+/// Columns a name takes up on the terminal: escape sequences take none, so a
+/// colored cell is padded after its reset exactly like a plain one
+fn name_width(name: &[u8]) -> usize {
+    truncate::display_width(name)
+}
+
+/// Name and color of a Finder label index; orange, which has no standard
+/// color of its own, is picked from the terminal's palette
+fn finder_label_style(label: u8, depth: termcaps::ColorDepth) -> (&'static str, String) {
+    let (name, sgr) = match label {
+        1 => ("gray", "90"),
+        2 => ("green", "32"),
+        3 => ("purple", "35"),
+        4 => ("blue", "34"),
+        5 => ("yellow", "33"),
+        6 => ("red", "31"),
+        _ => return ("orange", format!("\x1b[{}m", theme::Rgb(255, 135, 0).foreground(depth))),
+    };
+    (name, format!("\x1b[{}m", sgr))
+}
+
+/// The fields every format writes before an entry: the -i inode number, then
+/// the -s block count, each right-aligned to `widths` and followed by a space.
+/// `LongWidths::default()` leaves them unpadded
+fn prefix_fields(entry: &Entry, config: &Config, widths: &LongWidths) -> String {
+    let mut fields = String::new();
+    if config.inode {
+        fields.push_str(&format!("{:>width$} ", entry.metadata.ino, width = widths.inode));
+    }
+    if config.blocks {
+        fields.push_str(&format!("{:>width$} ", display_blocks(&entry.metadata, config.block_size), width = widths.blocks));
+    }
+    fields
+}
+
+pub fn print_single_column(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    let widths = LongWidths::of(entries, config).max(config.long_widths);
+    for entry in entries {
+        write!(stdout, "{}", prefix_fields(entry, config, &widths))?;
+        stdout.write_all(&display_name(entry, config, use_color))?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+pub fn print_multi_column_down(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    print_grid(entries, config, stdout, use_color, Direction::Down)
+}
+
+pub fn print_multi_column_across(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    print_grid(entries, config, stdout, use_color, Direction::Across)
+}
+
+fn print_grid(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool, direction: Direction) -> io::Result<()> {
+    let field_widths = LongWidths::of(entries, config).max(config.long_widths);
+    let names: Vec<Vec<u8>> = entries.iter()
+        .map(|e| {
+            let name = match config.max_name_width {
+                Some(max) => decorate_name(truncate_middle(&e.name, max).into_bytes(), e, config, use_color),
+                None => display_name(e, config, use_color),
+            };
+            [prefix_fields(e, config, &field_widths).into_bytes(), name].concat()
+        })
+        .collect();
+    let widths: Vec<usize> = names.iter().map(|n| name_width(n)).collect();
+    let gap = name_width(config.grid_gutter.as_bytes());
+
+    let grid = layout::grid(&widths, config.width, gap, direction);
+    let cell_width = grid.col_width - gap;
+
+    for row in &grid.rows {
+        for (i, &idx) in row.iter().enumerate() {
+            stdout.write_all(&names[idx])?;
+            if config.trim_grid && i + 1 == row.len() {
+                break;
+            }
+            write!(stdout, "{:pad$}{}", "", config.grid_gutter, pad = cell_width.saturating_sub(name_width(&names[idx])))?;
+        }
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}
+
+/// --long-grid: cells of size, time and name laid out like -C
+pub fn print_long_grid(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    let size_width = entries.iter().map(|e| e.display.size.chars().count()).max().unwrap_or(0);
+    let field_widths = LongWidths::of(entries, config).max(config.long_widths);
+    let cells: Vec<(Vec<u8>, usize)> = entries.iter()
+        .map(|e| {
+            let size = &e.display.size;
+            let time = format_time(get_time_field(&e.metadata, config.time_field), config.time_style, config.time_zone);
+            let prefix = format!("{}{:>size_width$} {} ", prefix_fields(e, config, &field_widths), size, time, size_width = size_width);
+            // Widths come from the uncolored name so escape codes don't count
+            let width = prefix.chars().count() + name_width(&display_name(e, config, false));
+            let mut cell = prefix.into_bytes();
+            cell.extend(display_name(e, config, use_color));
+            (cell, width)
+        })
+        .collect();
+    let widths: Vec<usize> = cells.iter().map(|(_, width)| *width).collect();
+    let gap = name_width(config.grid_gutter.as_bytes());
+
+    let grid = layout::grid(&widths, config.width, gap, Direction::Down);
+
+    for row in &grid.rows {
+        for (i, &idx) in row.iter().enumerate() {
+            let (cell, width) = &cells[idx];
+            stdout.write_all(cell)?;
+            // No trailing padding after the last cell of a row
+            if i + 1 < row.len() {
+                write!(stdout, "{:pad$}{}", "", config.grid_gutter, pad = grid.col_width - gap - width)?;
+            }
+        }
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}
+
+/// --compact: `ls -sh` sizes and ages instead of dates, padded only as much as alignment needs
+pub fn print_compact(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    let now = chrono::Local::now().timestamp();
+    let field_widths = LongWidths::of(entries, config).max(config.long_widths);
+    let rows: Vec<(String, String, String)> = entries.iter()
+        .map(|e| {
+            let bytes = match config.size_mode {
+                SizeMode::Apparent => e.metadata.size,
+                SizeMode::Allocated => e.metadata.blocks * 512,
+            };
+            let age = format_age(now.saturating_sub(get_time_field(&e.metadata, config.time_field)));
+            (prefix_fields(e, config, &field_widths), format_size(bytes, true), age)
+        })
+        .collect();
+    let size_width = rows.iter().map(|(_, size, _)| size.len()).max().unwrap_or(0);
+    let age_width = rows.iter().map(|(_, _, age)| age.len()).max().unwrap_or(0);
+
+    for (e, (prefix, size, age)) in entries.iter().zip(&rows) {
+        write!(stdout, "{}{:>size_width$} {:>age_width$} ", prefix, size, age, size_width = size_width, age_width = age_width)?;
+        stdout.write_all(&display_name(e, config, use_color))?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+/// How long ago something happened in its largest whole unit, e.g. 45s, 5m, 3d, 2y.
+/// Times in the future count as now
+fn format_age(secs: i64) -> String {
+    const UNITS: &[(i64, &str)] = &[(365 * 86_400, "y"), (30 * 86_400, "mo"), (86_400, "d"), (3_600, "h"), (60, "m")];
+    let secs = secs.max(0);
+    match UNITS.iter().find(|(unit, _)| secs >= *unit) {
+        Some((unit, suffix)) => format!("{}{}", secs / unit, suffix),
+        None => format!("{}s", secs),
+    }
+}
+
+pub fn print_stream_format(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    // Like GNU ls, -m doesn't align the prefixes
+    let prefixes: Vec<String> = entries.iter().map(|e| prefix_fields(e, config, &LongWidths::default())).collect();
+    let widths: Vec<usize> = entries.iter()
+        .zip(&prefixes)
+        .map(|(e, prefix)| prefix.len() + name_width(&display_name(e, config, false)))
+        .collect();
+    let lines = layout::stream(&widths, config.width);
+
+    for (n, line) in lines.iter().enumerate() {
+        for (i, &idx) in line.iter().enumerate() {
+            if i > 0 {
+                write!(stdout, ", ")?;
+            }
+            write!(stdout, "{}", prefixes[idx])?;
+            stdout.write_all(&display_name(&entries[idx], config, use_color))?;
+        }
+        if n + 1 < lines.len() {
+            write!(stdout, ",")?;
+        }
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+pub fn print_long_format(entries: &[Entry], config: &Config, stdout: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    // Pre-populate caches in parallel for large directories
+    if entries.len() > 100 {
+        warm_name_caches(entries, &config.user_lookup, &config.group_lookup);
+    }
+
+    // Calculate column widths
+    let widths = LongWidths::of(entries, config).max(config.long_widths);
+    let flags: Vec<String> = if config.file_flags {
+        entries.iter().map(|e| attrs::format_flags(e.file_flags)).collect()
+    } else {
+        Vec::new()
+    };
+    let counts: Vec<String> = if config.dir_counts { entries.iter().map(dir_count).collect() } else { Vec::new() };
+    let extra_widths: Vec<usize> = entries.iter().fold(Vec::new(), |mut widths, e| {
+        for (i, column) in e.extra_columns.iter().enumerate() {
+            let width = column.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+        widths
+    });
+    // Like macOS ls, widen the mode column by one when any entry has extended attributes
+    let xattr_column = entries.iter().any(|e| e.xattrs.has_xattrs);
+    let max_size = entries.iter().map(|e| e.metadata.size).max().unwrap_or(0);
+    let heat: Vec<String> = match config.heat {
+        Some(key) if use_color => heat_styles(entries, key, config.time_field, config.term.colors),
+        _ => Vec::new(),
+    };
+    let (show_user, show_group) = if config.collapse_uniform_columns {
+        write_uniform_owners(entries, config, stdout)?
+    } else {
+        (true, true)
+    };
+
+    for (idx, entry) in entries.iter().enumerate() {
+        // Colored names reset the tint themselves; plain ones stay tinted to the end
+        if let Some(style) = heat.get(idx) {
+            write!(stdout, "\x1b[{}m", style)?;
+        }
+        let mut mode_str = match config.octal_permissions {
+            None => format_mode(entry.metadata.mode),
+            Some(OctalPermissions::Both) => format!("{:04o} {}", entry.metadata.mode & 0o7777, format_mode(entry.metadata.mode)),
+            Some(OctalPermissions::Only) => format!("{:04o}", entry.metadata.mode & 0o7777),
+        };
+        if xattr_column {
+            mode_str.push(if entry.xattrs.has_xattrs { '@' } else { ' ' });
+        }
+        if config.access {
+            mode_str.push(' ');
+            mode_str.push_str(&access::summary(&entry.metadata));
+        }
+        let nlink = entry.metadata.nlink;
+        let uid = entry.metadata.uid;
+        let gid = entry.metadata.gid;
+        let time_val = get_time_field(&entry.metadata, config.time_field);
+
+        let is_device = entry.metadata.file_type().is_device();
+        
+        // Format size or device major:minor
+        let size_or_device = if is_device {
+            // Extract major and minor device numbers
+            let dev = entry.metadata.rdev;
+            let major = ((dev >> 24) & 0xFF) as u32;
+            let minor = (dev & 0xFFFFFF) as u32;
+            format!("{}, {}", major, minor)
+        } else {
+            entry.display.size.clone()
+        };
+
+        let time_str = format_time(time_val, config.time_style, config.time_zone);
+
+        write!(stdout, "{}{} {:>link_width$} ", prefix_fields(entry, config, &widths), mode_str, nlink, link_width = widths.link)?;
+        if show_user {
+            write!(stdout, "{:>8} ", owner_name(uid, true, config))?;
+        }
+        if show_group {
+            write!(stdout, "{:>8} ", owner_name(gid, false, config))?;
+        }
+
+        // Print file flags if requested
+        if config.file_flags {
+            write!(stdout, "{:<flags_width$} ", flags[idx], flags_width = widths.flags)?;
+        }
+
+        if config.dir_counts {
+            write!(stdout, "{:>count_width$} ", counts[idx], count_width = widths.count)?;
+        }
+        write!(stdout, "{:>size_width$} ", size_or_device, size_width = widths.size)?;
+        if config.size_bars {
+            let bar = if is_device { String::new() } else { size_bar(entry.metadata.size, max_size) };
+            write!(stdout, "{:<bar_width$} ", bar, bar_width = SIZE_BAR_CELLS)?;
+        }
+        write!(stdout, "{} ", time_str)?;
+
+        for (column, width) in entry.extra_columns.iter().zip(&extra_widths) {
+            write!(stdout, "{:<width$} ", column, width = width)?;
+        }
+
+        stdout.write_all(&display_name(entry, config, use_color))?;
+
+        if let Some(ref target) = entry.symlink_target {
+            write!(stdout, " -> ")?;
+            if config.hide_control_chars {
+                write!(stdout, "{}", hide_control_chars(&target.to_string_lossy()))?;
+            } else {
+                stdout.write_all(target.as_os_str().as_bytes())?;
+            }
+        }
+
+        if !heat.is_empty() {
+            write!(stdout, "\x1b[0m")?;
+        }
+        writeln!(stdout)?;
+
+        if config.dereference_both && entry.metadata.is_symlink() {
+            write_target_line(entry, config, widths.size, stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The owner (`user`) or group cell for `id`, shortened by --truncate-owner
+fn owner_name(id: u32, user: bool, config: &Config) -> String {
+    let name = if user { get_user_name_cached(id, &config.user_lookup) } else { get_group_name_cached(id, &config.group_lookup) };
+    match config.truncate_owner {
+        Some(max) => truncate_end(&name, max),
+        None => name.to_string(),
+    }
+}
+
+/// --collapse-uniform-columns: name the owner and the group above the entries
+/// when every entry shares them, and return which columns are still needed
+fn write_uniform_owners(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<(bool, bool)> {
+    let Some(first) = entries.first() else {
+        return Ok((true, true));
+    };
+    let uniform_user = entries.iter().all(|e| e.metadata.uid == first.metadata.uid);
+    let uniform_group = entries.iter().all(|e| e.metadata.gid == first.metadata.gid);
+    let mut shared = Vec::new();
+    if uniform_user {
+        shared.push(format!("owner {}", owner_name(first.metadata.uid, true, config)));
+    }
+    if uniform_group {
+        shared.push(format!("group {}", owner_name(first.metadata.gid, false, config)));
+    }
+    if !shared.is_empty() {
+        writeln!(out, "{}", shared.join(", "))?;
+    }
+    Ok((!uniform_user, !uniform_group))
+}
+
+/// --dereference-both: the mode, size and time of a symlink's target, indented
+/// under the link's line; a dangling or looping link gets question marks
+fn write_target_line(entry: &Entry, config: &Config, size_width: usize, out: &mut dyn Write) -> io::Result<()> {
+    let (mode, size, time) = match &entry.target_metadata {
+        Some(target) => (
+            format_mode(target.mode),
+            format_size_field(target, config),
+            format_time(get_time_field(target, config.time_field), config.time_style, config.time_zone),
+        ),
+        None => ("?".repeat(10), "?".to_string(), "?".to_string()),
+    };
+    writeln!(out, "    => {} {:>size_width$} {}", mode, size, time, size_width = size_width)
+}
+
+/// The --dir-counts cell: files leave it blank, unreadable directories show ?
+fn dir_count(entry: &Entry) -> String {
+    match entry.child_count {
+        Some(count) => count.to_string(),
+        None if entry.metadata.is_dir() => "?".to_string(),
+        None => String::new(),
+    }
+}
+
+// Width of a --size-bars bar in character cells
+const SIZE_BAR_CELLS: usize = 8;
+
+/// A bar of `size` relative to `max`, drawn in eighth-cell steps
+fn size_bar(size: u64, max: u64) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    if max == 0 {
+        return String::new();
+    }
+    let eighths = (size as u128 * (SIZE_BAR_CELLS * 8) as u128).div_ceil(max as u128) as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(PARTIAL[eighths % 8]);
+    }
+    bar
+}
+
+/// Per-entry SGR tint for --heat: the newest (or largest) entry of the listing
+/// is green (or orange) and the oldest (or smallest) gray
+fn heat_styles(entries: &[Entry], key: Heat, time_field: TimeField, depth: termcaps::ColorDepth) -> Vec<String> {
+    const COLD: theme::Rgb = theme::Rgb(118, 118, 118);
+    let (hot, values): (theme::Rgb, Vec<f64>) = match key {
+        Heat::Time => (
+            theme::Rgb(95, 215, 95),
+            entries.iter().map(|e| get_time_field(&e.metadata, time_field) as f64).collect(),
+        ),
+        // Sizes span orders of magnitude, so grade them on a log scale
+        Heat::Size => (
+            theme::Rgb(255, 135, 0),
+            entries.iter().map(|e| (e.metadata.size as f64).ln_1p()).collect(),
+        ),
+    };
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|&v| {
+            let t = if max > min { (v - min) / (max - min) } else { 1.0 };
+            COLD.mix(hot, t).foreground(depth)
+        })
+        .collect()
+}
+
+/// Shorten `s` to at most `max` characters, ending in an ellipsis when cut
+fn truncate_end(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut out: String = s.chars().take(max.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// Shorten `s` to at most `max` characters by replacing its middle with an ellipsis
+fn truncate_middle(s: &str, max: usize) -> String {
+    let len = s.chars().count();
+    if len <= max {
+        return s.to_string();
+    }
+    let keep = max.saturating_sub(1);
+    let head = keep.div_ceil(2);
+    let tail = keep - head;
+    let mut out: String = s.chars().take(head).collect();
+    out.push('…');
+    out.extend(s.chars().skip(len - tail));
+    out
+}
+
+fn format_mode(mode: u32) -> String {
+    let file_type = match FileKind::from_mode(mode) {
+        FileKind::Regular => '-',
+        FileKind::Directory => 'd',
+        FileKind::Symlink => 'l',
+        FileKind::CharDevice => 'c',
+        FileKind::BlockDevice => 'b',
+        FileKind::Fifo => 'p',
+        FileKind::Socket => 's',
+        FileKind::Door => 'D',
+        FileKind::Whiteout => 'w',
+        FileKind::Unknown => '?',
+    };
+
+    let perms = [
+        (0o400, 'r'), (0o200, 'w'), (0o100, 'x'),
+        (0o040, 'r'), (0o020, 'w'), (0o010, 'x'),
+        (0o004, 'r'), (0o002, 'w'), (0o001, 'x'),
+    ];
+
+    let mut result = String::with_capacity(10);
+    result.push(file_type);
+    
+    for (bit, ch) in perms {
+        if mode & bit != 0 {
+            result.push(ch);
+        } else {
+            result.push('-');
+        }
+    }
+
+    result
+}
+
+fn format_time(secs: i64, style: TimeStyle, zone: Zone) -> String {
+    const SIX_MONTHS: i64 = 6 * 30 * 24 * 60 * 60;
+
+    // Timestamps chrono can't represent (hundreds of millennia away) stay numeric
+    let Some(datetime) = zone.datetime(secs, 0) else {
+        return secs.to_string();
+    };
+    // Pre-1970 files have negative times; future ones a negative age. Both show the year
+    let age = chrono::Utc::now().timestamp().saturating_sub(secs);
+
+    let recent = (0..=SIX_MONTHS).contains(&age);
+
+    match style {
+        TimeStyle::Default if recent => datetime.format("%b %e %H:%M").to_string(),
+        TimeStyle::Default => datetime.format("%b %e  %Y").to_string(),
+        TimeStyle::Locale { locale, month_width } => {
+            let month = datetime.format_localized("%b", locale).to_string();
+            let rest = if recent { "%e %H:%M" } else { "%e  %Y" };
+            format!("{:<month_width$} {}", month, datetime.format_localized(rest, locale), month_width = month_width)
+        }
+        TimeStyle::Iso => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+    }
+}
+
+/// Initial buffer size for the reentrant passwd/group lookups
+fn nss_buffer_size(name: libc::c_int) -> usize {
+    let size = unsafe { libc::sysconf(name) };
+    if size > 0 { size as usize } else { 1024 }
+}
+
+fn get_user_name(uid: u32) -> String {
+    let mut buf = vec![0 as libc::c_char; nss_buffer_size(libc::_SC_GETPW_R_SIZE_MAX)];
+    loop {
+        unsafe {
+            let mut pwd: libc::passwd = std::mem::zeroed();
+            let mut result: *mut libc::passwd = std::ptr::null_mut();
+            let rc = libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result);
+            if rc == libc::ERANGE {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if rc != 0 || result.is_null() {
+                return uid.to_string();
+            }
+            return std::ffi::CStr::from_ptr(pwd.pw_name)
+                .to_string_lossy()
+                .to_string();
+        }
+    }
+}
+
+fn get_group_name(gid: u32) -> String {
+    let mut buf = vec![0 as libc::c_char; nss_buffer_size(libc::_SC_GETGR_R_SIZE_MAX)];
+    loop {
+        unsafe {
+            let mut grp: libc::group = std::mem::zeroed();
+            let mut result: *mut libc::group = std::ptr::null_mut();
+            let rc = libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.len(), &mut result);
+            if rc == libc::ERANGE {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if rc != 0 || result.is_null() {
+                return gid.to_string();
+            }
+            return std::ffi::CStr::from_ptr(grp.gr_name)
+                .to_string_lossy()
+                .to_string();
+        }
+    }
+}
+
+fn get_user_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut buf = vec![0 as libc::c_char; nss_buffer_size(libc::_SC_GETPW_R_SIZE_MAX)];
+    loop {
+        unsafe {
+            let mut pwd: libc::passwd = std::mem::zeroed();
+            let mut result: *mut libc::passwd = std::ptr::null_mut();
+            let rc = libc::getpwnam_r(name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result);
+            if rc == libc::ERANGE {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            return (rc == 0 && !result.is_null()).then_some(pwd.pw_uid);
+        }
+    }
+}
+
+fn get_group_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut buf = vec![0 as libc::c_char; nss_buffer_size(libc::_SC_GETGR_R_SIZE_MAX)];
+    loop {
+        unsafe {
+            let mut grp: libc::group = std::mem::zeroed();
+            let mut result: *mut libc::group = std::ptr::null_mut();
+            let rc = libc::getgrnam_r(name.as_ptr(), &mut grp, buf.as_mut_ptr(), buf.len(), &mut result);
+            if rc == libc::ERANGE {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            return (rc == 0 && !result.is_null()).then_some(grp.gr_gid);
+        }
+    }
+}
+
+fn get_time_field(metadata: &FileStat, field: TimeField) -> i64 {
+    match field {
+        TimeField::Modify => metadata.mtime,
+        TimeField::Change => metadata.ctime,
+        TimeField::Access => metadata.atime,
+        TimeField::Birth => metadata.btime.unwrap_or(metadata.ctime), // Fallback to ctime if birth not available
+    }
+}
+
+fn is_tty() -> bool {
+    unsafe { libc::isatty(1) == 1 }
+}
+
+fn get_indicator(metadata: &FileStat, classify: bool) -> &'static str {
+    // -p marks only directories
+    match metadata.file_type() {
+        FileKind::Directory => "/",
+        _ if !classify => "",
+        FileKind::Symlink => "@",
+        FileKind::Socket => "=",
+        FileKind::Fifo => "|",
+        FileKind::Door => ">",
+        FileKind::Whiteout => "%",
+        FileKind::Regular if metadata.mode & 0o111 != 0 => "*",
+        FileKind::Regular | FileKind::BlockDevice | FileKind::CharDevice | FileKind::Unknown => "",
+    }
+}
+
+/// Determine the unit for block counts from the environment.
+///
+/// LS_BLOCK_SIZE and BLOCK_SIZE take precedence over BSD's BLOCKSIZE; without
+/// any of them POSIXLY_CORRECT selects 512-byte blocks and the default is 1024.
+fn resolve_block_size() -> u64 {
+    for var in ["LS_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE"] {
+        if let Some(size) = std::env::var(var).ok().and_then(|v| parse_block_size(&v)) {
+            return size;
+        }
+    }
+    if std::env::var_os("POSIXLY_CORRECT").is_some() { 512 } else { 1024 }
+}
+
+/// Parse a block size such as `512`, `1K`, `4KiB`, `1MB` or a bare unit like `K`
+fn parse_block_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let digits_end = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, suffix) = value.split_at(digits_end);
+    let count: u64 = if digits.is_empty() { 1 } else { digits.parse().ok()? };
+
+    let multiplier: u64 = match suffix {
+        "" | "B" => 1,
+        "K" | "k" | "KiB" => 1 << 10,
+        "M" | "MiB" => 1 << 20,
+        "G" | "GiB" => 1 << 30,
+        "T" | "TiB" => 1 << 40,
+        "kB" | "KB" => 1000,
+        "MB" => 1000 * 1000,
+        "GB" => 1000 * 1000 * 1000,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        _ => return None,
+    };
+
+    match count.checked_mul(multiplier) {
+        Some(0) | None => None,
+        size => size,
+    }
+}
+
+/// Number of `block_size` units allocated to a file, rounded up
+fn display_blocks(metadata: &FileStat, block_size: u64) -> u64 {
+    (metadata.blocks * 512).div_ceil(block_size)
+}
+
+/// Whether a non-directory has more than one link
+fn is_hardlinked(metadata: &FileStat) -> bool {
+    !metadata.is_dir() && metadata.nlink > 1
+}
+
+/// Number entries that share a (dev, inode) pair with another entry, in listing order
+fn assign_link_groups(entries: &mut [Entry]) {
+    use std::collections::HashMap;
+
+    let mut counts: HashMap<(u64, u64), usize> = HashMap::new();
+    for entry in entries.iter().filter(|e| is_hardlinked(&e.metadata)) {
+        *counts.entry((entry.metadata.dev, entry.metadata.ino)).or_insert(0) += 1;
+    }
+
+    let mut groups: HashMap<(u64, u64), usize> = HashMap::new();
+    for entry in entries.iter_mut() {
+        let key = (entry.metadata.dev, entry.metadata.ino);
+        if counts.get(&key).is_some_and(|&n| n > 1) {
+            let next = groups.len() + 1;
+            entry.link_group = Some(*groups.entry(key).or_insert(next));
+        }
+    }
+}
+
+/// Whether a regular file occupies fewer bytes on disk than its apparent length
+fn is_sparse(metadata: &FileStat) -> bool {
+    metadata.is_file() && metadata.blocks * 512 < metadata.size
+}
+
+/// Size column text for a non-device entry, honoring `--size` and marking
+/// sparse files with an `S` when showing allocated usage
+fn format_size_field(metadata: &FileStat, config: &Config) -> String {
+    let size_mode = if metadata.is_dir() && config.dirs_show == DirsShow::Blocks { SizeMode::Allocated } else { config.size_mode };
+    let bytes = match size_mode {
+        SizeMode::Apparent => metadata.size,
+        SizeMode::Allocated => metadata.blocks * 512,
+    };
+
+    let mut size = if config.human_readable {
+        format_size(bytes, true)
+    } else {
+        let (count, suffix) = match config.size_unit {
+            Some(ref unit) => (bytes.div_ceil(unit.bytes), unit.suffix.as_str()),
+            None => (bytes, ""),
+        };
+        let digits = count.to_string();
+        let mut size = if config.size_grouping { group_digits(&digits, &THOUSANDS_SEP) } else { digits };
+        size.push_str(suffix);
+        size
+    };
+
+    if size_mode == SizeMode::Allocated && is_sparse(metadata) {
+        size.push('S');
+    }
+    size
+}
+
+// Thousands separator of the user's LC_NUMERIC locale, as `init_locale` set
+// it; the C locale has none, so digits stay ungrouped there as in GNU ls
+static THOUSANDS_SEP: Lazy<String> = Lazy::new(|| unsafe {
+    let conv = libc::localeconv();
+    if conv.is_null() || (*conv).thousands_sep.is_null() {
+        String::new()
+    } else {
+        std::ffi::CStr::from_ptr((*conv).thousands_sep).to_string_lossy().to_string()
+    }
+});
+
+/// Insert `sep` between every group of three digits
+fn group_digits(digits: &str, sep: &str) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3 * sep.len());
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(sep);
+        }
+        out.push(ch);
+    }
+    out
+}
+
+fn format_size(size: u64, human_readable: bool) -> String {
+    if !human_readable {
+        return size.to_string();
+    }
+    
+    const UNITS: &[&str] = &["B", "K", "M", "G", "T", "P"];
+    if size == 0 {
+        return "0B".to_string();
+    }
+    
+    let mut size_f = size as f64;
+    let mut unit_idx = 0;
+    
+    while size_f >= 1024.0 && unit_idx < UNITS.len() - 1 {
+        size_f /= 1024.0;
+        unit_idx += 1;
+    }
+    
+    if unit_idx == 0 {
+        format!("{}{}", size, UNITS[unit_idx])
+    } else if size_f >= 10.0 {
+        format!("{:.0}{}", size_f, UNITS[unit_idx])
+    } else {
+        format!("{:.1}{}", size_f, UNITS[unit_idx])
+    }
+}
+
+/// Columns of the terminal stdout, stderr or, when both are redirected, the
+/// controlling terminal is showing
+fn terminal_size() -> Option<usize> {
+    use std::os::fd::AsRawFd;
+
+    let columns = |fd: libc::c_int| unsafe {
+        let mut winsize: libc::winsize = std::mem::zeroed();
+        (libc::ioctl(fd, libc::TIOCGWINSZ, &mut winsize) == 0 && winsize.ws_col > 0).then_some(winsize.ws_col as usize)
+    };
+    columns(1)
+        .or_else(|| columns(2))
+        .or_else(|| fs::File::open("/dev/tty").ok().and_then(|tty| columns(tty.as_raw_fd())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{MemoryFs, S_IFBLK, S_IFCHR, S_IFDIR, S_IFDOOR, S_IFIFO, S_IFREG, S_IFWHT};
+
+    fn config(flags: &[&str]) -> Config {
+        Config::from_args(&Args::parse_from(std::iter::once("ls").chain(flags.iter().copied())))
+    }
+
+    fn long_listing(fs: &MemoryFs, path: &str, flags: &[&str]) -> String {
+        let config = config(flags);
+        let mut entries = collect_entries(fs, Path::new(path), &config).unwrap();
+        sort_entries(&mut entries, &config);
+        let mut out = Vec::new();
+        print_long_format(&entries, &config, &mut out, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Counts the writes it receives
+    struct CountingWriter(Vec<u8>, usize);
+
+    impl std::io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1 += 1;
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_directory_is_written_in_one_piece() {
+        let mut fs = MemoryFs::new("/dev");
+        fs.insert("/dev/sda", FileStat { mode: S_IFBLK | 0o660, rdev: (8 << 24) | 1, nlink: 1, ..FileStat::default() });
+        fs.insert("/dev/tty", FileStat { mode: S_IFCHR | 0o666, rdev: (5 << 24), nlink: 1, ..FileStat::default() });
+        fs.insert("/dev/zero", FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+        let config = config(&["-l", "--no-lookup"]);
+        let mut out = CountingWriter(Vec::new(), 0);
+        list_directory(&fs, Path::new("/dev"), &config, &mut out).unwrap();
+        assert_eq!(out.1, 1);
+        // The total line and one per entry
+        assert_eq!(out.0.iter().filter(|&&b| b == b'\n').count(), 4);
+        assert_eq!(render_listing(&fs, Path::new("/dev"), &config).unwrap(), out.0);
+    }
+
+    #[test]
+    fn recursion_stays_in_pre_order_past_the_read_ahead_window() {
+        let mut fs = MemoryFs::new("/t");
+        let mut expected = Vec::new();
+        for i in 0..6 {
+            let dir = format!("/t/d{}", i);
+            fs.insert(&dir, FileStat { mode: S_IFDIR | 0o755, nlink: 3, ..FileStat::default() });
+            fs.insert(format!("{}/sub", dir), FileStat { mode: S_IFDIR | 0o755, nlink: 2, ..FileStat::default() });
+            fs.insert(format!("{}/sub/file", dir), FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+            expected.push(format!("{}:", dir));
+            expected.push(format!("{}/sub:", dir));
+        }
+        // One thread reads two directories ahead, fewer than there are siblings
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let listing = pool.install(|| render_listing(&fs, Path::new("/t"), &config(&["-R"])).unwrap());
+        let headers: Vec<&str> = std::str::from_utf8(&listing).unwrap().lines().filter(|line| line.ends_with(':')).collect();
+        assert_eq!(headers, expected);
+    }
+
+    #[test]
+    fn digits_are_grouped_in_threes_from_the_right() {
+        assert_eq!(group_digits("10000000", ","), "10,000,000");
+        assert_eq!(group_digits("1234", "\u{202f}"), "1\u{202f}234");
+        assert_eq!(group_digits("999", ","), "999");
+        // The C locale's separator is empty
+        assert_eq!(group_digits("1234", ""), "1234");
+    }
+
+    #[test]
+    fn uniform_owners_and_groups_are_named_once() {
+        let mut fs = MemoryFs::new("/home");
+        fs.insert("/home/mine", FileStat { mode: S_IFREG | 0o644, uid: 1000, gid: 100, nlink: 1, ..FileStat::default() });
+        fs.insert("/home/theirs", FileStat { mode: S_IFREG | 0o644, uid: 1001, gid: 100, nlink: 1, ..FileStat::default() });
+        let out = long_listing(&fs, "/home", &["-l", "--no-lookup", "--collapse-uniform-columns"]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "group 100");
+        assert!(lines[1].starts_with("-rw-r--r-- 1     1000 ") && lines[1].ends_with(" mine"), "{}", lines[1]);
+        assert!(!lines[1].contains(" 100 "));
+
+        fs.insert("/home/theirs", FileStat { mode: S_IFREG | 0o644, uid: 1000, gid: 100, nlink: 1, ..FileStat::default() });
+        let out = long_listing(&fs, "/home", &["-l", "--no-lookup", "--collapse-uniform-columns"]);
+        assert!(out.starts_with("owner 1000, group 100\n-rw-r--r-- 1 0 "), "{}", out);
+    }
+
+    #[test]
+    fn entries_not_owned_by_the_directory_owner_are_marked() {
+        let mut fs = MemoryFs::new("/");
+        fs.insert("/home", FileStat { mode: S_IFDIR | 0o755, uid: 1000, nlink: 2, ..FileStat::default() });
+        fs.insert("/home/mine", FileStat { mode: S_IFREG | 0o644, uid: 1000, nlink: 1, ..FileStat::default() });
+        fs.insert("/home/left-by-sudo", FileStat { mode: S_IFREG | 0o644, uid: 0, nlink: 1, ..FileStat::default() });
+        let listing = |flags: &[&str]| {
+            let mut out = Vec::new();
+            list_directory(&fs, Path::new("/home"), &config(flags), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(listing(&["-1", "--no-lookup", "--mark-foreign-owners"]), "left-by-sudo [owner:0]\nmine\n");
+        assert_eq!(listing(&["-1", "--no-lookup"]), "left-by-sudo\nmine\n");
+    }
+
+    #[test]
+    fn perm_filters_match_exact_all_or_any_bits() {
+        let mut fs = MemoryFs::new("/d");
+        for (name, mode) in [("private", 0o600), ("shared", 0o666), ("script", 0o755), ("tmp", 0o1777)] {
+            fs.insert(format!("/d/{}", name), FileStat { mode: S_IFREG | mode, nlink: 1, ..FileStat::default() });
+        }
+        let listing = |perm: &str| {
+            let mut out = Vec::new();
+            list_directory(&fs, Path::new("/d"), &config(&["-1", "--perm", perm]), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(listing("755"), "script\n");
+        assert_eq!(listing("-0002"), "shared\ntmp\n");
+        assert_eq!(listing("-1000"), "tmp\n");
+        assert_eq!(listing("/111"), "script\ntmp\n");
+        assert_eq!(listing("/000"), "private\nscript\nshared\ntmp\n");
+    }
+
+    #[test]
+    fn classify_marks_each_kind_and_leaves_unknown_types_bare() {
+        let mut fs = MemoryFs::new("/d");
+        for (name, mode) in [("dir", S_IFDIR | 0o755), ("exe", S_IFREG | 0o755), ("fifo", S_IFIFO | 0o644), ("odd", 0o644), ("tty", S_IFCHR | 0o755)] {
+            fs.insert(format!("/d/{}", name), FileStat { mode, nlink: 1, ..FileStat::default() });
+        }
+        fs.insert("/d/door", FileStat { mode: S_IFDOOR | 0o644, nlink: 1, ..FileStat::default() });
+        fs.insert("/d/gone", FileStat { mode: S_IFWHT, nlink: 1, ..FileStat::default() });
+        let mut out = Vec::new();
+        list_directory(&fs, Path::new("/d"), &config(&["-1F"]), &mut out).unwrap();
+        let door = if FileKind::from_mode(S_IFDOOR) == FileKind::Door { "door>" } else { "door" };
+        let gone = if FileKind::from_mode(S_IFWHT) == FileKind::Whiteout { "gone%" } else { "gone" };
+        // Devices aren't executables, whatever their mode says
+        assert_eq!(String::from_utf8(out).unwrap(), format!("dir/\n{}\nexe*\nfifo|\n{}\nodd\ntty\n", door, gone));
+
+        assert_eq!(FileKind::from_mode(0o644), FileKind::Unknown);
+        assert!(long_listing(&fs, "/d", &["-l", "--no-lookup"]).lines().any(|line| line.starts_with("?rw-r--r-- ") && line.ends_with(" odd")));
+    }
+}
@@ -0,0 +1,83 @@
+//! Library hooks into how entries are chosen and ordered. Programs embedding
+//! the listing via `run_with_pipeline` can drop entries with their own
+//! `EntryFilter`s and order them with an `EntryComparator`, on top of the
+//! collection, formatting and recursion ls already does.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Entry;
+
+/// Decides whether an entry read from a directory is listed. Filters run after
+/// the built-in ones (-a, --ignore-glob, --regex and so on), on entries that
+/// have been stat'ed; operands named on the command line are always listed
+pub trait EntryFilter: Send + Sync + fmt::Debug {
+    fn keep(&self, entry: &Entry) -> bool;
+}
+
+/// Orders entries in place of the --sort key. Ties still fall back to the
+/// name, -r still reverses the order and -U still leaves entries unsorted
+pub trait EntryComparator: Send + Sync + fmt::Debug {
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering;
+}
+
+/// The extra filters and comparator of one run
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    /// An entry is listed only when every filter keeps it
+    pub filters: Vec<Arc<dyn EntryFilter>>,
+    pub comparator: Option<Arc<dyn EntryComparator>>,
+}
+
+impl Pipeline {
+    pub(crate) fn keeps(&self, entry: &Entry) -> bool {
+        self.filters.iter().all(|filter| filter.keep(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::filesystem::{FileStat, MemoryFs, S_IFREG};
+    use crate::{collect_entries, sort_entries, Args, Config};
+    use clap::Parser;
+
+    #[derive(Debug)]
+    struct NoLogs;
+
+    impl EntryFilter for NoLogs {
+        fn keep(&self, entry: &Entry) -> bool {
+            entry.path().extension().is_none_or(|extension| extension != "log")
+        }
+    }
+
+    #[derive(Debug)]
+    struct ShortestName;
+
+    impl EntryComparator for ShortestName {
+        fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+            a.name().len().cmp(&b.name().len())
+        }
+    }
+
+    fn names(flags: &[&str]) -> Vec<String> {
+        let mut fs = MemoryFs::new("/d");
+        for name in ["ccc", "a.log", "bb", "dddd"] {
+            fs.insert(format!("/d/{}", name), FileStat { mode: S_IFREG | 0o644, nlink: 1, ..FileStat::default() });
+        }
+        let mut config = Config::from_args(&Args::parse_from(std::iter::once("ls").chain(flags.iter().copied())));
+        config.pipeline = Pipeline { filters: vec![Arc::new(NoLogs)], comparator: Some(Arc::new(ShortestName)) };
+        let mut entries = collect_entries(&fs, Path::new("/d"), &config).unwrap();
+        sort_entries(&mut entries, &config);
+        entries.iter().map(|entry| entry.name().to_string()).collect()
+    }
+
+    #[test]
+    fn filters_drop_entries_and_the_comparator_orders_the_rest() {
+        assert_eq!(names(&[]), ["bb", "ccc", "dddd"]);
+        assert_eq!(names(&["-r"]), ["dddd", "ccc", "bb"]);
+    }
+}
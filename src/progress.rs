@@ -0,0 +1,114 @@
+//! Stderr progress indicator for listings that are slow to produce output
+//! (NFS, cold caches). It only appears once stdout has been quiet for a
+//! while and is erased before anything else reaches the terminal.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const FRAMES: &[char] = &['|', '/', '-', '\\'];
+const TICK: Duration = Duration::from_millis(100);
+
+static SCANNED: AtomicUsize = AtomicUsize::new(0);
+static STATE: Mutex<State> = Mutex::new(State { drawn: false, last_output: None });
+
+/// Indicator bookkeeping; holding the lock keeps the indicator off screen
+pub struct State {
+    drawn: bool,
+    last_output: Option<Instant>,
+}
+
+/// Count entries as they are stat'ed so the indicator has something to report
+pub fn record_scanned(count: usize) {
+    SCANNED.fetch_add(count, Ordering::Relaxed);
+}
+
+/// Entries stat'ed so far in this process
+pub fn scanned() -> usize {
+    SCANNED.load(Ordering::Relaxed)
+}
+
+/// Erase the indicator and keep it hidden while the returned guard is held
+pub fn suspend() -> MutexGuard<'static, State> {
+    let mut state = STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if state.drawn {
+        let _ = io::stderr().write_all(b"\r\x1b[K");
+        state.drawn = false;
+    }
+    state.last_output = Some(Instant::now());
+    state
+}
+
+/// Background thread drawing the indicator; stopping it erases the line
+pub struct Spinner {
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    /// Start the indicator, shown once output has stalled for `delay`
+    pub fn start(delay: Duration) -> Spinner {
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&done);
+        let started = Instant::now();
+
+        let handle = thread::spawn(move || {
+            let mut frame = 0;
+            while !stop.load(Ordering::Relaxed) {
+                thread::park_timeout(TICK);
+                let mut state = STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                if stop.load(Ordering::Relaxed) || state.last_output.unwrap_or(started).elapsed() < delay {
+                    continue;
+                }
+                // Push out any partial line first so the indicator never lands inside it
+                let _ = io::stdout().flush();
+                let line = format!("\r\x1b[K{} scanned {} entries", FRAMES[frame % FRAMES.len()], SCANNED.load(Ordering::Relaxed));
+                let _ = io::stderr().write_all(line.as_bytes());
+                state.drawn = true;
+                frame += 1;
+            }
+        });
+
+        Spinner { done, handle: Some(handle) }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            // Wake the thread so a fast listing doesn't wait out a whole tick
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+        drop(suspend());
+    }
+}
+
+/// Writer that clears the indicator before passing output through
+pub struct ClearingWriter<W>(pub W);
+
+impl<W: Write> Write for ClearingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _quiet = suspend();
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_writer_passes_output_through() {
+        let mut out = ClearingWriter(Vec::new());
+        write!(out, "a\nb\n").unwrap();
+        assert_eq!(out.0, b"a\nb\n");
+    }
+}
@@ -0,0 +1,268 @@
+//! Settings chosen by one of several contradictory flags. The flags of each
+//! group override one another in `Args`, so like GNU ls the last one given is
+//! the only one still set here, whatever order the checks below run in.
+
+use crate::error::{self, ErrorFormat, LsError};
+use crate::{is_tty, terminal_size, Args, ColorMode, FollowSymlinks, OutputFormat, RecordFormat, SortBy, TimeField};
+
+/// --color=WHEN or -G, else what the environment asks for
+pub(crate) fn color(args: &Args, errors: ErrorFormat) -> ColorMode {
+    match args.color_when.as_deref() {
+        Some("always") => ColorMode::Always,
+        Some("never") => ColorMode::Never,
+        Some("auto") => ColorMode::Auto,
+        Some(other) => error::fatal(&LsError::InvalidArgument {
+            option: "--color",
+            value: other.to_string(),
+            expected: "auto, always or never",
+        }, errors),
+        None if args.color_flag => ColorMode::Always,
+        None => env_color(|var| std::env::var(var).ok()),
+    }
+}
+
+/// The no-color.org and CLICOLOR conventions: NO_COLOR disables, CLICOLOR_FORCE
+/// colors even a pipe, and CLICOLOR=0 keeps a terminal plain
+fn env_color(var: impl Fn(&str) -> Option<String>) -> ColorMode {
+    let set = |name: &str| var(name).filter(|value| !value.is_empty());
+    if set("NO_COLOR").is_some() {
+        ColorMode::Never
+    } else if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+        ColorMode::Always
+    } else if set("CLICOLOR").as_deref() == Some("0") {
+        ColorMode::Never
+    } else {
+        ColorMode::Auto
+    }
+}
+
+/// -t or -S; -f implies -a as well, so it stays out of the group and always wins
+pub(crate) fn sort(args: &Args) -> SortBy {
+    if args.no_sort {
+        SortBy::Unsorted
+    } else if args.sort_time {
+        SortBy::Time
+    } else if args.sort_size {
+        SortBy::Size
+    } else {
+        SortBy::Name
+    }
+}
+
+/// -L, -P or -H; without any of them links are never followed, as with -P
+pub(crate) fn follow_symlinks(args: &Args) -> FollowSymlinks {
+    if args.follow_symlinks {
+        FollowSymlinks::Always
+    } else if args.follow_cli_symlinks {
+        FollowSymlinks::CommandLine
+    } else {
+        FollowSymlinks::Never
+    }
+}
+
+/// The screen width grids lay out for: -w, else the terminal's, else COLUMNS,
+/// else 80. The terminal is asked even when stdout is a pipe, so `ls -C | less`
+/// still fits the window it is read in
+pub(crate) fn width(args: &Args) -> usize {
+    screen_width(args.width, terminal_size, |var| std::env::var(var).ok())
+}
+
+fn screen_width(requested: Option<usize>, terminal: impl Fn() -> Option<usize>, var: impl Fn(&str) -> Option<String>) -> usize {
+    requested
+        .filter(|&w| w > 0)
+        .or_else(terminal)
+        .or_else(|| var("COLUMNS").and_then(|c| c.parse().ok()).filter(|&w| w > 0))
+        .unwrap_or(80)
+}
+
+/// -c, -u or -U
+pub(crate) fn time_field(args: &Args) -> TimeField {
+    if args.ctime {
+        TimeField::Change
+    } else if args.atime {
+        TimeField::Access
+    } else if args.birthtime {
+        TimeField::Birth
+    } else {
+        TimeField::Modify
+    }
+}
+
+/// A --format word. The flag is in the group of the format flags and of the
+/// record formats, so whichever of them comes last is the only one set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FormatWord {
+    Across,
+    Commas,
+    Long,
+    SingleColumn,
+    Vertical,
+    Cbor,
+}
+
+/// --format, with GNU's words for the display formats
+pub(crate) fn format_word(args: &Args, errors: ErrorFormat) -> Option<FormatWord> {
+    let word = args.format.as_deref()?;
+    Some(match word {
+        "across" | "horizontal" => FormatWord::Across,
+        "commas" => FormatWord::Commas,
+        "long" | "verbose" => FormatWord::Long,
+        "single-column" => FormatWord::SingleColumn,
+        "vertical" => FormatWord::Vertical,
+        "cbor" => FormatWord::Cbor,
+        other => error::fatal(&LsError::InvalidArgument {
+            option: "--format",
+            value: other.to_string(),
+            expected: "across, commas, horizontal, long, single-column, verbose, vertical or cbor",
+        }, errors),
+    })
+}
+
+/// -1, -C, -x, -m, --long-grid, --compact, --stat or their --format words; -l is
+/// in the same group and prints its own way. Only the unforced default depends
+/// on stdout being a tty
+pub(crate) fn output_format(args: &Args, word: Option<FormatWord>) -> OutputFormat {
+    if word == Some(FormatWord::Across) {
+        OutputFormat::MultiColumnAcross
+    } else if word == Some(FormatWord::Commas) {
+        OutputFormat::Stream
+    } else if word == Some(FormatWord::SingleColumn) {
+        OutputFormat::SingleColumn
+    } else if word == Some(FormatWord::Vertical) {
+        OutputFormat::MultiColumnDown
+    } else if args.stat {
+        OutputFormat::Stat
+    } else if args.compact {
+        OutputFormat::Compact
+    } else if args.long_grid {
+        OutputFormat::LongGrid
+    } else if args.one {
+        OutputFormat::SingleColumn
+    } else if args.stream_format {
+        OutputFormat::Stream
+    } else if args.multi_column_across {
+        OutputFormat::MultiColumnAcross
+    } else if args.multi_column_down || is_tty() {
+        OutputFormat::MultiColumnDown
+    } else {
+        OutputFormat::SingleColumn
+    }
+}
+
+/// --porcelain, --yaml, --json or --format=cbor
+pub(crate) fn records(args: &Args, word: Option<FormatWord>, errors: ErrorFormat) -> Option<RecordFormat> {
+    if let Some(version) = &args.porcelain {
+        match version.as_str() {
+            "1" => Some(RecordFormat::Porcelain(1)),
+            "2" => Some(RecordFormat::Porcelain(2)),
+            other => error::fatal(&LsError::InvalidArgument {
+                option: "--porcelain",
+                value: other.to_string(),
+                expected: "1 or 2",
+            }, errors),
+        }
+    } else if args.yaml {
+        Some(RecordFormat::Yaml)
+    } else if args.json {
+        Some(RecordFormat::Json)
+    } else if word == Some(FormatWord::Cbor) {
+        Some(RecordFormat::Cbor)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Args, ColorMode, Config, FollowSymlinks, OutputFormat, RecordFormat, SortBy, TimeField};
+    use clap::Parser;
+
+    fn config(argv: &[&str]) -> Config {
+        Config::from_args(&Args::parse_from(std::iter::once("ls").chain(argv.iter().copied())))
+    }
+
+    #[test]
+    fn the_last_format_flag_wins() {
+        let columns = config(&["-lC"]);
+        assert!(!columns.long);
+        assert_eq!(columns.format, OutputFormat::MultiColumnDown);
+        assert!(config(&["-Cl"]).long);
+        assert!(config(&["-1", "-l"]).long);
+        assert_eq!(config(&["-l", "-1"]).format, OutputFormat::SingleColumn);
+        assert_eq!(config(&["-1", "-x"]).format, OutputFormat::MultiColumnAcross);
+        assert_eq!(config(&["-x", "-m"]).format, OutputFormat::Stream);
+        assert_eq!(config(&["-m", "--long-grid", "-C"]).format, OutputFormat::MultiColumnDown);
+        assert_eq!(config(&["-l", "--compact"]).format, OutputFormat::Compact);
+        assert!(config(&["--compact", "-l"]).long);
+        assert_eq!(config(&["-l", "--stat"]).format, OutputFormat::Stat);
+        assert!(!config(&["-l", "--stat"]).long);
+        assert_eq!(config(&["-l", "--format=commas"]).format, OutputFormat::Stream);
+        assert!(!config(&["-l", "--format=commas"]).long);
+        assert!(config(&["-1", "--format=verbose"]).long);
+        assert_eq!(config(&["--format=across", "-1"]).format, OutputFormat::SingleColumn);
+        assert_eq!(config(&["--yaml", "--format=cbor"]).records, Some(RecordFormat::Cbor));
+        assert_eq!(config(&["--format=cbor", "--porcelain"]).records, Some(RecordFormat::Porcelain(1)));
+        assert_eq!(config(&["--format=cbor", "--json"]).records, Some(RecordFormat::Json));
+    }
+
+    #[test]
+    fn all_and_almost_all_override_each_other() {
+        let almost = config(&["-a", "-A"]);
+        assert!(almost.almost_all && !almost.all);
+        let all = config(&["-A", "-a"]);
+        assert!(all.all && !all.almost_all);
+    }
+
+    #[test]
+    fn the_last_color_flag_wins() {
+        assert!(matches!(config(&["--color=never", "-G"]).color, ColorMode::Always));
+        assert!(matches!(config(&["-G", "--color=never"]).color, ColorMode::Never));
+        assert!(matches!(config(&["-G", "--color=auto"]).color, ColorMode::Auto));
+    }
+
+    #[test]
+    fn color_environment_follows_the_clicolor_conventions() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            super::env_color(move |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()))
+        };
+        assert!(matches!(env(&[]), ColorMode::Auto));
+        assert!(matches!(env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]), ColorMode::Never));
+        assert!(matches!(env(&[("NO_COLOR", ""), ("CLICOLOR_FORCE", "1")]), ColorMode::Always));
+        assert!(matches!(env(&[("CLICOLOR_FORCE", "0")]), ColorMode::Auto));
+        assert!(matches!(env(&[("CLICOLOR", "0")]), ColorMode::Never));
+        assert!(matches!(env(&[("CLICOLOR", "1")]), ColorMode::Auto));
+    }
+
+    #[test]
+    fn width_comes_from_the_flag_then_the_terminal_then_columns() {
+        let width = |requested, terminal: Option<usize>, columns: Option<&'static str>| {
+            super::screen_width(requested, || terminal, |_| columns.map(String::from))
+        };
+        assert_eq!(width(Some(40), Some(120), Some("60")), 40);
+        assert_eq!(width(None, Some(120), Some("60")), 120);
+        assert_eq!(width(None, None, Some("60")), 60);
+        assert_eq!(width(None, None, Some("wide")), 80);
+        assert_eq!(width(None, None, None), 80);
+    }
+
+    #[test]
+    fn the_last_sort_time_and_symlink_flags_win() {
+        assert!(matches!(config(&["-tS"]).sort, SortBy::Size));
+        assert!(matches!(config(&["-St"]).sort, SortBy::Time));
+        // -f disables sorting whatever comes after it
+        assert!(matches!(config(&["-f", "-t"]).sort, SortBy::Unsorted));
+        assert!(matches!(config(&["-cu"]).time_field, TimeField::Access));
+        assert!(matches!(config(&["-uc"]).time_field, TimeField::Change));
+        assert!(matches!(config(&["-LP"]).follow_symlinks, FollowSymlinks::Never));
+        assert!(matches!(config(&["-PH"]).follow_symlinks, FollowSymlinks::CommandLine));
+        assert!(matches!(config(&["-HL"]).follow_symlinks, FollowSymlinks::Always));
+    }
+
+    #[test]
+    fn classify_and_slash_override_each_other() {
+        let slash = config(&["-F", "-p"]);
+        assert!(slash.slash && !slash.classify);
+        let classify = config(&["-p", "-F"]);
+        assert!(classify.classify && !classify.slash);
+    }
+}
@@ -0,0 +1,183 @@
+//! s3://bucket/prefix operands. Keys are split on `/` so common prefixes act
+//! as directories, which gives -R prefix recursion for free.
+//!
+//! Credentials come from the usual AWS environment and profile files. Set
+//! AWS_ENDPOINT_URL to reach MinIO or another S3-compatible store.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dashmap::DashMap;
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+
+use crate::backend::Backend;
+use crate::error::LsError;
+use crate::filesystem::{DirEntry, FileStat, Filesystem, XattrSummary, S_IFDIR, S_IFREG};
+
+const SCHEME: &str = "s3://";
+
+/// The bucket named by an s3:// operand, or None when it is a local path
+fn bucket_name(operand: &Path) -> Option<&str> {
+    let rest = operand.to_str()?.strip_prefix(SCHEME)?;
+    let bucket = rest.split('/').next()?;
+    (!bucket.is_empty()).then_some(bucket)
+}
+
+/// Operands of the form s3://bucket/prefix
+pub struct S3Backend;
+
+impl Backend for S3Backend {
+    fn accepts(&self, operand: &Path) -> bool {
+        bucket_name(operand).is_some()
+    }
+
+    fn open(&self, operand: &Path) -> Result<Box<dyn Filesystem>, LsError> {
+        let bucket = bucket_name(operand).expect("accepted operands name a bucket");
+        match S3Fs::connect(bucket) {
+            Ok(fs) => Ok(Box::new(fs)),
+            Err(source) => Err(LsError::CannotAccess { path: operand.to_path_buf(), source }),
+        }
+    }
+}
+
+/// One bucket, listed with ListObjectsV2 and a `/` delimiter
+pub struct S3Fs {
+    bucket: Box<Bucket>,
+    prefix: String,
+    owner: (u32, u32),
+    // Attributes learned from listings, so entries don't each cost a request
+    stats: DashMap<PathBuf, FileStat>,
+}
+
+impl S3Fs {
+    fn connect(bucket: &str) -> io::Result<S3Fs> {
+        let region_name = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = std::env::var("AWS_ENDPOINT_URL").ok().filter(|e| !e.is_empty());
+        let region = match &endpoint {
+            Some(endpoint) => Region::Custom { region: region_name, endpoint: endpoint.clone() },
+            None => region_name.parse().map_err(io::Error::other)?,
+        };
+        // Public buckets can still be listed without credentials
+        let credentials = Credentials::default()
+            .or_else(|_| Credentials::anonymous())
+            .map_err(io::Error::other)?;
+
+        let mut handle = Bucket::new(bucket, region, credentials).map_err(io::Error::other)?;
+        if endpoint.is_some() {
+            // Self-hosted stores rarely have per-bucket DNS names
+            handle = handle.with_path_style();
+        }
+
+        // Objects carry no owner, so show them as the caller's
+        let owner = unsafe { (libc::getuid(), libc::getgid()) };
+        Ok(S3Fs { bucket: handle, prefix: format!("{}{}", SCHEME, bucket), owner, stats: DashMap::new() })
+    }
+
+    /// The key behind a listing path such as `s3://bucket/logs/2024`
+    fn key(&self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        path.strip_prefix(&self.prefix).unwrap_or(&path).trim_matches('/').to_string()
+    }
+
+    fn directory_stat(&self) -> FileStat {
+        FileStat { mode: S_IFDIR | 0o755, nlink: 2, uid: self.owner.0, gid: self.owner.1, ..FileStat::default() }
+    }
+
+    fn object_stat(&self, object: &s3::serde_types::Object) -> FileStat {
+        let mtime = chrono::DateTime::parse_from_rfc3339(&object.last_modified).map_or(0, |t| t.timestamp());
+        FileStat {
+            mode: S_IFREG | 0o644,
+            size: object.size,
+            uid: self.owner.0,
+            gid: self.owner.1,
+            nlink: 1,
+            blocks: object.size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            ..FileStat::default()
+        }
+    }
+
+    /// Objects and common prefixes directly below `prefix`
+    fn list(&self, prefix: String) -> io::Result<Vec<s3::serde_types::ListBucketResult>> {
+        self.bucket.list(prefix, Some("/".to_string())).map_err(io::Error::other)
+    }
+}
+
+impl Filesystem for S3Fs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let key = self.key(path);
+        let prefix = if key.is_empty() { key } else { format!("{}/", key) };
+
+        let mut entries = Vec::new();
+        for page in self.list(prefix.clone())? {
+            for object in &page.contents {
+                let name = &object.key[prefix.len().min(object.key.len())..];
+                // The placeholder object some tools create for an "empty folder"
+                if name.is_empty() {
+                    continue;
+                }
+                let path = path.join(name);
+                self.stats.insert(path.clone(), self.object_stat(object));
+                entries.push(DirEntry { name: name.to_string(), path });
+            }
+            for common in page.common_prefixes.iter().flatten() {
+                let name = common.prefix[prefix.len().min(common.prefix.len())..].trim_end_matches('/');
+                let path = path.join(name);
+                self.stats.insert(path.clone(), self.directory_stat());
+                entries.push(DirEntry { name: name.to_string(), path });
+            }
+        }
+        Ok(entries)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileStat> {
+        if let Some(stat) = self.stats.get(path) {
+            return Ok(stat.clone());
+        }
+        let key = self.key(path);
+        if key.is_empty() {
+            return Ok(self.directory_stat());
+        }
+
+        // One listing tells apart an object, a prefix, or nothing at all
+        let pages = self.list(key.clone())?;
+        if let Some(object) = pages.iter().flat_map(|p| &p.contents).find(|o| o.key == key) {
+            return Ok(self.object_stat(object));
+        }
+        let dir_prefix = format!("{}/", key);
+        if pages.iter().flat_map(|p| p.common_prefixes.iter().flatten()).any(|c| c.prefix == dir_prefix) {
+            return Ok(self.directory_stat());
+        }
+        Err(io::Error::from_raw_os_error(libc::ENOENT))
+    }
+
+    fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::from_raw_os_error(libc::EINVAL))
+    }
+
+    fn file_flags(&self, _path: &Path) -> io::Result<u32> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn xattr_summary(&self, _path: &Path) -> XattrSummary {
+        XattrSummary::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_s3_operands() {
+        assert_eq!(bucket_name(Path::new("s3://logs/2024/01")), Some("logs"));
+        assert_eq!(bucket_name(Path::new("s3://logs")), Some("logs"));
+        assert_eq!(bucket_name(Path::new("s3:///x")), None);
+        assert_eq!(bucket_name(Path::new("logs/s3://x")), None);
+    }
+}
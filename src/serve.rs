@@ -0,0 +1,150 @@
+//! --serve SOCKET: answer listing requests over a Unix socket from one
+//! long-running process, so the owner and group name caches and, with
+//! --cached, directory listings stay warm from one request to the next.
+//!
+//! Requests are answered one at a time. Every message is made of frames: a
+//! 4-byte big-endian length followed by that many bytes.
+//!
+//! - request: one frame with the ls arguments, without the program name, each
+//!   followed by a NUL byte. Relative operands resolve against the server's
+//!   working directory, so clients should send absolute paths.
+//! - response: a frame with the listing, a frame with the error reports, and
+//!   one byte with the exit status.
+//!
+//! The socket is only open to the server's own user. Requests still may not
+//! use the options that run commands or write files (--column-cmd,
+//! --snapshot), read operands from the server's stdin or files
+//! (--files-from), or start another server.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::Parser;
+
+use crate::backend::Backend;
+use crate::columns::Column;
+use crate::pipeline::Pipeline;
+use crate::{error, Args};
+
+// Arguments are small; anything bigger is not a request
+const MAX_REQUEST: u32 = 1 << 20;
+
+pub fn serve(socket: &Path, extra: Vec<Box<dyn Backend>>, columns: Vec<Arc<dyn Column>>, pipeline: Pipeline) -> io::Result<()> {
+    // A socket left behind by an earlier server would make bind fail
+    if fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(socket)?;
+    }
+    // Created as 0600, so no one but the server's user can ever connect; a chmod
+    // after bind would leave a moment when anyone could
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+    // The extra backends live as long as the server, and every request borrows them
+    let extra: &'static [Box<dyn Backend>] = Vec::leak(extra);
+
+    // A client that hangs up or sends garbage only loses its own answer
+    for mut stream in listener.incoming().flatten() {
+        let _ = answer(&mut stream, extra, &columns, &pipeline);
+    }
+    Ok(())
+}
+
+fn answer(stream: &mut UnixStream, extra: &'static [Box<dyn Backend>], columns: &[Arc<dyn Column>], pipeline: &Pipeline) -> io::Result<()> {
+    let request = read_frame(stream)?;
+    let argv = std::iter::once(OsString::from("ls")).chain(split_args(&request).into_iter().map(OsStr::to_owned));
+
+    let mut listing = Vec::new();
+    crate::reset_name_lookups();
+    error::begin_capture();
+    let (status, message) = match Args::try_parse_from(argv) {
+        Ok(args) => match refused_option(&args) {
+            Some(option) => (2, format!("ls: {} cannot be used in a request\n", option)),
+            None => {
+                let backends = extra.iter().map(|backend| Box::new(backend.as_ref()) as Box<dyn Backend>).collect();
+                let run = panic::catch_unwind(AssertUnwindSafe(|| crate::run_with_output(args, backends, columns.to_vec(), pipeline.clone(), Some(&mut listing))));
+                // A fatal error has already been reported; any other panic was printed by the hook
+                (run.unwrap_or(2), String::new())
+            }
+        },
+        Err(e) if e.use_stderr() => (e.exit_code(), e.render().to_string()),
+        Err(e) => {
+            // --help and --version answer on stdout
+            listing.extend_from_slice(e.render().to_string().as_bytes());
+            (e.exit_code(), String::new())
+        }
+    };
+    let mut errors = error::end_capture();
+    errors.extend_from_slice(message.as_bytes());
+
+    write_frame(stream, &listing)?;
+    write_frame(stream, &errors)?;
+    stream.write_all(&[status as u8])?;
+    stream.flush()
+}
+
+/// An option a request may not use: --serve, or one that would run commands,
+/// write files or read operands as the server's user
+fn refused_option(args: &Args) -> Option<&'static str> {
+    if args.serve.is_some() {
+        Some("--serve")
+    } else if !args.column_cmd.is_empty() {
+        Some("--column-cmd")
+    } else if args.snapshot.is_some() {
+        Some("--snapshot")
+    } else if args.files_from.is_some() {
+        // Would read the server's stdin, or any file its user can
+        Some("--files-from")
+    } else {
+        None
+    }
+}
+
+/// The NUL-terminated arguments of a request
+fn split_args(request: &[u8]) -> Vec<&OsStr> {
+    if request.is_empty() {
+        return Vec::new();
+    }
+    // Tolerate a missing terminator after the last argument
+    let request = request.strip_suffix(b"\0").unwrap_or(request);
+    request.split(|&b| b == 0).map(OsStr::from_bytes).collect()
+}
+
+fn read_frame(stream: &mut UnixStream) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_REQUEST {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+    }
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+fn write_frame(stream: &mut UnixStream, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "response too large"))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_nul_terminated() {
+        let args = |request: &[u8]| split_args(request).into_iter().map(|a| a.as_bytes().to_vec()).collect::<Vec<_>>();
+        assert_eq!(args(b"-l\0/tmp\0"), [b"-l".to_vec(), b"/tmp".to_vec()]);
+        assert_eq!(args(b""), Vec::<Vec<u8>>::new());
+        // An empty argument is still an argument
+        assert_eq!(args(b"\0"), [Vec::<u8>::new()]);
+    }
+}
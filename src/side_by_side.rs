@@ -0,0 +1,70 @@
+//! --side-by-side A B...: directory listings in adjacent columns, one row per
+//! name in a single shared order, so a name one side lacks shows up as a gap
+//! in its column. Each column gets an equal share of the width, and names too
+//! wide for it are shortened in the middle. Only the operands themselves are
+//! listed; -R does not descend.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::diff::Side;
+use crate::error::LsError;
+use crate::{collect_entries, compare_names, decorate_name, display_name, name_width, truncate_middle, use_color, Config, Entry};
+
+pub fn list_side_by_side(sides: &[Side], config: &Config, out: &mut dyn Write) -> Result<(), LsError> {
+    let color = use_color(config);
+    let listings = sides.iter().map(|side| collect_entries(side.fs, side.path, config)).collect::<Result<Vec<_>, _>>()?;
+    let by_name: Vec<HashMap<&str, &Entry>> =
+        listings.iter().map(|entries| entries.iter().map(|e| (e.name.as_str(), e)).collect()).collect();
+
+    let mut names: Vec<&str> = listings.iter().flatten().map(|e| e.name.as_str()).collect();
+    names.sort_by(|x, y| compare_names(x, y, config.sort_case).then_with(|| x.cmp(y)));
+    names.dedup();
+    if config.reverse {
+        names.reverse();
+    }
+
+    let headers: Vec<String> = sides.iter().map(|side| side.path.display().to_string()).collect();
+    let widest = headers
+        .iter()
+        .map(|header| name_width(header.as_bytes()))
+        .chain(listings.iter().flatten().map(|e| name_width(&display_name(e, config, false))))
+        .max()
+        .unwrap_or(0);
+    let gap = name_width(config.grid_gutter.as_bytes());
+    let share = config.width.saturating_sub(gap * (sides.len() - 1)) / sides.len();
+    let cell_width = widest.min(share).max(1);
+
+    let header_cells: Vec<Vec<u8>> = headers.iter().map(|header| truncate_middle(header, cell_width).into_bytes()).collect();
+    write_row(out, &header_cells, cell_width, config)?;
+    for name in names {
+        let cells: Vec<Vec<u8>> = by_name
+            .iter()
+            .map(|entries| entries.get(name).map(|e| cell(e, cell_width, config, color)).unwrap_or_default())
+            .collect();
+        write_row(out, &cells, cell_width, config)?;
+    }
+    Ok(())
+}
+
+/// An entry's decorated name, shortened so the whole of it fits `width` columns
+fn cell(entry: &Entry, width: usize, config: &Config, color: bool) -> Vec<u8> {
+    let decorations = name_width(&display_name(entry, config, false)) - name_width(entry.name.as_bytes());
+    if name_width(entry.name.as_bytes()) + decorations <= width {
+        return display_name(entry, config, color);
+    }
+    let name = truncate_middle(&entry.name, width.saturating_sub(decorations).max(1));
+    decorate_name(name.into_bytes(), entry, config, color)
+}
+
+fn write_row(out: &mut dyn Write, cells: &[Vec<u8>], width: usize, config: &Config) -> io::Result<()> {
+    // Trailing empty cells leave nothing to pad
+    let used = cells.iter().rposition(|cell| !cell.is_empty()).map_or(0, |last| last + 1);
+    for (i, cell) in cells[..used].iter().enumerate() {
+        out.write_all(cell)?;
+        if i + 1 < used {
+            write!(out, "{:pad$}{}", "", config.grid_gutter, pad = width.saturating_sub(name_width(cell)))?;
+        }
+    }
+    writeln!(out)
+}
@@ -0,0 +1,88 @@
+//! --snapshot FILE records a listing as JSON; --since-snapshot FILE loads it
+//! back as an in-memory tree and reports drift with the --diff machinery.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{diff_directories, Side};
+use crate::error::LsError;
+use crate::filesystem::{FileStat, Filesystem, MemoryFs};
+use crate::{collect_entries, Config};
+
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    /// Entries in pre-order, paths relative to the listed directory
+    entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    path: PathBuf,
+    stat: FileStat,
+    target: Option<PathBuf>,
+}
+
+/// Record the entries of `dir` (and below it with -R) in `file`
+pub fn write_snapshot(fs: &dyn Filesystem, dir: &Path, config: &Config, file: &Path) -> Result<(), LsError> {
+    let mut entries = Vec::new();
+    record(fs, dir, Path::new(""), config, &mut entries)?;
+
+    let snapshot_error = |source| LsError::Snapshot { path: file.to_path_buf(), source };
+    let mut out = BufWriter::new(File::create(file).map_err(snapshot_error)?);
+    serde_json::to_writer(&mut out, &Snapshot { version: VERSION, entries })
+        .map_err(io::Error::from)
+        .and_then(|()| out.flush())
+        .map_err(snapshot_error)
+}
+
+fn record(fs: &dyn Filesystem, dir: &Path, relative: &Path, config: &Config, out: &mut Vec<SnapshotEntry>) -> Result<(), LsError> {
+    let mut entries = collect_entries(fs, dir, config)?;
+    entries.retain(|e| e.name != "." && e.name != "..");
+    // Directory order is arbitrary; keep snapshots of the same tree byte-identical
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for entry in entries {
+        let path = relative.join(&entry.name);
+        let is_dir = entry.metadata.is_dir();
+        out.push(SnapshotEntry { path: path.clone(), stat: entry.metadata, target: entry.symlink_target });
+        if config.recursive
+            && is_dir
+            && let Err(e) = record(fs, &entry.path, &path, config, out)
+        {
+            crate::error::report(&e, config.errors, false);
+        }
+    }
+    Ok(())
+}
+
+/// Compare the live `dir` against the snapshot in `file`
+pub fn compare_with_snapshot(fs: &dyn Filesystem, dir: &Path, config: &Config, file: &Path, out: &mut dyn Write) -> Result<(), LsError> {
+    let snapshot_error = |source| LsError::Snapshot { path: file.to_path_buf(), source };
+    let reader = BufReader::new(File::open(file).map_err(snapshot_error)?);
+    let snapshot: Snapshot = serde_json::from_reader(reader).map_err(|e| snapshot_error(e.into()))?;
+    if snapshot.version != VERSION {
+        let message = format!("unsupported snapshot version {}", snapshot.version);
+        return Err(snapshot_error(io::Error::new(io::ErrorKind::InvalidData, message)));
+    }
+
+    let mut recorded = MemoryFs::new(dir);
+    for entry in snapshot.entries {
+        let path = dir.join(&entry.path);
+        // Skip entries whose parent went missing from a hand-edited snapshot
+        if !path.parent().is_some_and(|parent| recorded.symlink_metadata(parent).is_ok_and(|m| m.is_dir())) {
+            continue;
+        }
+        match entry.target {
+            Some(target) => recorded.insert_symlink(path, target, entry.stat),
+            None => recorded.insert(path, entry.stat),
+        }
+    }
+
+    diff_directories(Side { fs: &recorded, path: dir }, Side { fs, path: dir }, config, out)
+}
@@ -0,0 +1,277 @@
+//! --low-memory: a directory with more entries than the threshold is read and
+//! stat'ed that many entries at a time. Each chunk is sorted and written to an
+//! unlinked temporary file as a CBOR sequence, and the chunks are then merged
+//! back, so memory holds one chunk while reading and one entry per chunk
+//! while writing.
+//!
+//! A spilled listing can't lay out columns or give hard links groups, both of
+//! which need every entry at once: it prints one entry per line (or in the
+//! long or record format asked for) and ignores --show-hardlinks. Directories
+//! under the threshold are listed as usual. --detect-case-collisions still
+//! covers the whole directory by counting the lowercased names as chunks are
+//! read, which keeps every name in memory.
+//!
+//! -R writes each directory as it is listed instead of reading subdirectories
+//! ahead, and --unordered, --recurse-order and --header-stats, which hold whole
+//! listings until they can be written, are refused.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Seek, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LsError;
+use crate::filesystem::{DirEntry, FileStat, Filesystem, LinkStatus, XattrSummary};
+use crate::{build_entries, compare_entries, descend, listed_dir, print_entries, render_entries, sort_entries, write_total, DirSummary, ListedDir, Rendered};
+use crate::stats::{self, Phase};
+use crate::{error, Config, DisplayCache, Entry, LongWidths, OutputFormat, SortBy};
+
+// Entries handed to the printer at a time while merging
+const PRINT_BATCH: usize = 1024;
+
+/// What an entry needs to be rebuilt after a round trip through disk. Paths
+/// are stored as bytes since they need not be UTF-8
+#[derive(Serialize, Deserialize)]
+struct SpilledEntry {
+    name: String,
+    path: Vec<u8>,
+    metadata: FileStat,
+    symlink_target: Option<Vec<u8>>,
+    target_metadata: Option<FileStat>,
+    file_flags: Option<u32>,
+    xattrs: XattrSummary,
+    link_status: Option<LinkStatus>,
+    other_device: bool,
+    foreign_owner: bool,
+    child_count: Option<usize>,
+}
+
+impl SpilledEntry {
+    fn new(entry: Entry) -> SpilledEntry {
+        SpilledEntry {
+            name: entry.name,
+            path: entry.path.into_os_string().into_vec(),
+            metadata: entry.metadata,
+            symlink_target: entry.symlink_target.map(|target| target.into_os_string().into_vec()),
+            target_metadata: entry.target_metadata,
+            file_flags: entry.file_flags,
+            xattrs: entry.xattrs,
+            link_status: entry.link_status,
+            other_device: entry.other_device,
+            foreign_owner: entry.foreign_owner,
+            child_count: entry.child_count,
+        }
+    }
+
+    fn into_entry(self, config: &Config) -> Entry {
+        let path = PathBuf::from(OsStr::from_bytes(&self.path));
+        let raw_name = path.file_name().filter(|raw| raw.to_str().is_none()).map(|raw| raw.to_owned());
+        let display = DisplayCache::new(&self.name, &self.metadata, self.child_count, config);
+        Entry {
+            name: self.name,
+            path,
+            metadata: self.metadata,
+            symlink_target: self.symlink_target.map(|target| PathBuf::from(OsStr::from_bytes(&target))),
+            target_metadata: self.target_metadata,
+            link_group: None,
+            file_flags: self.file_flags,
+            xattrs: self.xattrs,
+            link_status: self.link_status,
+            extra_columns: Vec::new(),
+            other_device: self.other_device,
+            case_collision: false,
+            foreign_owner: self.foreign_owner,
+            child_count: self.child_count,
+            display,
+            raw_name,
+        }
+    }
+}
+
+/// One sorted chunk of the directory
+enum Run {
+    /// Entries on disk, with how many are left to read
+    Spilled(BufReader<File>, usize),
+    /// The last chunk, which never needs to leave memory
+    Memory(std::vec::IntoIter<Entry>),
+}
+
+impl Run {
+    fn next(&mut self, config: &Config) -> io::Result<Option<Entry>> {
+        match self {
+            Run::Spilled(_, 0) => Ok(None),
+            Run::Spilled(reader, left) => {
+                *left -= 1;
+                let spilled: SpilledEntry = ciborium::from_reader(reader).map_err(|e| match e {
+                    ciborium::de::Error::Io(e) => e,
+                    other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+                })?;
+                Ok(Some(spilled.into_entry(config)))
+            }
+            Run::Memory(entries) => Ok(entries.next()),
+        }
+    }
+}
+
+/// Write sorted `entries` to a temporary file that is gone once closed
+fn spill(entries: Vec<Entry>) -> io::Result<Run> {
+    static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+    let count = entries.len();
+    let name = format!("ls-rs-{}-{}.spill", std::process::id(), SEQUENCE.fetch_add(1, AtomicOrdering::Relaxed));
+    let path = std::env::temp_dir().join(name);
+    let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+    fs::remove_file(&path)?;
+
+    let mut writer = BufWriter::new(file);
+    for entry in entries {
+        ciborium::into_writer(&SpilledEntry::new(entry), &mut writer).map_err(|e| match e {
+            ciborium::ser::Error::Io(e) => e,
+            ciborium::ser::Error::Value(message) => io::Error::other(message),
+        })?;
+    }
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.rewind()?;
+    Ok(Run::Spilled(BufReader::new(file), count))
+}
+
+/// The next entry of a run while merging; the heap pops the smallest first,
+/// and the earlier run on ties so equal entries keep their order. Unsorted
+/// listings merge by run alone: the heap holds one head per run, so that
+/// empties the runs one after another, in the order they were read
+struct Head<'a> {
+    entry: Entry,
+    run: usize,
+    config: &'a Config,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = match self.config.sort {
+            SortBy::Unsorted => Ordering::Equal,
+            _ => compare_entries(&self.entry, &other.entry, self.config),
+        };
+        key.then(self.run.cmp(&other.run)).reverse()
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+/// `render_directory` for --low-memory
+pub(crate) fn render_directory(fs: &dyn Filesystem, path: &Path, config: &Config, threshold: usize, out: &mut dyn Write) -> Result<Rendered, LsError> {
+    let mut runs = Vec::new();
+    let mut widths = LongWidths::default();
+    let mut total_bytes = 0;
+    let mut summary = DirSummary::default();
+    let mut pending: Vec<DirEntry> = Vec::new();
+    let mut failed: Option<io::Error> = None;
+    // --detect-case-collisions: how many names fold to each lowercase name
+    let mut folded: HashMap<String, usize> = HashMap::new();
+    let listed = if ListedDir::wanted(config) { fs.metadata(path).map(|dir| listed_dir(&dir, config)).unwrap_or_default() } else { ListedDir::default() };
+
+    let finish_chunk = |chunk: Vec<DirEntry>, widths: &mut LongWidths, total_bytes: &mut u64| {
+        let mut entries = stats::time(Phase::Stat, || build_entries(fs, chunk, listed, config));
+        sort_entries(&mut entries, config);
+        *widths = widths.max(LongWidths::of(&entries, config));
+        *total_bytes += entries.iter().map(|e| e.metadata.blocks * 512).sum::<u64>();
+        entries
+    };
+    let mut finish_chunk = |chunk, widths: &mut LongWidths, total_bytes: &mut u64| {
+        let entries = finish_chunk(chunk, widths, total_bytes);
+        summary.entries += entries.len();
+        summary.bytes += entries.iter().map(|e| e.metadata.size).sum::<u64>();
+        if config.detect_case_collisions {
+            for entry in &entries {
+                *folded.entry(entry.name.to_lowercase()).or_default() += 1;
+            }
+        }
+        entries
+    };
+    fs.read_dir_each(path, &mut |entry| {
+        // A full chunk goes to disk only once there is more to come
+        if pending.len() == threshold && failed.is_none() {
+            let entries = finish_chunk(std::mem::take(&mut pending), &mut widths, &mut total_bytes);
+            match spill(entries) {
+                Ok(run) => runs.push(run),
+                Err(e) => failed = Some(e),
+            }
+        }
+        pending.push(entry);
+    })
+    .map_err(|source| LsError::CannotOpenDir { path: path.to_path_buf(), source })?;
+    if let Some(e) = failed {
+        return Err(LsError::Write(e));
+    }
+
+    // Small enough after all: list it the usual way
+    if runs.is_empty() {
+        return render_entries(fs, path, stats::time(Phase::Stat, || build_entries(fs, pending, listed, config)), config, out);
+    }
+    let last = finish_chunk(pending, &mut widths, &mut total_bytes);
+    runs.push(Run::Memory(last.into_iter()));
+
+    let config = &Config {
+        format: OutputFormat::SingleColumn,
+        long_widths: widths.max(config.long_widths),
+        show_hardlinks: false,
+        ..config.clone()
+    };
+    if (config.long || config.blocks) && config.records.is_none() {
+        write_total(total_bytes, config, out)?;
+    }
+
+    let mut heap = BinaryHeap::new();
+    for (run, source) in runs.iter_mut().enumerate() {
+        if let Some(entry) = source.next(config)? {
+            heap.push(Head { entry, run, config });
+        }
+    }
+    let shown = config.limit.map_or(0..summary.entries, |limit| limit.range(summary.entries));
+    let mut children = Vec::new();
+    let mut batch = Vec::with_capacity(PRINT_BATCH);
+    let mut position = 0;
+    let mut collisions = 0;
+    while let Some(Head { mut entry, run, .. }) = heap.pop() {
+        if folded.get(&entry.name.to_lowercase()).is_some_and(|&count| count > 1) {
+            entry.case_collision = true;
+            collisions += 1;
+        }
+        batch.push(entry);
+        if let Some(entry) = runs[run].next(config)? {
+            heap.push(Head { entry, run, config });
+        }
+        if batch.len() == PRINT_BATCH || heap.is_empty() {
+            // Like the in-memory listing, -R descends past --head and --tail
+            if config.recursive {
+                children.extend(descend(fs, path, &batch, config));
+            }
+            let first = position;
+            position += batch.len();
+            let mut printed: Vec<Entry> = (first..).zip(batch.drain(..)).filter(|(i, _)| shown.contains(i)).map(|(_, entry)| entry).collect();
+            crate::columns::fill(&mut printed, config);
+            print_entries(&printed, config, out)?;
+        }
+    }
+    if collisions > 0 {
+        error::warn(&LsError::CaseCollisions { path: path.to_path_buf(), count: collisions }, config.errors);
+    }
+    Ok(Rendered { children, summary })
+}
@@ -0,0 +1,104 @@
+//! --stat: each entry as the block `stat(1)` prints for it, built from the
+//! metadata already collected for the listing. Times keep their nanoseconds
+//! and the zone offset; the mount point is found by walking up from the entry
+//! until the device changes, so it is only shown for local files.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::{FileKind, FileStat};
+use crate::{display_name, format_mode, get_group_name_cached, get_user_name_cached, Config, Entry};
+
+pub fn print_stat(entries: &[Entry], config: &Config, out: &mut dyn Write, use_color: bool) -> io::Result<()> {
+    for entry in entries {
+        let stat = &entry.metadata;
+        out.write_all(b"  File: ")?;
+        out.write_all(&display_name(entry, config, use_color))?;
+        if let Some(target) = &entry.symlink_target {
+            write!(out, " -> {}", target.display())?;
+        }
+        writeln!(out)?;
+        writeln!(out, "  Size: {:<10}\tBlocks: {:<10} IO Block: {:<6} {}", stat.size, stat.blocks, stat.blksize, file_type(stat))?;
+        write!(out, "Device: {}\tInode: {:<11} Links: {:<5}", device(stat.dev), stat.ino, stat.nlink)?;
+        if stat.file_type().is_device() {
+            write!(out, " Device type: {}", device(stat.rdev))?;
+        }
+        writeln!(out)?;
+        writeln!(
+            out,
+            "Access: ({:04o}/{})  Uid: ({:>5}/{:>8})   Gid: ({:>5}/{:>8})",
+            stat.mode & 0o7777,
+            format_mode(stat.mode),
+            stat.uid,
+            get_user_name_cached(stat.uid, &config.user_lookup),
+            stat.gid,
+            get_group_name_cached(stat.gid, &config.group_lookup)
+        )?;
+        writeln!(out, "Access: {}", precise_time(stat.atime, stat.atime_nsec, config))?;
+        writeln!(out, "Modify: {}", precise_time(stat.mtime, stat.mtime_nsec, config))?;
+        writeln!(out, "Change: {}", precise_time(stat.ctime, stat.ctime_nsec, config))?;
+        match stat.btime {
+            Some(btime) => writeln!(out, " Birth: {}", precise_time(btime, stat.btime_nsec, config))?,
+            None => writeln!(out, " Birth: -")?,
+        }
+        if let Some(mount) = mount_point(&entry.path, stat) {
+            writeln!(out, " Mount: {}", mount.display())?;
+        }
+    }
+    Ok(())
+}
+
+/// The file type in stat(1)'s words
+fn file_type(stat: &FileStat) -> &'static str {
+    match stat.file_type() {
+        FileKind::Regular if stat.size == 0 => "regular empty file",
+        FileKind::Regular => "regular file",
+        FileKind::Directory => "directory",
+        FileKind::Symlink => "symbolic link",
+        FileKind::Fifo => "fifo",
+        FileKind::Socket => "socket",
+        FileKind::BlockDevice => "block special file",
+        FileKind::CharDevice => "character special file",
+        FileKind::Door => "door",
+        FileKind::Whiteout => "whiteout",
+        FileKind::Unknown => "weird file",
+    }
+}
+
+/// A device number as major,minor
+fn device(dev: u64) -> String {
+    format!("{},{}", libc::major(dev), libc::minor(dev))
+}
+
+/// `2024-03-10 12:00:00.123456789 +0000` in the zone times are shown in
+fn precise_time(secs: i64, nsec: u32, config: &Config) -> String {
+    match config.time_zone.datetime(secs, nsec) {
+        Some(datetime) => datetime.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string(),
+        None => secs.to_string(),
+    }
+}
+
+/// The top of the filesystem `path` is on: its furthest ancestor still on the
+/// same device. None when `path` isn't the local file `stat` describes
+fn mount_point(path: &Path, stat: &FileStat) -> Option<PathBuf> {
+    let on_device = |path: &Path| fs::symlink_metadata(path).is_ok_and(|m| m.dev() == stat.dev);
+    let path = if stat.is_dir() {
+        fs::canonicalize(path).ok()?
+    } else {
+        let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        fs::canonicalize(parent).ok()?.join(path.file_name()?)
+    };
+    if !on_device(&path) && !fs::metadata(&path).is_ok_and(|m| m.dev() == stat.dev) {
+        return None;
+    }
+    let mut mount = path.as_path();
+    for ancestor in path.ancestors().skip(1) {
+        if !on_device(ancestor) {
+            break;
+        }
+        mount = ancestor;
+    }
+    Some(mount.to_path_buf())
+}
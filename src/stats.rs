@@ -0,0 +1,101 @@
+//! --stats: a report on stderr of how many entries were read and where the
+//! time went, for tuning --threads and --cached on slow filesystems. Phase
+//! times are summed over the threads that ran them, so with -R in parallel
+//! they can add up to more than the wall-clock total.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::progress;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static READ: AtomicU64 = AtomicU64::new(0);
+static STAT: AtomicU64 = AtomicU64::new(0);
+static SORT: AtomicU64 = AtomicU64::new(0);
+static FORMAT: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// A part of the pipeline that gets its own line in the report
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    /// Reading directory names
+    Read,
+    /// Stat calls and the other per-entry lookups
+    Stat,
+    Sort,
+    /// Laying out and writing the listing
+    Format,
+}
+
+impl Phase {
+    fn nanos(self) -> &'static AtomicU64 {
+        match self {
+            Phase::Read => &READ,
+            Phase::Stat => &STAT,
+            Phase::Sort => &SORT,
+            Phase::Format => &FORMAT,
+        }
+    }
+}
+
+/// Run `f`, adding the time it takes to `phase` while a report is being collected
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    phase.nanos().fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+/// Count a --cached lookup as served from the cache or not
+pub fn record_cache(hit: bool) {
+    let counter = if hit { &CACHE_HITS } else { &CACHE_MISSES };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Collects the statistics of one run and writes them to stderr when dropped
+pub struct Report {
+    started: Instant,
+    // The scanned count is shared with the progress indicator and never reset
+    scanned_before: usize,
+}
+
+impl Report {
+    pub fn start() -> Report {
+        for counter in [&READ, &STAT, &SORT, &FORMAT, &CACHE_HITS, &CACHE_MISSES] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        ENABLED.store(true, Ordering::Relaxed);
+        Report { started: Instant::now(), scanned_before: progress::scanned() }
+    }
+
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let millis = |duration: Duration| format!("{:.3} ms", duration.as_secs_f64() * 1000.0);
+        let phase = |phase: Phase| millis(Duration::from_nanos(phase.nanos().load(Ordering::Relaxed)));
+
+        writeln!(out, "entries scanned  {}", progress::scanned() - self.scanned_before)?;
+        writeln!(out, "read             {}", phase(Phase::Read))?;
+        writeln!(out, "stat             {}", phase(Phase::Stat))?;
+        writeln!(out, "sort             {}", phase(Phase::Sort))?;
+        writeln!(out, "format           {}", phase(Phase::Format))?;
+        writeln!(out, "total            {}", millis(self.started.elapsed()))?;
+        let (hits, misses) = (CACHE_HITS.load(Ordering::Relaxed), CACHE_MISSES.load(Ordering::Relaxed));
+        if hits + misses > 0 {
+            let rate = hits as f64 * 100.0 / (hits + misses) as f64;
+            writeln!(out, "cache            {} hits, {} misses ({:.0}% hit rate)", hits, misses, rate)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Report {
+    fn drop(&mut self) {
+        ENABLED.store(false, Ordering::Relaxed);
+        let _quiet = progress::suspend();
+        let _ = self.write(&mut io::stderr().lock());
+    }
+}
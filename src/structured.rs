@@ -0,0 +1,164 @@
+//! Entries as serde records for structured output (--yaml, --json,
+//! --format=cbor). Each
+//! record carries its whole path, so the records of every listed directory
+//! concatenate into one sequence.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::filesystem::{FileKind, FileStat, LinkStatus};
+use crate::{format_mode, get_group_name_cached, get_user_name_cached, Config, Entry};
+
+/// One entry as written by the structured output formats
+#[derive(Serialize)]
+pub(crate) struct EntryStat {
+    name: String,
+    path: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Permission bits in octal, setuid/setgid/sticky included, e.g. "0644"
+    mode: String,
+    /// The whole st_mode, type bits included
+    raw_mode: u32,
+    permissions: String,
+    nlink: u64,
+    uid: u32,
+    user: String,
+    gid: u32,
+    group: String,
+    size: u64,
+    blocks: u64,
+    blksize: u64,
+    inode: u64,
+    /// Device the entry lives on, and the device a block or character device is
+    dev: u64,
+    rdev: u64,
+    /// Seconds since the epoch, with the nanoseconds past them alongside
+    atime: i64,
+    atime_nsec: u32,
+    mtime: i64,
+    mtime_nsec: u32,
+    ctime: i64,
+    ctime_nsec: u32,
+    /// Null where the filesystem doesn't record a birth time
+    btime: Option<i64>,
+    btime_nsec: Option<u32>,
+    /// The same times as RFC 3339 in the --time-zone zone, null for times the
+    /// zone can't express
+    atime_iso: Option<String>,
+    mtime_iso: Option<String>,
+    ctime_iso: Option<String>,
+    btime_iso: Option<String>,
+    /// BSD file flags, only read with -O
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    /// With --check-links, whether a symlink resolves ("ok"), names nothing
+    /// ("dangling") or loops ("loop"), and how many links a resolving one took
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_hops: Option<usize>,
+}
+
+impl EntryStat {
+    pub(crate) fn new(entry: &Entry, config: &Config) -> EntryStat {
+        let stat = &entry.metadata;
+        EntryStat {
+            name: entry.name.clone(),
+            path: entry.path.to_string_lossy().into_owned(),
+            kind: kind(stat),
+            mode: format!("{:04o}", stat.mode & 0o7777),
+            raw_mode: stat.mode,
+            permissions: format_mode(stat.mode),
+            nlink: stat.nlink,
+            uid: stat.uid,
+            user: get_user_name_cached(stat.uid, &config.user_lookup).to_string(),
+            gid: stat.gid,
+            group: get_group_name_cached(stat.gid, &config.group_lookup).to_string(),
+            size: stat.size,
+            blocks: stat.blocks,
+            blksize: stat.blksize,
+            inode: stat.ino,
+            dev: stat.dev,
+            rdev: stat.rdev,
+            atime: stat.atime,
+            atime_nsec: stat.atime_nsec,
+            mtime: stat.mtime,
+            mtime_nsec: stat.mtime_nsec,
+            ctime: stat.ctime,
+            ctime_nsec: stat.ctime_nsec,
+            btime: stat.btime,
+            btime_nsec: stat.btime.map(|_| stat.btime_nsec),
+            atime_iso: iso_time(stat.atime, stat.atime_nsec, config),
+            mtime_iso: iso_time(stat.mtime, stat.mtime_nsec, config),
+            ctime_iso: iso_time(stat.ctime, stat.ctime_nsec, config),
+            btime_iso: stat.btime.and_then(|btime| iso_time(btime, stat.btime_nsec, config)),
+            flags: entry.file_flags,
+            target: entry.symlink_target.as_ref().map(|target| target.to_string_lossy().into_owned()),
+            link: entry.link_status.map(|status| match status {
+                LinkStatus::Resolves { .. } => "ok",
+                LinkStatus::Dangling => "dangling",
+                LinkStatus::Loop => "loop",
+            }),
+            link_hops: match entry.link_status {
+                Some(LinkStatus::Resolves { hops }) => Some(hops),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn iso_time(secs: i64, nsec: u32, config: &Config) -> Option<String> {
+    let datetime = config.time_zone.datetime(secs, nsec)?;
+    Some(datetime.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, false))
+}
+
+fn kind(stat: &FileStat) -> &'static str {
+    match stat.file_type() {
+        FileKind::Regular => "file",
+        FileKind::Directory => "directory",
+        FileKind::Symlink => "symlink",
+        FileKind::Fifo => "fifo",
+        FileKind::Socket => "socket",
+        FileKind::BlockDevice => "block_device",
+        FileKind::CharDevice => "char_device",
+        FileKind::Door => "door",
+        FileKind::Whiteout => "whiteout",
+        FileKind::Unknown => "unknown",
+    }
+}
+
+/// --yaml: the entries as items of a block sequence
+pub fn print_yaml(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    // An empty sequence would be written as `[]`, which can't be continued by the next directory
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let records: Vec<EntryStat> = entries.iter().map(|entry| EntryStat::new(entry, config)).collect();
+    serde_yaml::to_writer(out, &records).map_err(io::Error::other)
+}
+
+/// --json: one object per line, which keeps a listing streamable and lets the
+/// lines of every directory simply follow each other
+pub fn print_json(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    for entry in entries {
+        serde_json::to_writer(&mut *out, &EntryStat::new(entry, config))?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// --format=cbor: one self-delimiting CBOR map per entry, so readers can decode
+/// records as they arrive instead of waiting for the listing to finish
+pub fn print_cbor(entries: &[Entry], config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    for entry in entries {
+        ciborium::into_writer(&EntryStat::new(entry, config), &mut *out).map_err(|e| match e {
+            ciborium::ser::Error::Io(e) => e,
+            ciborium::ser::Error::Value(message) => io::Error::other(message),
+        })?;
+    }
+    Ok(())
+}
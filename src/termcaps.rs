@@ -0,0 +1,159 @@
+//! What the terminal can show, so styles degrade to what it understands
+//! instead of assuming the best. Worked out once per run from the
+//! environment: COLORTERM announces truecolor, the terminfo entry for TERM
+//! gives the size of the palette (a `-256color` name stands in when there is
+//! no entry), and the variables well-known terminals set tell whether they
+//! follow OSC 8 hyperlinks.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// How many colors SGR sequences can pick from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// The 8 standard colors
+    Basic,
+    /// The xterm 256-color palette
+    Ansi256,
+    /// Any 24-bit color
+    Truecolor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermCaps {
+    pub colors: ColorDepth,
+    /// Whether OSC 8 hyperlinks are followed rather than printed as junk
+    pub hyperlinks: bool,
+}
+
+impl TermCaps {
+    pub fn detect() -> TermCaps {
+        TermCaps::from_env(|name| std::env::var(name).ok().filter(|value| !value.is_empty()), terminfo_colors)
+    }
+
+    /// The capabilities `var` describes, reading the palette size of a TERM
+    /// through `colors`
+    fn from_env(var: impl Fn(&str) -> Option<String>, colors: impl Fn(&str) -> Option<i32>) -> TermCaps {
+        let term = var("TERM").unwrap_or_default();
+        let colors = if var("COLORTERM").is_some_and(|v| v == "truecolor" || v == "24bit") {
+            ColorDepth::Truecolor
+        } else {
+            match colors(&term) {
+                // Direct-color entries such as xterm-direct
+                Some(n) if n >= 1 << 24 => ColorDepth::Truecolor,
+                Some(n) if n >= 256 => ColorDepth::Ansi256,
+                Some(_) => ColorDepth::Basic,
+                None if term.contains("256color") => ColorDepth::Ansi256,
+                None => ColorDepth::Basic,
+            }
+        };
+        let hyperlinks = var("TERM_PROGRAM").is_some_and(|p| matches!(p.as_str(), "iTerm.app" | "WezTerm" | "vscode" | "ghostty"))
+            || var("VTE_VERSION").and_then(|v| v.parse::<u32>().ok()).is_some_and(|v| v >= 5000)
+            || var("KITTY_WINDOW_ID").is_some()
+            || var("WT_SESSION").is_some()
+            || term.starts_with("xterm-kitty")
+            || term.starts_with("foot")
+            || term.starts_with("alacritty");
+        TermCaps { colors, hyperlinks }
+    }
+}
+
+impl fmt::Display for TermCaps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colors = match self.colors {
+            ColorDepth::Basic => "8 colors",
+            ColorDepth::Ansi256 => "256 colors",
+            ColorDepth::Truecolor => "truecolor",
+        };
+        write!(f, "{}, {}", colors, if self.hyperlinks { "hyperlinks" } else { "no hyperlinks" })
+    }
+}
+
+// Index of max_colors among terminfo's numeric capabilities
+const MAX_COLORS: usize = 13;
+
+/// The `colors` capability of the compiled terminfo entry for `term`, looked
+/// up where ncurses looks; None when there is no entry or it doesn't say
+fn terminfo_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+    if term.contains('/') {
+        return None;
+    }
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    dirs.extend(std::env::var_os("TERMINFO").map(PathBuf::from));
+    dirs.extend(std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".terminfo")));
+    if let Some(list) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"].map(PathBuf::from));
+    dirs.iter()
+        // Linux files entries by their first letter, macOS by its hex code
+        .flat_map(|dir| [dir.join(first.to_string()).join(term), dir.join(format!("{:x}", first as u32)).join(term)])
+        .find_map(|path| fs::read(path).ok())
+        .and_then(|entry| parse_colors(&entry))
+}
+
+/// `colors` from a compiled terminfo entry, in the legacy format with 16-bit
+/// numbers or the extended one with 32-bit numbers
+fn parse_colors(entry: &[u8]) -> Option<i32> {
+    let short = |i: usize| entry.get(i * 2..i * 2 + 2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names, bools, numbers) = (short(1)? as usize, short(2)? as usize, short(3)? as usize);
+    if numbers <= MAX_COLORS {
+        return None;
+    }
+    let mut start = 12 + names + bools;
+    // Numbers start on an even byte
+    start += start % 2;
+    let at = start + MAX_COLORS * number_size;
+    let bytes = entry.get(at..at + number_size)?;
+    let colors = if number_size == 2 { i16::from_le_bytes([bytes[0], bytes[1]]) as i32 } else { i32::from_le_bytes(bytes.try_into().ok()?) };
+    (colors >= 0).then_some(colors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(vars: &[(&str, &str)], colors: Option<i32>) -> TermCaps {
+        let vars: Vec<(String, String)> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        TermCaps::from_env(|name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone()), |_| colors)
+    }
+
+    #[test]
+    fn depth_comes_from_colorterm_then_terminfo_then_the_name() {
+        assert_eq!(caps(&[("COLORTERM", "truecolor"), ("TERM", "xterm")], Some(8)).colors, ColorDepth::Truecolor);
+        assert_eq!(caps(&[("TERM", "xterm-256color")], Some(256)).colors, ColorDepth::Ansi256);
+        assert_eq!(caps(&[("TERM", "xterm-direct")], Some(1 << 24)).colors, ColorDepth::Truecolor);
+        assert_eq!(caps(&[("TERM", "screen-256color")], None).colors, ColorDepth::Ansi256);
+        assert_eq!(caps(&[("TERM", "vt100")], Some(8)).colors, ColorDepth::Basic);
+        assert_eq!(caps(&[], None), TermCaps { colors: ColorDepth::Basic, hyperlinks: false });
+        assert!(caps(&[("TERM", "xterm-kitty")], None).hyperlinks);
+        assert!(caps(&[("VTE_VERSION", "6800")], None).hyperlinks);
+        assert!(!caps(&[("VTE_VERSION", "4000")], None).hyperlinks);
+    }
+
+    #[test]
+    fn colors_are_read_from_both_terminfo_formats() {
+        // Header, the name "t|test" padded to even, no booleans, 14 numbers with colors last
+        let entry = |magic: i16, number_size: usize, colors: i32| {
+            let mut entry = Vec::new();
+            for short in [magic, 7, 0, 14, 0, 0] {
+                entry.extend_from_slice(&short.to_le_bytes());
+            }
+            entry.extend_from_slice(b"t|test\0\0");
+            entry.extend(std::iter::repeat_n(0xff, MAX_COLORS * number_size));
+            entry.extend_from_slice(&colors.to_le_bytes()[..number_size]);
+            entry
+        };
+        assert_eq!(parse_colors(&entry(0o432, 2, 256)), Some(256));
+        assert_eq!(parse_colors(&entry(0o1036, 4, 1 << 24)), Some(1 << 24));
+        assert_eq!(parse_colors(&entry(0o432, 2, -1)), None);
+        assert_eq!(parse_colors(b"not terminfo"), None);
+    }
+}
@@ -0,0 +1,334 @@
+//! Color theme: SGR styles per file type plus extension classes, built in
+//! with GNU-like defaults and overridable through LS_COLORS.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::filesystem::{FileKind, FileStat};
+use crate::termcaps::ColorDepth;
+
+/// Extension classes colored out of the box, with their default style
+const EXTENSION_CLASSES: &[(&str, &str, &[&str])] = &[
+    ("archive", "01;31", &[
+        "7z", "ace", "alz", "arc", "arj", "bz", "bz2", "cab", "cpio", "deb", "dmg", "dz", "ear", "gz",
+        "jar", "lha", "lrz", "lz", "lz4", "lzh", "lzma", "lzo", "rar", "rpm", "rz", "sar", "t7z", "tar",
+        "taz", "tbz", "tbz2", "tgz", "tlz", "txz", "tz", "tzo", "tzst", "war", "wim", "xz", "z", "zip",
+        "zoo", "zst",
+    ]),
+    ("image", "00;35", &[
+        "avif", "bmp", "gif", "heic", "ico", "jpeg", "jpg", "mng", "pbm", "pcx", "pgm", "png", "ppm",
+        "svg", "svgz", "tga", "tif", "tiff", "webp", "xbm", "xcf", "xpm", "xwd",
+    ]),
+    ("media", "01;35", &[
+        "aac", "au", "avi", "flac", "flv", "m2v", "m4a", "m4v", "mid", "midi", "mka", "mkv", "mov",
+        "mp3", "mp4", "mpc", "mpeg", "mpg", "oga", "ogg", "ogm", "ogv", "opus", "qt", "ra", "rm", "rmvb",
+        "spx", "vob", "wav", "webm", "wmv",
+    ]),
+    ("document", "01;33", &[
+        "doc", "docx", "epub", "odp", "ods", "odt", "pdf", "ppt", "pptx", "ps", "rtf", "xls", "xlsx",
+    ]),
+];
+
+/// SGR parameters (e.g. `01;34`) for each kind of entry
+#[derive(Debug, Clone)]
+pub struct Theme {
+    directory: Option<String>,
+    symlink: Option<String>,
+    executable: Option<String>,
+    sparse: Option<String>,
+    fifo: Option<String>,
+    socket: Option<String>,
+    block_device: Option<String>,
+    char_device: Option<String>,
+    extensions: HashMap<String, String>,
+}
+
+impl Theme {
+    /// Defaults used when LS_COLORS says nothing about a class
+    pub fn builtin() -> Theme {
+        let extensions = EXTENSION_CLASSES
+            .iter()
+            .flat_map(|&(_, sgr, exts)| exts.iter().map(move |ext| (ext.to_string(), sgr.to_string())))
+            .collect();
+        Theme {
+            directory: Some("34".to_string()),
+            symlink: Some("36".to_string()),
+            executable: Some("32".to_string()),
+            sparse: Some("33".to_string()),
+            fifo: None,
+            socket: None,
+            block_device: None,
+            char_device: None,
+            extensions,
+        }
+    }
+
+    /// Built-in theme with LS_COLORS layered on top
+    pub fn from_env() -> Theme {
+        let mut theme = Theme::builtin();
+        if let Ok(spec) = std::env::var("LS_COLORS") {
+            theme.apply_ls_colors(&spec);
+        }
+        theme
+    }
+
+    /// Apply a dircolors-style `key=sgr:*.ext=sgr` spec; unknown keys are ignored
+    fn apply_ls_colors(&mut self, spec: &str) {
+        for (key, sgr) in spec.split(':').filter_map(|rule| rule.split_once('=')) {
+            // An empty or zero style switches the class off
+            let style = Some(sgr.to_string()).filter(|s| !s.is_empty() && s != "0" && s != "00");
+            if let Some(ext) = key.strip_prefix("*.") {
+                match style {
+                    Some(style) => self.extensions.insert(ext.to_lowercase(), style),
+                    None => self.extensions.remove(&ext.to_lowercase()),
+                };
+                continue;
+            }
+            let slot = match key {
+                "di" => &mut self.directory,
+                "ln" => &mut self.symlink,
+                "ex" => &mut self.executable,
+                "pi" => &mut self.fifo,
+                "so" => &mut self.socket,
+                "bd" => &mut self.block_device,
+                "cd" => &mut self.char_device,
+                _ => continue,
+            };
+            *slot = style;
+        }
+    }
+
+    /// --print-theme: one line per file type and per colored extension with
+    /// its LS_COLORS key, its SGR parameters and a sample painted with them
+    pub fn preview(&self, out: &mut dyn Write, use_color: bool) -> io::Result<()> {
+        let types = [
+            ("di", "directory", &self.directory),
+            ("ln", "symlink", &self.symlink),
+            ("ex", "executable", &self.executable),
+            ("pi", "fifo", &self.fifo),
+            ("so", "socket", &self.socket),
+            ("bd", "block device", &self.block_device),
+            ("cd", "character device", &self.char_device),
+            // Built in only; LS_COLORS has no key for it
+            ("", "sparse file", &self.sparse),
+        ];
+        let mut extensions: Vec<(String, &str, Option<&String>)> =
+            self.extensions.iter().map(|(ext, style)| (format!("*.{}", ext), ext.as_str(), Some(style))).collect();
+        extensions.sort_by(|a, b| a.1.cmp(b.1));
+        let samples: Vec<(String, String, Option<&String>)> = types
+            .into_iter()
+            .map(|(key, sample, style)| (key.to_string(), sample.to_string(), style.as_ref()))
+            .chain(extensions.into_iter().map(|(key, ext, style)| (key, format!("file.{}", ext), style)))
+            .collect();
+
+        let key_width = samples.iter().map(|(key, _, _)| key.len()).max().unwrap_or(0);
+        let style_width = samples.iter().map(|(_, _, style)| style.map_or(1, |s| s.len())).max().unwrap_or(0);
+        for (key, sample, style) in &samples {
+            write!(out, "{:<key_width$}  {:<style_width$}  ", key, style.map_or("-", |s| s.as_str()))?;
+            match style.filter(|_| use_color) {
+                Some(style) => out.write_all(&paint(sample.as_bytes(), style))?,
+                None => out.write_all(sample.as_bytes())?,
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Style for an entry named `name`, or None when it stays uncolored
+    pub fn style(&self, name: &str, metadata: &FileStat) -> Option<&str> {
+        let style = match metadata.file_type() {
+            FileKind::Directory => &self.directory,
+            FileKind::Symlink => &self.symlink,
+            FileKind::Fifo => &self.fifo,
+            FileKind::Socket => &self.socket,
+            FileKind::BlockDevice => &self.block_device,
+            FileKind::CharDevice => &self.char_device,
+            FileKind::Door | FileKind::Whiteout | FileKind::Unknown => return None,
+            FileKind::Regular if metadata.mode & 0o111 != 0 => &self.executable,
+            FileKind::Regular => {
+                let by_extension = name
+                    .rsplit_once('.')
+                    .filter(|(stem, _)| !stem.is_empty())
+                    .and_then(|(_, ext)| self.extensions.get(&ext.to_lowercase()));
+                match by_extension {
+                    Some(style) => return Some(style),
+                    None if crate::is_sparse(metadata) => &self.sparse,
+                    None => return None,
+                }
+            }
+        };
+        style.as_deref()
+    }
+}
+
+/// A 24-bit color, rendered as closely as the terminal's palette allows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// The color a fraction `t` (0.0..=1.0) of the way from `self` to `to`
+    pub fn mix(self, to: Rgb, t: f64) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        Rgb(channel(self.0, to.0), channel(self.1, to.1), channel(self.2, to.2))
+    }
+
+    /// Foreground SGR parameters: `38;2;r;g;b` on a truecolor terminal, the
+    /// nearest xterm-256 color cube entry on a 256-color one, and otherwise the
+    /// nearest of the 8 standard colors
+    pub fn foreground(self, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::Truecolor => format!("38;2;{};{};{}", self.0, self.1, self.2),
+            ColorDepth::Ansi256 => {
+                let level = |c: u8| if c < 48 { 0 } else if c < 115 { 1 } else { (c as u16 - 35) / 40 };
+                format!("38;5;{}", 16 + 36 * level(self.0) + 6 * level(self.1) + level(self.2))
+            }
+            ColorDepth::Basic => {
+                // xterm's rendering of black, red, green, yellow, blue, magenta, cyan and white
+                const PALETTE: [Rgb; 8] = [
+                    Rgb(0, 0, 0),
+                    Rgb(205, 0, 0),
+                    Rgb(0, 205, 0),
+                    Rgb(205, 205, 0),
+                    Rgb(0, 0, 238),
+                    Rgb(205, 0, 205),
+                    Rgb(0, 205, 205),
+                    Rgb(229, 229, 229),
+                ];
+                // A gray is nearer some hue than black or white; keep it colorless
+                let (max, min) = (self.0.max(self.1).max(self.2), self.0.min(self.1).min(self.2));
+                if max - min < 32 {
+                    return if max < 64 { "30" } else { "37" }.to_string();
+                }
+                let distance = |c: &Rgb| {
+                    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+                    d(self.0, c.0) + d(self.1, c.1) + d(self.2, c.2)
+                };
+                let nearest = (0..PALETTE.len()).min_by_key(|&i| distance(&PALETTE[i])).unwrap_or(7);
+                (30 + nearest).to_string()
+            }
+        }
+    }
+}
+
+/// --color-scale=owner: a hue of its own for each uid
+pub fn owner_style(uid: u32, depth: ColorDepth) -> String {
+    // Steps of the golden ratio keep consecutive uids far apart on the wheel
+    let hue = (uid as f64 * 0.618_033_988_75).fract() * 6.0;
+    let (saturation, value) = (0.6, 0.9);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f64| ((c + value - chroma) * 255.0).round() as u8;
+    Rgb(channel(r), channel(g), channel(b)).foreground(depth)
+}
+
+/// --color-scale=risk: setuid and setgid files on red, world-writable ones
+/// (other than sticky directories and symlinks) in bold red, and root-owned
+/// files inside a home directory in bold yellow; anything else stays plain.
+/// `in_home` is only asked about root-owned files
+pub fn risk_style(metadata: &FileStat, in_home: impl FnOnce() -> bool) -> Option<&'static str> {
+    let file_type = metadata.file_type();
+    if file_type != FileKind::Directory && metadata.mode & 0o6000 != 0 {
+        Some("37;41")
+    } else if file_type != FileKind::Symlink && metadata.mode & 0o002 != 0 && !(file_type == FileKind::Directory && metadata.mode & 0o1000 != 0) {
+        Some("01;31")
+    } else if metadata.uid == 0 && in_home() {
+        Some("01;33")
+    } else {
+        None
+    }
+}
+
+/// Wrap `text` in the SGR `style` and a reset
+pub fn paint(text: &[u8], style: &str) -> Vec<u8> {
+    let mut painted = format!("\x1b[{}m", style).into_bytes();
+    painted.extend_from_slice(text);
+    painted.extend_from_slice(b"\x1b[0m");
+    painted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::{S_IFDIR, S_IFLNK, S_IFREG};
+
+    fn file(mode: u32) -> FileStat {
+        FileStat { mode: S_IFREG | mode, ..FileStat::default() }
+    }
+
+    #[test]
+    fn extension_classes_color_regular_files() {
+        let theme = Theme::builtin();
+        assert_eq!(theme.style("backup.tar", &file(0o644)), Some("01;31"));
+        assert_eq!(theme.style("PHOTO.JPG", &file(0o644)), Some("00;35"));
+        assert_eq!(theme.style("song.flac", &file(0o644)), Some("01;35"));
+        assert_eq!(theme.style("paper.pdf", &file(0o644)), Some("01;33"));
+        assert_eq!(theme.style("notes.txt", &file(0o644)), None);
+        // Dotfiles have no extension, and executables keep the executable color
+        assert_eq!(theme.style(".zip", &file(0o644)), None);
+        assert_eq!(theme.style("installer.zip", &file(0o755)), Some("32"));
+    }
+
+    #[test]
+    fn rgb_degrades_to_the_terminals_palette() {
+        let green = Rgb(0, 215, 95);
+        assert_eq!(green.foreground(ColorDepth::Truecolor), "38;2;0;215;95");
+        assert_eq!(green.foreground(ColorDepth::Ansi256), "38;5;41");
+        assert_eq!(green.foreground(ColorDepth::Basic), "32");
+        // Mid grays read as white rather than vanish into black
+        assert_eq!(Rgb(118, 118, 118).foreground(ColorDepth::Basic), "37");
+        assert_eq!(Rgb(0, 0, 0).mix(Rgb(255, 255, 255), 0.5), Rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn preview_lists_types_then_extensions() {
+        let mut theme = Theme::builtin();
+        theme.apply_ls_colors("pi=40;33:*.md=01");
+        let mut out = Vec::new();
+        theme.preview(&mut out, true).unwrap();
+        let preview = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = preview.lines().collect();
+        assert_eq!(lines[0], "di      34     \x1b[34mdirectory\x1b[0m");
+        assert_eq!(lines[3], "pi      40;33  \x1b[40;33mfifo\x1b[0m");
+        assert_eq!(lines[4], "so      -      socket");
+        assert!(lines.contains(&"*.md    01     \x1b[01mfile.md\x1b[0m"), "{}", preview);
+        assert_eq!(lines.len(), 8 + theme.extensions.len());
+    }
+
+    #[test]
+    fn owner_hues_differ_between_uids() {
+        assert_eq!(owner_style(0, ColorDepth::Truecolor), "38;2;229;92;92");
+        let hues: std::collections::HashSet<String> = (1000..1010).map(|uid| owner_style(uid, ColorDepth::Truecolor)).collect();
+        assert_eq!(hues.len(), 10);
+        assert_eq!(owner_style(1000, ColorDepth::Truecolor), owner_style(1000, ColorDepth::Truecolor));
+    }
+
+    #[test]
+    fn risk_flags_setuid_world_writable_and_root_in_home() {
+        let owned = |mode: u32, uid: u32| FileStat { mode, uid, ..FileStat::default() };
+        assert_eq!(risk_style(&owned(S_IFREG | 0o4755, 0), || false), Some("37;41"));
+        assert_eq!(risk_style(&owned(S_IFREG | 0o666, 1000), || false), Some("01;31"));
+        assert_eq!(risk_style(&owned(S_IFDIR | 0o1777, 0), || false), None);
+        assert_eq!(risk_style(&owned(S_IFLNK | 0o777, 1000), || true), None);
+        assert_eq!(risk_style(&owned(S_IFREG | 0o644, 0), || true), Some("01;33"));
+        assert_eq!(risk_style(&owned(S_IFREG | 0o644, 1000), || true), None);
+    }
+
+    #[test]
+    fn ls_colors_overrides_builtin_styles() {
+        let mut theme = Theme::builtin();
+        theme.apply_ls_colors("di=01;34:*.txt=04:*.zip=0:xx=1");
+        let dir = FileStat { mode: S_IFDIR | 0o755, ..FileStat::default() };
+        assert_eq!(theme.style("src", &dir), Some("01;34"));
+        assert_eq!(theme.style("notes.TXT", &file(0o644)), Some("04"));
+        assert_eq!(theme.style("archive.zip", &file(0o644)), None);
+    }
+}
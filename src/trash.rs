@@ -0,0 +1,109 @@
+//! --trash: list the XDG trash can with the original path and deletion date
+//! recorded in each `.trashinfo` file.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::LsError;
+use crate::filesystem::Filesystem;
+use crate::{collect_entries, print_long_format, sort_entries, Config};
+
+/// `$XDG_DATA_HOME/Trash`, defaulting to `~/.local/share/Trash`
+fn trash_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(data_home) => Some(PathBuf::from(data_home).join("Trash")),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/Trash")),
+    }
+}
+
+/// The fields of a `.trashinfo` file ls shows
+#[derive(Debug, PartialEq)]
+struct TrashInfo {
+    path: String,
+    deletion_date: String,
+}
+
+fn parse_trash_info(text: &str) -> Option<TrashInfo> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+    let (mut path, mut deletion_date) = (None, None);
+    for line in lines {
+        match line.split_once('=') {
+            Some(("Path", value)) => path = Some(percent_decode(value)),
+            // Stored as local time in RFC 3339 form without a zone
+            Some(("DeletionDate", value)) => deletion_date = Some(value.replacen('T', " ", 1)),
+            _ => {}
+        }
+    }
+    Some(TrashInfo { path: path?, deletion_date: deletion_date? })
+}
+
+/// Undo the URL escaping the spec requires for `Path=`
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// List the trash in long format with deletion date and original path columns
+pub fn list_trash(fs: &dyn Filesystem, config: &Config, stdout: &mut dyn Write) -> Result<(), LsError> {
+    let root = trash_dir().ok_or_else(|| LsError::CannotAccess {
+        path: PathBuf::from("Trash"),
+        source: io::Error::new(io::ErrorKind::NotFound, "neither XDG_DATA_HOME nor HOME is set"),
+    })?;
+    let mut entries = collect_entries(fs, &root.join("files"), config)?;
+    sort_entries(&mut entries, config);
+
+    for entry in &mut entries {
+        let info = read_trash_info(&root, &entry.name);
+        entry.extra_columns = match info {
+            Some(info) => vec![info.deletion_date, info.path],
+            None => vec!["?".to_string(), "?".to_string()],
+        };
+    }
+
+    let use_color = crate::use_color(config);
+    print_long_format(&entries, config, stdout, use_color)?;
+    Ok(())
+}
+
+fn read_trash_info(root: &Path, name: &str) -> Option<TrashInfo> {
+    let text = fs::read_to_string(root.join("info").join(format!("{}.trashinfo", name))).ok()?;
+    parse_trash_info(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_trash_info() {
+        let info = parse_trash_info("[Trash Info]\nPath=/home/me/My%20Notes/caf%C3%A9.txt\nDeletionDate=2024-03-01T09:15:00\n");
+        assert_eq!(info, Some(TrashInfo {
+            path: "/home/me/My Notes/café.txt".to_string(),
+            deletion_date: "2024-03-01 09:15:00".to_string(),
+        }));
+        assert_eq!(parse_trash_info("Path=/x\n"), None);
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
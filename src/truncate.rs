@@ -0,0 +1,192 @@
+//! --truncate: long-format lines clipped to the terminal width with an
+//! ellipsis, so each entry stays on one row. Widths are measured as the
+//! terminal renders them: escape sequences take no room, East Asian wide
+//! characters take two columns and combining marks none.
+
+use std::io::{self, Write};
+
+/// Passes output through a line at a time, clipping lines wider than `width`
+pub struct TruncatingWriter<'a> {
+    out: &'a mut dyn Write,
+    width: usize,
+    line: Vec<u8>,
+}
+
+impl<'a> TruncatingWriter<'a> {
+    pub fn new(out: &'a mut dyn Write, width: usize) -> TruncatingWriter<'a> {
+        TruncatingWriter { out, width, line: Vec::new() }
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        self.out.write_all(&clip(&line, self.width))
+    }
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.line.extend_from_slice(&rest[..end]);
+            self.write_line()?;
+            self.out.write_all(b"\n")?;
+            rest = &rest[end + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        self.out.flush()
+    }
+}
+
+/// `line` cut to at most `width` columns, the last of them an ellipsis when
+/// anything was cut. Colors left open by the cut are reset before the ellipsis
+fn clip(line: &[u8], width: usize) -> Vec<u8> {
+    if display_width(line) <= width {
+        return line.to_vec();
+    }
+    let budget = width.saturating_sub(1);
+    let mut clipped = Vec::with_capacity(line.len());
+    let mut used = 0;
+    let mut colored = false;
+    for token in tokens(line) {
+        match token {
+            Token::Escape(sequence) => {
+                colored = true;
+                clipped.extend_from_slice(sequence);
+            }
+            Token::Text(bytes, columns) => {
+                if used + columns > budget {
+                    break;
+                }
+                used += columns;
+                clipped.extend_from_slice(bytes);
+            }
+        }
+    }
+    if colored {
+        clipped.extend_from_slice(b"\x1b[0m");
+    }
+    if width > 0 {
+        clipped.extend_from_slice("…".as_bytes());
+    }
+    clipped
+}
+
+/// Columns `line` takes up on a terminal
+pub fn display_width(line: &[u8]) -> usize {
+    tokens(line)
+        .map(|token| match token {
+            Token::Escape(_) => 0,
+            Token::Text(_, columns) => columns,
+        })
+        .sum()
+}
+
+enum Token<'a> {
+    /// A CSI (`ESC [ ... final`) or OSC (`ESC ] ... BEL` or `ESC ] ... ESC \`) sequence
+    Escape(&'a [u8]),
+    /// One character, or one byte that isn't valid UTF-8, and its width
+    Text(&'a [u8], usize),
+}
+
+fn tokens(line: &[u8]) -> impl Iterator<Item = Token<'_>> {
+    let mut rest = line;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let len = escape_len(rest).unwrap_or_else(|| utf8_len(rest));
+        let (bytes, tail) = rest.split_at(len);
+        rest = tail;
+        if bytes[0] == 0x1b && len > 1 {
+            return Some(Token::Escape(bytes));
+        }
+        let columns = std::str::from_utf8(bytes).ok().and_then(|s| s.chars().next()).map_or(1, char_width);
+        Some(Token::Text(bytes, columns))
+    })
+}
+
+/// Length of the escape sequence `bytes` starts with, if it starts with one
+fn escape_len(bytes: &[u8]) -> Option<usize> {
+    match bytes {
+        [0x1b, b'[', rest @ ..] => {
+            let end = rest.iter().position(|b| (0x40..=0x7e).contains(b))?;
+            Some(2 + end + 1)
+        }
+        [0x1b, b']', rest @ ..] => {
+            let end = rest.windows(2).position(|pair| pair[0] == 0x07 || pair == b"\x1b\\");
+            match end {
+                Some(end) if rest[end] == 0x07 => Some(2 + end + 1),
+                Some(end) => Some(2 + end + 2),
+                // A terminating BEL can also be the very last byte
+                None if rest.last() == Some(&0x07) => Some(bytes.len()),
+                None => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Length of the UTF-8 character `bytes` starts with; 1 for an invalid byte
+fn utf8_len(bytes: &[u8]) -> usize {
+    let len = match bytes[0] {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 1,
+    };
+    if bytes.len() >= len && std::str::from_utf8(&bytes[..len]).is_ok() { len } else { 1 }
+}
+
+/// Terminal columns of one character: 2 for East Asian wide and fullwidth
+/// characters and emoji, 0 for combining marks and other zero-width ones
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036f | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x20d0..=0x20ff | 0xfe20..=0xfe2f => 0,
+        0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_skip_escapes_and_count_wide_characters_twice() {
+        assert_eq!(display_width(b"\x1b[01;34mdir\x1b[0m"), 3);
+        assert_eq!(display_width("日本語.txt".as_bytes()), 10);
+        assert_eq!(display_width("cafe\u{301}".as_bytes()), 4);
+        assert_eq!(display_width(b"\x1b]8;;file:///x\x1b\\x\x1b]8;;\x1b\\"), 1);
+    }
+
+    #[test]
+    fn clipped_lines_end_in_an_ellipsis_and_reset_colors() {
+        assert_eq!(clip(b"short", 10), b"short");
+        assert_eq!(clip(b"exactly10!", 10), b"exactly10!");
+        assert_eq!(String::from_utf8(clip(b"a longer line", 8)).unwrap(), "a longe…");
+        // A wide character that doesn't fit whole is left out
+        assert_eq!(String::from_utf8(clip("ab日本".as_bytes(), 4)).unwrap(), "ab…");
+        assert_eq!(String::from_utf8(clip(b"\x1b[31mred text\x1b[0m", 5)).unwrap(), "\x1b[31mred \x1b[0m…");
+    }
+}
@@ -0,0 +1,142 @@
+//! Fixture trees and golden-file helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::fs::{self, File};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::os::unix::process::CommandExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+
+use assert_cmd::Command;
+use filetime::FileTime;
+use tempfile::TempDir;
+
+/// 2001-02-03 04:05:06 UTC, old enough that long listings show the year
+pub const BASE_TIME: i64 = 981_173_106;
+
+/// A temporary tree with every file type ls distinguishes.
+///
+/// `files/` holds only non-directories, with sparse sizes so block counts are
+/// zero on every filesystem; `tree/` holds nested directories for -R.
+pub struct Fixture {
+    pub dir: TempDir,
+    // Keeps the socket bound for the lifetime of the fixture
+    _socket: UnixListener,
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let files = dir.path().join("files");
+        fs::create_dir(&files).unwrap();
+
+        let mut stamp = BASE_TIME;
+        let mut next_time = || {
+            stamp += 3600;
+            FileTime::from_unix_time(stamp, 0)
+        };
+
+        for (name, len, mode) in [
+            ("alpha.txt", 5, 0o644),
+            ("Beta.md", 1234, 0o644),
+            ("gamma", 0, 0o755),
+            ("delta.log", 10_000_000, 0o600),
+            (".hidden", 12, 0o644),
+            ("café.txt", 42, 0o444),
+            ("日本語.txt", 7, 0o640),
+            ("with space", 3, 0o664),
+        ] {
+            let path = files.join(name);
+            File::create(&path).unwrap().set_len(len).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            filetime::set_file_mtime(&path, next_time()).unwrap();
+        }
+
+        make_fifo(&files.join("fifo"), 0o644);
+        let time = next_time();
+        filetime::set_symlink_file_times(files.join("fifo"), time, time).unwrap();
+
+        let socket = UnixListener::bind(files.join("sock")).unwrap();
+        fs::set_permissions(files.join("sock"), fs::Permissions::from_mode(0o755)).unwrap();
+        let time = next_time();
+        filetime::set_symlink_file_times(files.join("sock"), time, time).unwrap();
+
+        for (name, target) in [("link", "alpha.txt"), ("dangling", "missing")] {
+            let path = files.join(name);
+            symlink(target, &path).unwrap();
+            let time = next_time();
+            filetime::set_symlink_file_times(&path, time, time).unwrap();
+        }
+
+        let tree = dir.path().join("tree");
+        for sub in ["a/deep", "b", ".dot"] {
+            fs::create_dir_all(tree.join(sub)).unwrap();
+        }
+        for file in ["top.txt", "a/one.txt", "a/deep/two.txt", "b/three.txt", ".dot/four.txt"] {
+            File::create(tree.join(file)).unwrap();
+        }
+
+        Fixture { dir, _socket: socket }
+    }
+
+    pub fn path(&self, sub: &str) -> PathBuf {
+        self.dir.path().join(sub)
+    }
+}
+
+fn make_fifo(path: &Path, mode: u32) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), mode as libc::mode_t) }, 0);
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+/// Run ls-rs in `cwd` with a scrubbed, deterministic environment
+pub fn ls(cwd: &Path, args: &[&str]) -> Command {
+    let mut detached = std::process::Command::new(assert_cmd::cargo::cargo_bin("ls-rs"));
+    // In a session of its own, so a terminal running the tests can't lend
+    // ls its width through /dev/tty
+    unsafe {
+        detached.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut cmd = Command::from_std(detached);
+    cmd.current_dir(cwd)
+        .env_clear()
+        .env("TZ", "UTC")
+        .env("LC_ALL", "C")
+        .args(args);
+    cmd
+}
+
+/// Run ls-rs and return stdout, asserting it succeeded
+pub fn run(cwd: &Path, args: &[&str]) -> String {
+    let output = ls(cwd, args).output().unwrap();
+    assert!(output.status.success(), "ls-rs {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Replace the owner and group columns of long listings made with --no-lookup
+/// by fixed placeholders, since the ids depend on who runs the tests
+pub fn normalize_owner(output: &str) -> String {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+    output.replace(&format!(" {:>8} {:>8} ", uid, gid), "      uid      gid ")
+}
+
+/// Compare output against `tests/golden/<name>.txt`; set UPDATE_GOLDEN=1 to rewrite it
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("missing golden file {}: {} (run with UPDATE_GOLDEN=1)", path.display(), e));
+    assert!(expected == actual, "output differs from {}\n--- expected\n{}--- actual\n{}", path.display(), expected, actual);
+}
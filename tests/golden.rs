@@ -0,0 +1,1328 @@
+mod common;
+
+use common::{assert_golden, normalize_owner, run, Fixture};
+
+/// Each case lists the fixture subdirectory to run in and the flags to pass
+const NAME_CASES: &[(&str, &str, &[&str])] = &[
+    ("default", "files", &[]),
+    ("one_per_line", "files", &["-1"]),
+    ("all", "files", &["-1", "-A"]),
+    ("classify", "files", &["-1", "-F"]),
+    ("slash", "tree", &["-1", "-p"]),
+    ("reverse", "files", &["-1", "-r"]),
+    ("sort_size", "files", &["-1", "-S"]),
+    ("sort_size_reverse", "files", &["-1", "-S", "-r"]),
+    ("sort_time", "files", &["-1", "-t"]),
+    ("sort_time_reverse", "files", &["-1", "-t", "-r"]),
+    ("sort_case_sensitive", "files", &["-1", "--sort-case=sensitive"]),
+    ("stream", "files", &["-m"]),
+    ("columns_down", "files", &["-C"]),
+    ("columns_across", "files", &["-x"]),
+    ("max_name_width", "files", &["-C", "--max-name-width=6"]),
+    ("long_grid", "files", &["--long-grid"]),
+    ("recursive", "tree", &["-R", "-1"]),
+    ("recursive_all", "tree", &["-R", "-1", "-A"]),
+    ("color", "tree", &["-1", "--color=always"]),
+];
+
+const LONG_CASES: &[(&str, &[&str])] = &[
+    ("long", &["-l"]),
+    ("long_all", &["-l", "-A"]),
+    ("long_classify", &["-l", "-F"]),
+    ("long_human", &["-l", "--human-readable"]),
+    ("long_sort_size", &["-l", "-S"]),
+    ("long_size_grouping", &["-l", "--size-grouping"]),
+    ("long_block_size_k", &["-l", "--block-size=K"]),
+    ("long_sort_time_reverse", &["-l", "-t", "-r"]),
+];
+
+#[test]
+fn name_formats_match_golden() {
+    let fixture = Fixture::new();
+    for (name, dir, args) in NAME_CASES {
+        assert_golden(name, &run(&fixture.path(dir), args));
+    }
+}
+
+#[test]
+fn long_formats_match_golden() {
+    let fixture = Fixture::new();
+    for (name, args) in LONG_CASES {
+        let mut args = args.to_vec();
+        args.push("--no-lookup");
+        assert_golden(name, &normalize_owner(&run(&fixture.path("files"), &args)));
+    }
+}
+
+#[test]
+fn multiple_operands_match_golden() {
+    let fixture = Fixture::new();
+    let output = run(fixture.dir.path(), &["-1", "files/alpha.txt", "tree/b", "tree/a"]);
+    assert_golden("multiple_operands", &output);
+}
+
+#[test]
+fn no_headers_concatenates_operands() {
+    let fixture = Fixture::new();
+    let output = run(fixture.dir.path(), &["-1", "--no-headers", "tree/b", "files/alpha.txt", "tree/a"]);
+    assert_eq!(output, "files/alpha.txt\nthree.txt\ndeep\none.txt\n");
+}
+
+#[test]
+fn recursive_headers_keep_operand_spelling() {
+    let fixture = Fixture::new();
+    assert_golden("recursive_trailing_slash", &run(fixture.dir.path(), &["-R", "-1", "tree/"]));
+    assert_golden("recursive_dot_prefix", &run(fixture.dir.path(), &["-R", "-1", "./tree/a"]));
+}
+
+#[test]
+fn recursive_unordered_prints_the_same_sections() {
+    let fixture = Fixture::new();
+    let sections = |output: String| {
+        let mut sections: Vec<String> = output.split("\n\n").map(str::to_string).collect();
+        sections.sort();
+        sections
+    };
+    let ordered = run(fixture.dir.path(), &["-R", "-1", "-a", "tree"]);
+    let unordered = run(fixture.dir.path(), &["-R", "-1", "-a", "--unordered", "tree"]);
+    assert_eq!(sections(ordered), sections(unordered));
+}
+
+#[test]
+fn natural_sort_orders_digit_runs_by_value() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["file10", "File2", "file9", "file010b", "img1.png"] {
+        std::fs::File::create(dir.path().join(name)).unwrap();
+    }
+    let output = run(dir.path(), &["-1", "--sort-case=natural"]);
+    assert_eq!(output, "File2\nfile9\nfile10\nfile010b\nimg1.png\n");
+}
+
+#[test]
+fn trash_lists_original_path_and_deletion_date() {
+    let fixture = Fixture::new();
+    let trash = fixture.path("data/Trash");
+    std::fs::create_dir_all(trash.join("files")).unwrap();
+    std::fs::create_dir_all(trash.join("info")).unwrap();
+    std::fs::write(trash.join("files/report.pdf"), "").unwrap();
+    std::fs::write(
+        trash.join("info/report.pdf.trashinfo"),
+        "[Trash Info]\nPath=/home/me/My%20Docs/report.pdf\nDeletionDate=2024-03-01T09:15:00\n",
+    )
+    .unwrap();
+
+    let output = common::ls(fixture.dir.path(), &["--trash", "--no-lookup"])
+        .env("XDG_DATA_HOME", fixture.path("data"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with(" 2024-03-01 09:15:00 /home/me/My Docs/report.pdf report.pdf\n"), "{}", stdout);
+}
+
+#[test]
+fn cached_listings_are_served_until_refreshed() {
+    let fixture = Fixture::new();
+    let cache = fixture.path("cache");
+    let dir = fixture.path("tree/b");
+    let old = filetime::FileTime::from_unix_time(common::BASE_TIME, 0);
+    filetime::set_file_mtime(&dir, old).unwrap();
+
+    let list = |flags: &[&str]| {
+        let mut args = vec!["-1", "tree/b"];
+        args.extend(flags);
+        let output = common::ls(fixture.dir.path(), &args).env("XDG_CACHE_HOME", &cache).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(list(&["--cached"]), "three.txt\n");
+
+    // Remove an entry but keep the directory's cache key
+    std::fs::remove_file(dir.join("three.txt")).unwrap();
+    filetime::set_file_mtime(&dir, old).unwrap();
+    assert_eq!(list(&["--cached"]), "three.txt\n");
+    assert_eq!(list(&["--cached", "--refresh"]), "");
+    assert_eq!(list(&[]), "");
+}
+
+#[test]
+fn cached_listings_keep_non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let odd = dir.join("tree").join(OsStr::from_bytes(b"\xff"));
+    std::fs::create_dir_all(&odd).unwrap();
+    std::fs::write(odd.join("inner.txt"), "").unwrap();
+    let old = filetime::FileTime::from_unix_time(common::BASE_TIME, 0);
+    filetime::set_file_mtime(&odd, old).unwrap();
+    filetime::set_file_mtime(dir.join("tree"), old).unwrap();
+
+    let list = |flags: &[&str]| {
+        let mut args = vec!["-lR", "--no-lookup", "tree"];
+        args.extend(flags);
+        let output = common::ls(dir, &args).env("XDG_CACHE_HOME", dir.join("cache")).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        output.stdout
+    };
+    let uncached = list(&[]);
+    assert!(String::from_utf8_lossy(&uncached).contains("inner.txt"));
+    // The first run fills the cache, the second reads it
+    assert_eq!(list(&["--cached"]), uncached);
+    assert_eq!(list(&["--cached"]), uncached);
+}
+
+#[test]
+fn diff_marks_added_removed_and_changed_entries() {
+    let fixture = Fixture::new();
+    let (a, b) = (fixture.path("a"), fixture.path("b"));
+    for dir in [&a, &b] {
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("same.txt"), "same").unwrap();
+    }
+    std::fs::write(a.join("gone.txt"), "").unwrap();
+    std::fs::write(b.join("new.txt"), "").unwrap();
+    std::fs::write(a.join("sub/grown.txt"), "1").unwrap();
+    std::fs::write(b.join("sub/grown.txt"), "12").unwrap();
+    let time = filetime::FileTime::from_unix_time(common::BASE_TIME, 0);
+    for file in ["same.txt", "sub/grown.txt"] {
+        filetime::set_file_mtime(a.join(file), time).unwrap();
+        filetime::set_file_mtime(b.join(file), time).unwrap();
+    }
+
+    assert_eq!(run(fixture.dir.path(), &["--diff", "a", "b"]), "- gone.txt\n+ new.txt\n");
+    assert_eq!(
+        run(fixture.dir.path(), &["--diff", "-R", "a", "b"]),
+        "- gone.txt\n+ new.txt\n~ sub/grown.txt  size 1 -> 2\n"
+    );
+}
+
+#[test]
+fn since_snapshot_reports_drift() {
+    let fixture = Fixture::new();
+    let snapshot = fixture.path("tree.json");
+    let snapshot = snapshot.to_str().unwrap();
+    assert_eq!(run(fixture.dir.path(), &["-R", "--snapshot", snapshot, "tree"]), "");
+    assert_eq!(run(fixture.dir.path(), &["-R", "--since-snapshot", snapshot, "tree"]), "");
+
+    std::fs::remove_file(fixture.path("tree/b/three.txt")).unwrap();
+    std::fs::write(fixture.path("tree/a/five.txt"), "").unwrap();
+    std::fs::write(fixture.path("tree/top.txt"), "grown").unwrap();
+    let drift = run(fixture.dir.path(), &["-R", "--since-snapshot", snapshot, "tree"]);
+    let markers: Vec<&str> = drift.lines().map(|line| line.split("  ").next().unwrap()).collect();
+    assert_eq!(markers, ["+ a/five.txt", "- b/three.txt", "~ top.txt"]);
+}
+
+#[test]
+fn ls_rs_options_supplies_defaults_that_argv_overrides() {
+    let fixture = Fixture::new();
+    let list = |args: &[&str]| {
+        let output = common::ls(&fixture.path("files"), args)
+            .env("LS_RS_OPTIONS", "-1 --sort-case='sensitive'")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(list(&[]), run(&fixture.path("files"), &["-1", "--sort-case=sensitive"]));
+    assert_eq!(list(&["-1", "--sort-case=insensitive"]), run(&fixture.path("files"), &["-1"]));
+}
+
+#[test]
+fn passwd_and_group_files_replace_host_name_lookup() {
+    use std::os::unix::fs::MetadataExt;
+
+    let fixture = Fixture::new();
+    std::fs::write(fixture.path("passwd"), "").unwrap();
+    let meta = std::fs::metadata(fixture.path("passwd")).unwrap();
+    std::fs::write(fixture.path("passwd"), format!("# image users\nbuilder:x:{}:{}::/:/bin/sh\n", meta.uid(), meta.gid())).unwrap();
+    std::fs::write(fixture.path("group"), "wheel:x:0:\n").unwrap();
+
+    let listing = run(fixture.dir.path(), &["-l", "--passwd-file", "passwd", "--group-file", "group", "passwd"]);
+    let fields: Vec<&str> = listing.split_whitespace().collect();
+    assert_eq!(fields[2], "builder");
+    // Ids missing from the file are shown numerically rather than asking the host
+    let expected_group = if meta.gid() == 0 { "wheel".to_string() } else { meta.gid().to_string() };
+    assert_eq!(fields[3], expected_group);
+}
+
+#[test]
+fn heat_tints_long_lines_from_smallest_to_largest() {
+    let fixture = Fixture::new();
+    std::fs::create_dir(fixture.path("heat")).unwrap();
+    std::fs::write(fixture.path("heat/small"), "x").unwrap();
+    std::fs::write(fixture.path("heat/large"), vec![b'x'; 1 << 20]).unwrap();
+    let output = common::ls(&fixture.path("heat"), &["-l", "--heat=size", "--color=always"])
+        .env("COLORTERM", "truecolor")
+        .output()
+        .unwrap();
+    let listing = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = listing.lines().skip(1).collect();
+    assert!(lines[0].starts_with("\x1b[38;2;255;135;0m") && lines[0].ends_with("large\x1b[0m"), "{:?}", lines[0]);
+    assert!(lines[1].starts_with("\x1b[38;2;118;118;118m"), "{:?}", lines[1]);
+}
+
+#[test]
+fn size_bars_scale_to_the_largest_entry() {
+    let fixture = Fixture::new();
+    std::fs::create_dir(fixture.path("bars")).unwrap();
+    for (name, len) in [("empty", 0), ("half", 512), ("full", 1024)] {
+        std::fs::write(fixture.path("bars").join(name), vec![b'x'; len]).unwrap();
+    }
+    let listing = run(&fixture.path("bars"), &["-l", "--size-bars"]);
+    let lines: Vec<&str> = listing.lines().skip(1).collect();
+    assert!(lines[0].contains("    0          "), "{}", listing);
+    assert!(lines[1].contains(" 1024 ████████ "), "{}", listing);
+    assert!(lines[2].contains("  512 ████     "), "{}", listing);
+}
+
+#[test]
+fn dir_counts_show_entries_inside_each_directory() {
+    let fixture = Fixture::new();
+    let listing = run(&fixture.path("tree"), &["-lA", "--dir-counts"]);
+    let counts: Vec<(&str, &str)> = listing
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            (*fields.last().unwrap(), fields[4])
+        })
+        .collect();
+    // Files leave the count column blank, so their size takes its place here
+    assert_eq!(counts, [(".dot", "1"), ("a", "2"), ("b", "1"), ("top.txt", "0")]);
+}
+
+#[test]
+fn dirs_show_entries_puts_the_count_in_the_size_column() {
+    let fixture = Fixture::new();
+    let sizes = |flags: &[&str]| -> Vec<(String, String)> {
+        let listing = run(&fixture.path("tree"), &[&["-lA"], flags].concat());
+        listing
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                (fields.last().unwrap().to_string(), fields[4].to_string())
+            })
+            .collect()
+    };
+    let entries = sizes(&["--dirs-show=entries"]);
+    assert_eq!(entries, [(".dot", "1"), ("a", "2"), ("b", "1"), ("top.txt", "0")].map(|(n, s)| (n.to_string(), s.to_string())));
+    assert_eq!(sizes(&["--dirs-show=size"]), sizes(&[]));
+    common::ls(&fixture.path("tree"), &["-l", "--dirs-show=inodes"]).assert().code(2);
+}
+
+#[test]
+fn octal_permissions_show_the_numeric_mode() {
+    let fixture = Fixture::new();
+    let modes = |how: &str| -> Vec<String> {
+        let listing = run(&fixture.path("files"), &["-l", how, "gamma", "café.txt"]);
+        listing.lines().map(|line| line.split(" 1 ").next().unwrap().to_string()).collect()
+    };
+    assert_eq!(modes("--octal-permissions"), ["0444 -r--r--r--", "0755 -rwxr-xr-x"]);
+    assert_eq!(modes("--octal-permissions=only"), ["0444", "0755"]);
+}
+
+#[test]
+fn forced_columns_use_width_then_columns_when_piped() {
+    let fixture = Fixture::new();
+    let narrow = run(&fixture.path("files"), &["-C", "--width=30"]);
+    assert!(narrow.lines().count() > run(&fixture.path("files"), &["-C"]).lines().count());
+    assert!(narrow.lines().all(|line| line.trim_end().chars().count() <= 30), "{}", narrow);
+
+    let output = common::ls(&fixture.path("files"), &["-C"]).env("COLUMNS", "30").output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), narrow);
+    // An explicit --width wins over COLUMNS
+    let output = common::ls(&fixture.path("files"), &["-C", "-w", "80"]).env("COLUMNS", "30").output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), run(&fixture.path("files"), &["-C"]));
+}
+
+#[test]
+fn normalize_makes_composed_and_decomposed_names_sort_together() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("unicode");
+    std::fs::create_dir(&dir).unwrap();
+    // U+00E9 as macOS stores it, decomposed into e and a combining acute accent
+    for name in ["caf\u{e9}1", "cafe\u{301}2", "cafeteria"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    let list = |form: &str| run(&dir, &["-1", form]);
+    assert_eq!(list("--normalize=none"), "cafeteria\ncafe\u{301}2\ncaf\u{e9}1\n");
+    assert_eq!(list("--normalize=nfc"), "cafeteria\ncaf\u{e9}1\ncaf\u{e9}2\n");
+    assert_eq!(list("--normalize=nfd"), "cafeteria\ncafe\u{301}1\ncafe\u{301}2\n");
+}
+
+#[test]
+fn control_characters_are_hidden_on_request() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("control");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("bell\x07\ttab"), "").unwrap();
+
+    // Pipes get the raw bytes unless -q asks otherwise; the last of the two flags wins
+    assert_eq!(run(&dir, &["-1"]), "bell\x07\ttab\n");
+    assert_eq!(run(&dir, &["-1", "-q"]), "bell??tab\n");
+    assert_eq!(run(&dir, &["-1", "-q", "--show-control-chars"]), "bell\x07\ttab\n");
+}
+
+#[test]
+fn non_utf8_names_are_written_byte_for_byte() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let fixture = Fixture::new();
+    let dir = fixture.path("latin1");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join(OsStr::from_bytes(b"caf\xe9")), "").unwrap();
+
+    let output = common::ls(&dir, &["-1"]).output().unwrap();
+    assert_eq!(output.stdout, b"caf\xe9\n");
+    let output = common::ls(&dir, &["-1", "-q"]).output().unwrap();
+    assert_eq!(output.stdout, b"caf?\n");
+}
+
+#[test]
+fn thread_count_does_not_change_the_listing() {
+    let fixture = Fixture::new();
+    let default = run(fixture.dir.path(), &["-lR", "--no-lookup", "tree"]);
+    assert_eq!(run(fixture.dir.path(), &["-lR", "--no-lookup", "--threads", "1", "tree"]), default);
+
+    let output = common::ls(fixture.dir.path(), &["--threads", "0"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn timestamps_before_1970_and_far_ahead_show_their_year() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("epochs");
+    std::fs::create_dir(&dir).unwrap();
+    for (name, secs) in [("old", -86_400), ("future", 4_102_444_800)] {
+        std::fs::write(dir.join(name), "").unwrap();
+        filetime::set_file_mtime(dir.join(name), filetime::FileTime::from_unix_time(secs, 0)).unwrap();
+    }
+
+    let listing = run(&dir, &["-l", "--no-lookup"]);
+    let dates: Vec<String> = listing
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().rev().skip(1).take(3).collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(dates, ["2100 1 Jan", "1969 31 Dec"]);
+}
+
+#[test]
+fn locale_time_style_uses_native_month_names() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("months");
+    std::fs::create_dir(&dir).unwrap();
+    // 2021-03-15 and 2021-05-15, noon UTC
+    for (name, secs) in [("march", 1_615_809_600), ("may", 1_621_080_000)] {
+        std::fs::write(dir.join(name), "").unwrap();
+        filetime::set_file_mtime(dir.join(name), filetime::FileTime::from_unix_time(secs, 0)).unwrap();
+    }
+
+    let dates = |args: &[&str], locale: &str| -> Vec<String> {
+        let output = common::ls(&dir, args).env("LC_ALL", locale).output().unwrap();
+        let listing = String::from_utf8(output.stdout).unwrap();
+        listing.lines().skip(1).map(|line| line[line.rfind(" 0 ").unwrap() + 3..].to_string()).collect()
+    };
+    let long = ["-l", "--no-lookup"];
+    assert_eq!(dates(&long, "de_DE.UTF-8"), ["Mar 15  2021 march", "May 15  2021 may"]);
+    let locale = ["-l", "--no-lookup", "--time-style=locale"];
+    assert_eq!(dates(&locale, "de_DE.UTF-8"), ["Mär 15  2021 march", "Mai 15  2021 may"]);
+    // Month names of different lengths are padded so the names stay aligned
+    assert_eq!(dates(&locale, "fr_FR.UTF-8"), ["mars  15  2021 march", "mai   15  2021 may"]);
+    assert_eq!(dates(&locale, "C"), dates(&long, "C"));
+}
+
+#[test]
+fn auto_time_style_writes_iso_dates_into_a_pipe() {
+    let fixture = Fixture::new();
+    let dates = |args: &[&str], options: &str| -> Vec<String> {
+        let output = common::ls(&fixture.path("files"), args).env("LS_RS_OPTIONS", options).output().unwrap();
+        let listing = String::from_utf8(output.stdout).unwrap();
+        listing.lines().filter(|line| line.ends_with(" alpha.txt") && !line.contains(" -> ")).map(|line| line.split_whitespace().rev().skip(1).take(2).collect::<Vec<_>>().join(" ")).collect()
+    };
+    let long = ["-l", "--no-lookup"];
+    assert_eq!(dates(&long, "--time-style=auto"), ["05:05:06 2001-02-03"]);
+    assert_eq!(dates(&long, "--time-style=iso"), ["05:05:06 2001-02-03"]);
+    // An explicit style on the command line overrides the configured one
+    assert_eq!(dates(&["-l", "--no-lookup", "--time-style=default"], "--time-style=auto"), ["2001 3"]);
+}
+
+#[test]
+fn uniform_widths_align_every_recursive_section() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("uneven");
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("small"), "x").unwrap();
+    std::fs::write(dir.join("sub/big"), vec![b'x'; 123_456]).unwrap();
+    let time = filetime::FileTime::from_unix_time(common::BASE_TIME, 0);
+    for path in ["small", "sub", "sub/big"] {
+        filetime::set_file_mtime(dir.join(path), time).unwrap();
+    }
+
+    // Where the date starts on each entry line
+    let date_columns = |args: &[&str]| -> Vec<usize> {
+        let listing = run(&dir, args);
+        let date = run(&dir, &["-l", "--no-lookup", "small"]);
+        let date = &date[date.rfind(" 1 ").unwrap() + 3..date.rfind(' ').unwrap()];
+        listing.lines().filter_map(|line| line.find(date)).collect()
+    };
+    let ragged = date_columns(&["-lR", "--no-lookup"]);
+    assert_eq!(ragged.len(), 3);
+    assert!(ragged.iter().any(|&column| column != ragged[0]), "{:?}", ragged);
+    let uniform = date_columns(&["-lR", "--no-lookup", "--uniform-widths"]);
+    assert_eq!(uniform, [uniform[0]; 3]);
+}
+
+#[test]
+fn color_environment_variables_sit_below_color_flags() {
+    let fixture = Fixture::new();
+    let colored = |args: &[&str], env: &[(&str, &str)]| {
+        let output = common::ls(&fixture.path("tree"), args).envs(env.iter().copied()).output().unwrap();
+        output.stdout.contains(&0x1b)
+    };
+    assert!(!colored(&["-1"], &[]));
+    assert!(colored(&["-1"], &[("CLICOLOR_FORCE", "1")]));
+    assert!(!colored(&["-1"], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]));
+    assert!(colored(&["-1", "--color=always"], &[("NO_COLOR", "1")]));
+    assert!(!colored(&["-1", "--color=never"], &[("CLICOLOR_FORCE", "1")]));
+}
+
+#[test]
+fn grid_gap_separator_and_trimming_shape_columns() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("grid");
+    std::fs::create_dir(&dir).unwrap();
+    for name in ["a", "bb", "ccc", "dddd"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    assert_eq!(run(&dir, &["-x", "-w", "80"]), "a     bb    ccc   dddd  \n");
+    assert_eq!(run(&dir, &["-x", "-w", "80", "--grid-gap=1"]), "a    bb   ccc  dddd \n");
+    assert_eq!(run(&dir, &["-x", "-w", "80", "--grid-gap=1", "--trim-grid"]), "a    bb   ccc  dddd\n");
+    assert_eq!(run(&dir, &["-x", "-w", "16", "--grid-separator= | ", "--trim-grid"]), "a    | bb\nccc  | dddd\n");
+
+    let output = common::ls(&dir, &["-x", "--grid-gap=1", "--grid-separator=|"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn ignore_backups_and_globs_hide_matching_entries() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("backups");
+    std::fs::create_dir(&dir).unwrap();
+    for name in ["notes", "notes~", ".profile~", "build.o", "main.c"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    assert_eq!(run(&dir, &["-1", "-A", "-B"]), "build.o\nmain.c\nnotes\n");
+    assert_eq!(run(&dir, &["-1", "--ignore-glob=*.o", "--ignore-glob", "notes*"]), "main.c\n");
+    // Unlike the dotfile rule, -a does not bring ignored entries back
+    assert_eq!(run(&dir, &["-1", "-a", "--ignore-glob=.*"]), "build.o\nmain.c\nnotes\nnotes~\n");
+}
+
+#[test]
+fn recurse_order_lists_children_first_or_level_by_level() {
+    let fixture = Fixture::new();
+    let tree = fixture.path("tree");
+    let headers = |order: &str| -> Vec<String> {
+        let listing = run(&tree, &["-R", "-1", order]);
+        listing.lines().filter(|line| line.ends_with(':')).map(str::to_string).collect()
+    };
+    assert_eq!(headers("--recurse-order=pre"), [".:", "./a:", "./a/deep:", "./b:"]);
+    assert_eq!(headers("--recurse-order=post"), ["./a/deep:", "./a:", "./b:", ".:"]);
+    assert_eq!(headers("--recurse-order=breadth"), [".:", "./a:", "./b:", "./a/deep:"]);
+
+    // The sections themselves are the same, only their order changes
+    let mut pre: Vec<String> = run(&tree, &["-R", "-1"]).split("\n\n").map(|s| s.trim_end().to_string()).collect();
+    let mut post: Vec<String> = run(&tree, &["-R", "-1", "--recurse-order=post"]).split("\n\n").map(|s| s.trim_end().to_string()).collect();
+    pre.sort();
+    post.sort();
+    assert_eq!(pre, post);
+}
+
+#[test]
+fn sort_args_orders_directory_operands_after_missing_ones() {
+    let fixture = Fixture::new();
+    let root = fixture.dir.path();
+    let headers = |args: &[&str]| -> Vec<String> {
+        let listing = run(&fixture.path("tree"), args);
+        listing.lines().filter(|line| line.ends_with(':')).map(str::to_string).collect()
+    };
+    assert_eq!(headers(&["-1", "b", "a"]), ["b:", "a:"]);
+    assert_eq!(headers(&["-1", "--sort-args", "b", "a"]), ["a:", "b:"]);
+    assert_eq!(headers(&["-1", "--sort-args", "-r", "a", "b"]), ["b:", "a:"]);
+
+    // Errors for missing operands come before any listing, even on a shared stream
+    let log = root.join("combined.log");
+    let file = std::fs::File::create(&log).unwrap();
+    std::process::Command::new(assert_cmd::cargo::cargo_bin("ls-rs"))
+        .current_dir(fixture.path("tree"))
+        .args(["-1", "--sort-args", "b", "missing", "a"])
+        .stdout(file.try_clone().unwrap())
+        .stderr(file)
+        .status()
+        .unwrap();
+    let combined = std::fs::read_to_string(&log).unwrap();
+    assert!(combined.starts_with("ls: cannot access 'missing'"), "{}", combined);
+    assert!(combined.find("a:").unwrap() < combined.find("b:").unwrap());
+}
+
+#[test]
+fn porcelain_prints_one_stable_record_per_entry() {
+    let fixture = Fixture::new();
+    let listing = run(&fixture.path("files"), &["--porcelain", "alpha.txt", "link", "日本語.txt"]);
+    let records: Vec<Vec<&str>> = listing.lines().map(|line| line.split('\t').collect()).collect();
+    assert!(records.iter().all(|fields| fields.len() == 13 && fields[0] == "1"), "{}", listing);
+
+    let alpha = &records[0];
+    assert_eq!((alpha[1], alpha[2], alpha[7]), ("f", "100644", "5"));
+    assert_eq!(alpha[9], (common::BASE_TIME + 3600).to_string());
+    assert_eq!((alpha[11], alpha[12]), ("alpha.txt", ""));
+    let link = &records[1];
+    assert_eq!((link[1], link[11], link[12]), ("l", "link", "alpha.txt"));
+    assert_eq!(records[2][11], "\\346\\227\\245\\346\\234\\254\\350\\252\\236.txt");
+
+    // -R gives paths instead of headers, blank lines and totals
+    let tree = run(fixture.dir.path(), &["--porcelain", "-R", "-l", "tree"]);
+    assert!(tree.lines().all(|line| line.starts_with("1\t")), "{}", tree);
+    assert!(tree.contains("\ttree/a/deep/two.txt\t"));
+}
+
+#[test]
+fn porcelain_version_two_adds_the_rest_of_stat() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("files");
+    filetime::set_file_mtime(dir.join("gamma"), filetime::FileTime::from_unix_time(common::BASE_TIME, 250)).unwrap();
+    let listing = run(&dir, &["--porcelain=2", "alpha.txt", "gamma"]);
+    let records: Vec<Vec<&str>> = listing.lines().map(|line| line.split('\t').collect()).collect();
+    assert!(records.iter().all(|fields| fields.len() == 23 && fields[0] == "2"), "{}", listing);
+
+    let (alpha, gamma) = (&records[0], &records[1]);
+    assert_eq!((alpha[7], alpha[11]), ("5", "alpha.txt"));
+    assert_eq!(alpha[13], gamma[13], "both files are on one device");
+    assert_ne!(alpha[15], "0");
+    assert_eq!((alpha[18], gamma[18]), ("0", "250"));
+    // The flags field stays empty unless -O reads them
+    assert_eq!(alpha[20], "");
+    // Birth times are there when the filesystem records them, `-` when it doesn't
+    let birth = std::fs::metadata(dir.join("alpha.txt")).unwrap().created().ok().map(|time| time.duration_since(std::time::UNIX_EPOCH).unwrap());
+    match birth {
+        Some(birth) => assert_eq!((alpha[21], alpha[22]), (birth.as_secs().to_string().as_str(), birth.subsec_nanos().to_string().as_str())),
+        None => assert_eq!((alpha[21], alpha[22]), ("-", "-")),
+    }
+
+    let yaml = run(&dir, &["--yaml", "--no-lookup", "gamma"]);
+    let records: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(records[0]["raw_mode"].as_u64(), Some(0o100755));
+    assert_eq!(records[0]["mtime_nsec"].as_u64(), Some(250));
+    assert_eq!(records[0]["dev"].as_u64().map(|dev| dev.to_string()).as_deref(), Some(alpha[13]));
+    let birth = std::fs::metadata(dir.join("gamma")).unwrap().created().ok().map(|time| time.duration_since(std::time::UNIX_EPOCH).unwrap());
+    assert_eq!(records[0]["btime"].as_u64(), birth.map(|birth| birth.as_secs()));
+    assert_eq!(records[0]["btime_nsec"].as_u64(), birth.map(|birth| u64::from(birth.subsec_nanos())));
+    assert_eq!(records[0]["btime"].is_null(), birth.is_none());
+}
+
+#[test]
+fn yaml_lists_every_directory_as_one_sequence() {
+    let fixture = Fixture::new();
+    let listing = run(fixture.dir.path(), &["--yaml", "-R", "--no-lookup", "tree"]);
+    let records: serde_yaml::Value = serde_yaml::from_str(&listing).unwrap();
+    let records = records.as_sequence().unwrap();
+    let paths: Vec<&str> = records.iter().map(|r| r["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["tree/a", "tree/b", "tree/top.txt", "tree/a/deep", "tree/a/one.txt", "tree/a/deep/two.txt", "tree/b/three.txt"]);
+
+    let top = &records[2];
+    assert_eq!(top["type"].as_str(), Some("file"));
+    assert_eq!(top["mode"].as_str(), Some("0644"));
+    assert_eq!(top["user"].as_str(), top["uid"].as_u64().map(|uid| uid.to_string()).as_deref());
+}
+
+#[test]
+fn json_writes_one_object_per_line() {
+    let fixture = Fixture::new();
+    let listing = run(fixture.dir.path(), &["--json", "-R", "--no-lookup", "tree"]);
+    let records: Vec<serde_json::Value> = listing.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let paths: Vec<&str> = records.iter().map(|r| r["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["tree/a", "tree/b", "tree/top.txt", "tree/a/deep", "tree/a/one.txt", "tree/a/deep/two.txt", "tree/b/three.txt"]);
+    assert_eq!(records[2]["type"], "file");
+    assert_eq!(records[2]["mode"], "0644");
+
+    let listing = run(&fixture.path("files"), &["--json", "--check-links", "dangling", "link"]);
+    let links: Vec<serde_json::Value> = listing.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!((&links[0]["name"], &links[0]["link"]), (&"dangling".into(), &"dangling".into()));
+    assert_eq!((&links[1]["name"], &links[1]["link"]), (&"link".into(), &"ok".into()));
+}
+
+#[test]
+fn json_times_follow_the_time_zone() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::write(dir.join("stamped"), "").unwrap();
+    filetime::set_file_mtime(dir.join("stamped"), filetime::FileTime::from_unix_time(1_792_134_000, 0)).unwrap();
+
+    let mtime = |zone: &str| -> serde_json::Value {
+        let listing = run(dir, &["--json", &format!("--time-zone={}", zone), "stamped"]);
+        let record: serde_json::Value = serde_json::from_str(listing.trim_end()).unwrap();
+        assert_eq!(record["mtime"], 1_792_134_000);
+        record["mtime_iso"].clone()
+    };
+    assert_eq!(mtime("UTC"), "2026-10-16T07:00:00+00:00");
+    assert_eq!(mtime("Asia/Kolkata"), "2026-10-16T12:30:00+05:30");
+}
+
+#[test]
+fn cbor_writes_one_map_per_entry() {
+    let fixture = Fixture::new();
+    let output = common::ls(fixture.dir.path(), &["--format=cbor", "-R", "tree"]).output().unwrap();
+    assert!(output.status.success());
+
+    let mut reader = std::io::Cursor::new(output.stdout);
+    let mut paths = Vec::new();
+    while (reader.position() as usize) < reader.get_ref().len() {
+        let record: ciborium::Value = ciborium::from_reader(&mut reader).unwrap();
+        let map = record.as_map().unwrap();
+        let path = map.iter().find(|(key, _)| key.as_text() == Some("path")).unwrap().1.as_text().unwrap();
+        paths.push(path.to_string());
+    }
+    assert_eq!(paths, ["tree/a", "tree/b", "tree/top.txt", "tree/a/deep", "tree/a/one.txt", "tree/a/deep/two.txt", "tree/b/three.txt"]);
+}
+
+#[test]
+fn serve_answers_listing_requests_over_a_socket() {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    fn frame(stream: &mut UnixStream) -> Vec<u8> {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut bytes = vec![0; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut bytes).unwrap();
+        bytes
+    }
+
+    // Stops the server even when an assertion fails, which would otherwise hang the suite
+    struct Server(std::process::Child);
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    let fixture = Fixture::new();
+    let socket = fixture.dir.path().join("ls.sock");
+    let _server = Server(std::process::Command::new(assert_cmd::cargo::cargo_bin("ls-rs"))
+        .arg("--serve")
+        .arg(&socket)
+        .env_clear()
+        .env("TZ", "UTC")
+        .env("LC_ALL", "C")
+        .spawn()
+        .unwrap());
+    let request = |args: &[&str]| {
+        let mut stream = (0..100)
+            .find_map(|_| UnixStream::connect(&socket).ok().or_else(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                None
+            }))
+            .expect("the server never started listening");
+        let args: Vec<u8> = args.iter().flat_map(|arg| arg.bytes().chain([0])).collect();
+        stream.write_all(&(args.len() as u32).to_be_bytes()).unwrap();
+        stream.write_all(&args).unwrap();
+        let listing = frame(&mut stream);
+        let errors = frame(&mut stream);
+        let mut status = [0];
+        stream.read_exact(&mut status).unwrap();
+        (String::from_utf8(listing).unwrap(), String::from_utf8(errors).unwrap(), status[0])
+    };
+
+    let tree = fixture.dir.path().join("tree");
+    let (listing, errors, status) = request(&["-1", "-R", tree.to_str().unwrap()]);
+    assert_eq!(listing, run(fixture.dir.path(), &["-1", "-R", tree.to_str().unwrap()]));
+    assert_eq!((errors.as_str(), status), ("", 0));
+
+    // Errors come back in their own frame and do not stop the server
+    let missing = fixture.dir.path().join("missing");
+    let (listing, errors, status) = request(&["-1", missing.to_str().unwrap()]);
+    assert_eq!(listing, "");
+    assert!(errors.contains("missing"), "{}", errors);
+    assert_eq!(status, 2);
+    let (_, _, status) = request(&["--color=sometimes"]);
+    assert_eq!(status, 2);
+    let (listing, _, _) = request(&["-1", tree.to_str().unwrap()]);
+    assert_eq!(listing, "a\nb\ntop.txt\n");
+
+    // Only the server's user may connect, and nothing a request asks for runs
+    // commands or writes files
+    let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&socket).unwrap().permissions());
+    assert_eq!(mode & 0o777, 0o600);
+    let written = fixture.dir.path().join("written");
+    let operands = tree.join("top.txt");
+    for args in [
+        &["-l", "--column-cmd", &format!("x=touch {}", written.display()), tree.to_str().unwrap()][..],
+        &["--snapshot", written.to_str().unwrap(), tree.to_str().unwrap()],
+        &["--serve", written.to_str().unwrap()],
+        &["--files-from=-"],
+        &["--files-from", operands.to_str().unwrap()],
+    ] {
+        let (listing, errors, status) = request(args);
+        assert_eq!((listing.as_str(), status), ("", 2), "{:?}", args);
+        assert!(errors.contains("cannot be used in a request"), "{}", errors);
+    }
+    assert!(!written.exists());
+
+    // Owner names come from each request's own lookup, not an earlier one's
+    let top = fixture.dir.path().join("tree/top.txt");
+    let uid = std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(&top).unwrap());
+    for name in ["first", "second"] {
+        std::fs::write(fixture.path(name), format!("{}:x:{}:0::/:/bin/sh\n", name, uid)).unwrap();
+    }
+    let owner = |args: &[&str]| {
+        let args: Vec<&str> = ["-l"].iter().chain(args).copied().chain([top.to_str().unwrap()]).collect();
+        request(&args).0.split_whitespace().nth(2).unwrap().to_string()
+    };
+    assert_eq!(owner(&["--no-lookup"]), uid.to_string());
+    let first = fixture.path("first");
+    let second = fixture.path("second");
+    assert_eq!(owner(&["--passwd-file", first.to_str().unwrap()]), "first");
+    assert_eq!(owner(&["--passwd-file", second.to_str().unwrap()]), "second");
+    assert_eq!(owner(&["--no-lookup"]), uid.to_string());
+}
+
+#[test]
+fn dereference_reports_loops_instead_of_recursing() {
+    let fixture = Fixture::new();
+    let tree = fixture.dir.path().join("tree");
+    std::os::unix::fs::symlink("..", tree.join("a/up")).unwrap();
+    std::os::unix::fs::symlink("self", tree.join("self")).unwrap();
+
+    let output = common::ls(fixture.dir.path(), &["-1", "-L", "-R", "tree"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("tree/a/deep:").count(), 1, "{}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("ls: cannot access 'tree/self': Too many levels of symbolic links\n"), "{}", stderr);
+    assert!(stderr.contains("ls: tree/a/up: not listing already-listed directory\n"), "{}", stderr);
+
+    // -L lists the file a link points to
+    let link = run(fixture.dir.path(), &["-l", "-L", "--no-lookup", "files/link"]);
+    assert!(link.starts_with("-rw-r--r--") && link.ends_with(" files/link\n"), "{}", link);
+}
+
+#[test]
+fn dereference_both_adds_a_target_line_under_each_link() {
+    let fixture = Fixture::new();
+    let listing = run(fixture.dir.path(), &["-l", "--dereference-both", "--no-lookup", "files"]);
+    let lines: Vec<&str> = listing.lines().collect();
+    let link = lines.iter().position(|line| line.ends_with(" link -> alpha.txt")).unwrap();
+    assert!(lines[link + 1].starts_with("    => -rw-r--r-- "), "{}", listing);
+    assert!(lines[link + 1].contains(" 5 Feb "), "{}", listing);
+    let dangling = lines.iter().position(|line| line.ends_with(" dangling -> missing")).unwrap();
+    assert!(lines[dangling + 1].starts_with("    => ?????????? "), "{}", listing);
+    // Everything else keeps its single line
+    assert_eq!(lines.iter().filter(|line| line.starts_with("    => ")).count(), 2);
+}
+
+#[test]
+fn block_counts_prefix_names_outside_long_format() {
+    let fixture = Fixture::new();
+    let listing = run(&fixture.path("files"), &["-s", "-1"]);
+    let mut lines = listing.lines();
+    assert!(lines.next().unwrap().starts_with("total "), "{}", listing);
+    let names: Vec<&str> = lines.collect();
+    // Counts are right-aligned, so every name starts in the same column
+    let column = names[0].find("alpha.txt").unwrap();
+    for line in &names {
+        let (count, name) = line.split_at(column);
+        assert!(count.trim().parse::<u64>().is_ok() && count.ends_with(' '), "{}", listing);
+        assert!(!name.starts_with(' '), "{}", listing);
+    }
+
+    let columns = run(&fixture.path("tree"), &["-s", "-C"]);
+    assert!(columns.starts_with("total ") && columns.lines().nth(1).unwrap().contains(" a "), "{}", columns);
+    // -m writes each count right before its name without padding
+    let stream = run(&fixture.path("tree"), &["-s", "-m"]);
+    let items: Vec<&str> = stream.lines().nth(1).unwrap().split(", ").collect();
+    assert_eq!(items.len(), 3, "{}", stream);
+    assert!(items.iter().all(|item| item.split_once(' ').is_some_and(|(count, _)| count.parse::<u64>().is_ok())), "{}", stream);
+}
+
+#[test]
+fn inode_numbers_prefix_names_outside_long_format() {
+    use std::os::unix::fs::MetadataExt;
+
+    let fixture = Fixture::new();
+    let tree = fixture.path("tree");
+    let inode = |name: &str| std::fs::symlink_metadata(tree.join(name)).unwrap().ino();
+    let width = ["a", "b", "top.txt"].iter().map(|name| inode(name).to_string().len()).max().unwrap();
+
+    let listing = run(&tree, &["-i", "-1"]);
+    let expected: String = ["a", "b", "top.txt"].iter().map(|name| format!("{:>width$} {}\n", inode(name), name)).collect();
+    assert_eq!(listing, expected);
+
+    let columns = run(&tree, &["-i", "-x", "-w", "200"]);
+    assert!(columns.starts_with(&format!("{:>width$} a", inode("a"))), "{}", columns);
+    let stream = run(&tree, &["-i", "-m"]);
+    assert_eq!(stream, format!("{} a, {} b, {} top.txt\n", inode("a"), inode("b"), inode("top.txt")));
+}
+
+#[test]
+fn low_memory_listings_match_in_memory_ones() {
+    let fixture = Fixture::new();
+    for args in [&["-1"][..], &["-1", "-S"], &["-1", "-t", "-r"], &["-1", "-f"], &["-l", "--no-lookup"], &["-1", "-R", "-A"]] {
+        let spilled: Vec<&str> = args.iter().copied().chain(["--low-memory=3"]).collect();
+        for dir in ["files", "tree"] {
+            assert_eq!(run(&fixture.path(dir), &spilled), run(&fixture.path(dir), args), "{:?} in {}", args, dir);
+        }
+    }
+    // Columns need every name at once, so a spilled listing has one per line
+    assert_eq!(run(&fixture.path("files"), &["-C", "--low-memory=3"]), run(&fixture.path("files"), &["-1"]));
+    // The threshold counts names as read, hidden ones included
+    assert_eq!(run(&fixture.path("tree"), &["-C", "--low-memory=4"]), run(&fixture.path("tree"), &["-C"]));
+
+    // Names that collide in case land in different chunks and are still found
+    let cases = tempfile::tempdir().unwrap();
+    for name in ["a", "B", "c", "d", "e", "b", "A"] {
+        std::fs::write(cases.path().join(name), "").unwrap();
+    }
+    let listing = |args: &[&str]| {
+        let output = common::ls(cases.path(), args).output().unwrap();
+        (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+    assert_eq!(listing(&["-1", "--detect-case-collisions", "--low-memory=2"]), listing(&["-1", "--detect-case-collisions"]));
+
+    // These hold whole listings in memory before writing them
+    for other in ["--unordered", "--recurse-order=post", "--header-stats"] {
+        common::ls(&fixture.path("tree"), &["-R", "--low-memory=3", other]).assert().code(2);
+    }
+}
+
+#[test]
+fn head_and_tail_keep_part_of_each_sorted_listing() {
+    let fixture = Fixture::new();
+    let files = fixture.path("files");
+    let sorted = run(&files, &["-1", "-S"]);
+    let lines: Vec<&str> = sorted.lines().collect();
+    let lines_of = |range: std::ops::Range<usize>| lines[range].iter().map(|line| format!("{}\n", line)).collect::<String>();
+
+    assert_eq!(run(&files, &["-1", "-S", "--head", "3"]), lines_of(0..3));
+    assert_eq!(run(&files, &["-1", "-S", "--tail", "2"]), lines_of(lines.len() - 2..lines.len()));
+    assert_eq!(run(&files, &["-1", "-S", "--head", "100"]), sorted);
+    assert_eq!(run(&files, &["-1", "-S", "--head", "3", "--low-memory=4"]), lines_of(0..3));
+    assert_eq!(run(&files, &["-1", "-S", "--tail", "2", "--low-memory=4"]), lines_of(lines.len() - 2..lines.len()));
+
+    // -R still lists every subdirectory, even those cut from their parent's listing
+    let tree = run(&fixture.path("tree"), &["-1", "-R", "--head", "1"]);
+    assert_eq!(tree, ".:\na\n\n./a:\ndeep\n\n./a/deep:\ntwo.txt\n\n./b:\nthree.txt\n");
+
+    common::ls(&files, &["--head", "1", "--tail", "1"]).assert().code(2);
+}
+
+#[test]
+fn regex_and_find_filter_names() {
+    let fixture = Fixture::new();
+    let files = fixture.path("files");
+    assert_eq!(run(&files, &["-1", "--regex", r"\.(txt|md)$"]), "alpha.txt\nBeta.md\ncafé.txt\n日本語.txt\n");
+    assert_eq!(run(&files, &["-1", "--find", "ALPHA"]), "alpha.txt\n");
+    // Both filters and --ignore-glob must all let a name through
+    assert_eq!(run(&files, &["-1", "-A", "--regex", "^[a-z]", "--find", "a", "--ignore-glob", "*.log"]), "alpha.txt\ncafé.txt\ndangling\ngamma\nwith space\n");
+
+    common::ls(&files, &["--regex", "("]).assert().code(2);
+}
+
+#[test]
+fn column_commands_add_a_cell_per_entry() {
+    let fixture = Fixture::new();
+    let listing = run(&fixture.path("tree"), &["-l", "--no-lookup", "--column-cmd", "up=basename \"$1\" | tr a-z A-Z", "--column-cmd", "fails=exit 1"]);
+    let lines: Vec<&str> = listing.lines().skip(1).collect();
+    assert_eq!(lines.len(), 3, "{}", listing);
+    for (line, name) in lines.iter().zip(["a", "b", "top.txt"]) {
+        let cells = format!(" {:<7} ? {}", name.to_uppercase(), name);
+        assert!(line.ends_with(&cells), "{:?} does not end with {:?}", line, cells);
+    }
+
+    // Only long listings have room for the columns
+    assert_eq!(run(&fixture.path("tree"), &["-1", "--column-cmd", "up=exit 1"]), "a\nb\ntop.txt\n");
+    common::ls(&fixture.path("tree"), &["--column-cmd", "=true"]).assert().code(2);
+}
+
+#[test]
+fn color_scales_replace_the_file_type_colors() {
+    let fixture = Fixture::new();
+    // Every fixture file has the same owner, so every name gets the same hue
+    let output = common::ls(&fixture.path("tree"), &["-1", "--color=always", "--color-scale=owner"])
+        .env("TERM", "xterm-256color")
+        .output()
+        .unwrap();
+    let owner = String::from_utf8(output.stdout).unwrap();
+    let styles: std::collections::HashSet<&str> = owner.lines().map(|line| line.split('m').next().unwrap()).collect();
+    assert_eq!(styles.len(), 1, "{}", owner);
+    assert!(owner.starts_with("\x1b[38;5;"), "{}", owner);
+    // Without a TERM to say otherwise, only the 8 standard colors are used
+    let basic = run(&fixture.path("tree"), &["-1", "--color=always", "--color-scale=owner"]);
+    assert!(basic.lines().all(|line| line.starts_with("\x1b[3") && line.as_bytes()[4] == b'm'), "{}", basic);
+
+    let risk = run(&fixture.path("tree"), &["-1", "--color=always", "--color-scale=risk"]);
+    assert_eq!(risk, "a\nb\ntop.txt\n");
+    common::ls(&fixture.path("tree"), &["--color-scale=mood"]).assert().code(2);
+}
+
+#[test]
+fn du_counts_hard_links_once_unless_asked() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("usage");
+    std::fs::create_dir_all(dir.join("d/sub")).unwrap();
+    std::fs::write(dir.join("d/file"), vec![b'x'; 3000]).unwrap();
+    std::fs::hard_link(dir.join("d/file"), dir.join("d/sub/link")).unwrap();
+
+    let size = |args: &[&str]| -> u64 {
+        let listing = run(&dir, args);
+        let line = listing.lines().find(|line| line.ends_with(" d")).unwrap();
+        line.split_whitespace().nth(4).unwrap().parse().unwrap()
+    };
+    let plain = size(&["-l", "--no-lookup"]);
+    let once = size(&["-l", "--no-lookup", "--du"]);
+    assert!(once >= plain + 3000);
+    assert_eq!(size(&["-l", "--no-lookup", "--du", "--count-links"]), once + 3000);
+}
+
+#[test]
+fn fs_info_names_the_filesystem_and_marks_mount_points() {
+    use std::os::unix::fs::MetadataExt;
+
+    let fixture = Fixture::new();
+    // A single operand gets a header too, to carry the type
+    let listing = run(&fixture.path("tree"), &["--fs-info", "a"]);
+    let header = listing.lines().next().unwrap();
+    assert!(header.starts_with("a: (") && header.ends_with(')') && header.len() > "a: ()".len(), "{}", listing);
+    assert_eq!(listing.lines().skip(1).collect::<Vec<_>>(), ["deep", "one.txt"]);
+
+    let listing = run(&fixture.path("tree"), &["--fs-info=mounts", "a"]);
+    assert!(!listing.contains("[mount]"), "{}", listing);
+    // /proc is a mount point wherever it is on its own device
+    let proc_mounted = std::fs::metadata("/proc").is_ok_and(|proc| std::fs::metadata("/").is_ok_and(|root| root.dev() != proc.dev()));
+    if proc_mounted {
+        let root = run(&fixture.path("tree"), &["--fs-info=mounts", "/"]);
+        assert!(root.lines().any(|line| line == "proc [mount]"), "{}", root);
+    }
+}
+
+#[test]
+fn stats_report_on_stderr_after_the_listing() {
+    let fixture = Fixture::new();
+    let output = common::ls(&fixture.path("tree"), &["--stats", "-R"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), run(&fixture.path("tree"), &["-R"]));
+
+    let report = String::from_utf8(output.stderr).unwrap();
+    let labels: Vec<&str> = report.lines().map(|line| line.split("  ").next().unwrap()).collect();
+    assert_eq!(labels, ["entries scanned", "read", "stat", "sort", "format", "total"]);
+    // a, b and top.txt, then deep and one.txt, two.txt and three.txt
+    assert_eq!(report.lines().next(), Some("entries scanned  7"));
+}
+
+#[test]
+fn interrupt_stops_between_lines_with_status_130() {
+    use std::process::{Command, Stdio};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    for sub in ["a", "b"] {
+        std::fs::create_dir(dir.join(sub)).unwrap();
+        std::fs::write(dir.join(sub).join("inner.txt"), "").unwrap();
+    }
+    std::fs::write(dir.join("top.txt"), "").unwrap();
+    let interrupted = |args: &[&str]| {
+        let child = Command::new(assert_cmd::cargo::cargo_bin("ls-rs"))
+            .args(args)
+            .current_dir(dir)
+            .env_clear()
+            .env("TZ", "UTC")
+            .env("LC_ALL", "C")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+        let output = child.wait_with_output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+
+    // Every cell takes a while, so the signal lands in the middle of the listing
+    let (status, listing) = interrupted(&["-lR", "--no-lookup", "--column-cmd", "w=sleep 5", "--color=always"]);
+    assert_eq!(status, Some(130));
+    assert!(listing.is_empty() || listing.ends_with("\n\x1b[0m"), "{:?}", listing);
+
+    // The directory being listed is finished, and nothing below it is started
+    let (status, listing) = interrupted(&["-lR", "--no-lookup", "--column-cmd", "w=sleep 0.5", "--finish-on-interrupt"]);
+    assert_eq!(status, Some(130));
+    let names: Vec<&str> = listing.lines().skip(2).map(|line| line.rsplit(' ').next().unwrap()).collect();
+    assert_eq!(names, ["a", "b", "top.txt"], "{}", listing);
+}
+
+#[test]
+fn case_collisions_are_marked_and_reported() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    for name in ["README", "Readme", "readme.md", "Ärger", "ärger"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+
+    // A warning: the listing itself succeeded
+    let output = common::ls(dir, &["-1", "--detect-case-collisions"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "README [case]\nReadme [case]\nreadme.md\närger [case]\nÄrger [case]\n"
+    );
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "ls: '.': 4 names differ from another only in case\n");
+}
+
+#[test]
+fn names_in_a_dot_hidden_file_are_hidden_like_dotfiles() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("build")).unwrap();
+    for name in ["notes.txt", "src.rs"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    std::fs::write(dir.join(".hidden"), "build\nnotes.txt\n\nmissing\n").unwrap();
+
+    assert_eq!(run(dir, &["-1"]), "src.rs\n");
+    assert_eq!(run(dir, &["-1A"]), ".hidden\nbuild\nnotes.txt\nsrc.rs\n");
+}
+
+#[test]
+fn compact_shows_size_age_and_name() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    for (name, size, age) in [("fresh", 10, 150), ("hour", 2048, 2 * 3600), ("old", 5_000_000, 3 * 365 * 86_400)] {
+        std::fs::write(dir.join(name), vec![b'x'; size]).unwrap();
+        filetime::set_file_mtime(dir.join(name), filetime::FileTime::from_unix_time(now - age, 0)).unwrap();
+    }
+
+    assert_eq!(run(dir, &["--compact"]), " 10B 2m fresh\n2.0K 2h hour\n4.8M 3y old\n");
+    // The last format flag wins, as for the others in the group
+    assert!(run(dir, &["--compact", "-1"]).starts_with("fresh\n"));
+}
+
+#[test]
+fn truncate_clips_long_lines_to_the_width() {
+    let fixture = Fixture::new();
+    let full = run(&fixture.path("files"), &["-l", "--no-lookup", "alpha.txt", "link"]);
+    let clipped = run(&fixture.path("files"), &["-l", "--no-lookup", "--truncate", "--width=30", "alpha.txt", "link"]);
+    for (full, clipped) in full.lines().zip(clipped.lines()) {
+        assert_eq!(clipped.chars().count(), 30, "{}", clipped);
+        let kept = clipped.strip_suffix('…').unwrap();
+        assert!(full.starts_with(kept), "{} / {}", full, clipped);
+    }
+    // Lines that fit are left alone
+    assert_eq!(run(&fixture.path("files"), &["-l", "--no-lookup", "--truncate", "--width=200", "alpha.txt"]), run(&fixture.path("files"), &["-l", "--no-lookup", "alpha.txt"]));
+}
+
+#[test]
+fn header_stats_count_and_total_each_listing() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("big"), vec![b'x'; 3072]).unwrap();
+    std::fs::write(dir.join("sub/one"), vec![b'x'; 100]).unwrap();
+    std::fs::write(dir.join("sub/two"), "").unwrap();
+
+    let headers = |args: &[&str]| -> Vec<String> {
+        run(dir, args).lines().filter(|line| line.contains(':')).map(str::to_string).collect()
+    };
+    assert_eq!(headers(&["--header-stats", "sub", "big", "sub"]), ["sub: 2 files, 100B", "sub: 2 files, 100B"]);
+    for order in ["--recurse-order=pre", "--recurse-order=post", "--unordered"] {
+        let found = headers(&["-R", "--header-stats", order, "."]);
+        assert!(found.iter().any(|h| h.starts_with(".: 2 files, ")), "{}: {:?}", order, found);
+        assert!(found.contains(&"./sub: 2 files, 100B".to_string()), "{}: {:?}", order, found);
+    }
+    // Without the flag headers are unchanged
+    assert_eq!(headers(&["-R", "."]), [".:", "./sub:"]);
+}
+
+/// Checks that `line` leaves no color open at its end and never pads while one is open
+fn assert_resets_before_padding(line: &str) {
+    let mut open = false;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(sequence) = rest.strip_prefix("\x1b[") {
+            let end = sequence.find('m').expect("unterminated SGR sequence");
+            open = !matches!(&sequence[..end], "" | "0");
+            rest = &sequence[end + 1..];
+            continue;
+        }
+        assert!(!(open && rest.starts_with("  ")), "padding inside a colored span: {:?}", line);
+        rest = &rest[c.len_utf8()..];
+    }
+    assert!(!open, "color left open at the end of {:?}", line);
+}
+
+#[test]
+fn colored_output_resets_before_padding() {
+    let fixture = Fixture::new();
+    let sgr = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    for args in [&["-C", "--width=40"][..], &["-x", "--width=40"], &["-CF", "--width=60"], &["-l", "--no-lookup"], &["--long-grid", "--width=100"]] {
+        let colored = run(&fixture.path("tree"), &[args, &["-A", "--color=always"]].concat());
+        let plain = run(&fixture.path("tree"), &[args, &["-A", "--color=never"]].concat());
+        for line in colored.lines() {
+            assert_resets_before_padding(line);
+        }
+        // Colored cells are padded to the same columns as plain ones
+        let stripped = sgr.replace_all(&colored, "");
+        assert_eq!(stripped, plain, "{:?}", args);
+        assert!(colored.contains("\x1b["), "{:?}", args);
+    }
+}
+
+#[test]
+fn side_by_side_lines_up_names_across_directories() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("builds");
+    for (side, names) in [("old", &["app", "app.map", "lib.so"][..]), ("new", &["app", "lib.so", "lib.so.debug"])] {
+        std::fs::create_dir_all(dir.join(side)).unwrap();
+        for name in names {
+            std::fs::write(dir.join(side).join(name), "").unwrap();
+        }
+    }
+    assert_eq!(
+        run(&dir, &["--side-by-side", "old", "new"]),
+        "old           new\napp           app\napp.map\nlib.so        lib.so\n              lib.so.debug\n"
+    );
+    // Narrow columns shorten names in the middle
+    assert_eq!(run(&dir, &["--side-by-side", "--width=16", "-r", "old", "new"]).lines().nth(1), Some("         lib…bug"));
+
+    common::ls(&dir, &["--side-by-side", "old"]).assert().code(2);
+}
+
+#[test]
+fn time_zone_shifts_the_shown_times() {
+    let fixture = Fixture::new();
+    let file = fixture.path("stamped");
+    std::fs::write(&file, "").unwrap();
+    // 2024-03-10 12:00:00 UTC
+    filetime::set_file_mtime(&file, filetime::FileTime::from_unix_time(1_710_072_000, 0)).unwrap();
+
+    let time = |zone: &str| -> String {
+        let listing = run(&fixture.path(""), &["-l", "--time-style=iso", &format!("--time-zone={}", zone), "stamped"]);
+        listing.split_whitespace().skip(5).take(2).collect::<Vec<_>>().join(" ")
+    };
+    assert_eq!(time("UTC"), "2024-03-10 12:00:00");
+    assert_eq!(time("+05:30"), "2024-03-10 17:30:00");
+    assert_eq!(time("-0800"), "2024-03-10 04:00:00");
+    // The test environment's TZ=UTC stays in charge without the flag
+    assert_eq!(time("local"), "2024-03-10 12:00:00");
+    // Named zones come with their daylight saving rules, which began that morning in California
+    assert_eq!(time("Asia/Kolkata"), "2024-03-10 17:30:00");
+    assert_eq!(time("America/Los_Angeles"), "2024-03-10 05:00:00");
+    for zone in ["Mars/Olympus", "+1é1", "+é", "+25", "+05:60"] {
+        common::ls(&fixture.path(""), &[&format!("--time-zone={}", zone)]).assert().code(2);
+    }
+}
+
+#[test]
+fn stat_prints_a_block_per_entry() {
+    let fixture = Fixture::new();
+    let file = fixture.path("stamped");
+    std::fs::write(&file, "twelve bytes").unwrap();
+    std::os::unix::fs::symlink("stamped", fixture.path("link")).unwrap();
+    // 2024-03-10 12:00:00.123456789 UTC
+    filetime::set_file_times(&file, filetime::FileTime::from_unix_time(1_710_072_000, 5), filetime::FileTime::from_unix_time(1_710_072_000, 123_456_789))
+        .unwrap();
+
+    let listing = run(&fixture.path(""), &["--stat", "--time-zone=+01:00", "stamped", "link"]);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len() % 2, 0);
+    let (link, file) = lines.split_at(lines.len() / 2);
+    assert_eq!(link[0], "  File: link -> stamped");
+    assert!(link[1].ends_with(" symbolic link"), "{}", link[1]);
+    assert_eq!(file[0], "  File: stamped");
+    assert!(file[1].starts_with("  Size: 12 ") && file[1].ends_with(" regular file"), "{}", file[1]);
+    assert!(file[2].starts_with("Device: ") && file[2].contains("Links: 1"), "{}", file[2]);
+    assert!(file[3].starts_with("Access: (0"), "{}", file[3]);
+    assert_eq!(file[4], "Access: 2024-03-10 13:00:00.000000005 +0100");
+    assert_eq!(file[5], "Modify: 2024-03-10 13:00:00.123456789 +0100");
+    assert!(file[6].starts_with("Change: "));
+    assert!(file[7].starts_with(" Birth: "));
+    assert!(file[8].starts_with(" Mount: /"), "{}", file[8]);
+}
+
+#[test]
+fn prune_lists_matching_directories_without_entering_them() {
+    let fixture = Fixture::new();
+    let headers = |flags: &[&str]| -> Vec<String> {
+        run(&fixture.path("tree"), &[&["-RA"], flags].concat()).lines().filter(|line| line.ends_with(':')).map(String::from).collect()
+    };
+    assert_eq!(headers(&["--prune=a", "--prune=.*"]), [".:", "./b:"]);
+    assert_eq!(headers(&["--prune=dee?"]), [".:", "./.dot:", "./a:", "./b:"]);
+    assert!(run(&fixture.path("tree"), &["-R", "--prune=a"]).lines().any(|line| line == "a"));
+    common::ls(&fixture.path("tree"), &["--prune=a"]).assert().code(2);
+}
+
+#[test]
+fn check_links_marks_dangling_links_and_records_them() {
+    let fixture = Fixture::new();
+    let dir = fixture.path("links");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("real"), "").unwrap();
+    std::os::unix::fs::symlink("real", dir.join("good")).unwrap();
+    std::os::unix::fs::symlink("missing", dir.join("broken")).unwrap();
+
+    assert_eq!(run(&dir, &["-1", "--check-links"]), "broken [dangling]\ngood\nreal\n");
+    let yaml = run(&dir, &["--yaml", "--no-lookup", "--check-links"]);
+    let records: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(records[0]["link"].as_str(), Some("dangling"));
+    assert_eq!((records[1]["link"].as_str(), records[1]["link_hops"].as_u64()), (Some("ok"), Some(1)));
+    assert!(records[2].get("link").is_none());
+}
+
+#[test]
+fn owner_and_group_filters_take_names_or_ids() {
+    use std::os::unix::fs::MetadataExt;
+
+    let fixture = Fixture::new();
+    let meta = std::fs::metadata(fixture.path("files")).unwrap();
+    std::fs::write(fixture.path("passwd"), format!("builder:x:{}:{}::/:/bin/sh\n", meta.uid(), meta.gid())).unwrap();
+    std::fs::write(fixture.path("group"), format!("crew:x:{}:\n", meta.gid())).unwrap();
+    let everything = run(fixture.dir.path(), &["-1", "tree"]);
+    let listing = |filter: &[&str]| run(fixture.dir.path(), &[&["-1", "--passwd-file", "passwd", "--group-file", "group"], filter, &["tree"]].concat());
+
+    assert_eq!(listing(&["--owner", "builder"]), everything);
+    assert_eq!(listing(&["--owner", &meta.uid().to_string(), "--group", "crew"]), everything);
+    assert_eq!(listing(&["--group", &(meta.gid() + 1).to_string()]), "");
+    // A name nobody has is a mistake, not an empty filter
+    let unknown = common::ls(fixture.dir.path(), &["--passwd-file", "passwd", "--owner", "nobody-here", "tree"]).output().unwrap();
+    assert_eq!(unknown.status.code(), Some(2));
+    assert!(unknown.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("invalid argument 'nobody-here' for '--owner'"));
+}
+
+#[test]
+fn sort_ties_and_reversal_match_coreutils() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    // Same second throughout: a and b share a time, d, e and f share a later one
+    for (name, contents, nsec) in [("a", "a", 500_000_000), ("b", "b", 500_000_000), ("c", "c", 100_000_000), ("d", "d", 900_000_000), ("e", "e", 900_000_000), ("f", "ff", 900_000_000)] {
+        std::fs::write(dir.join(name), contents).unwrap();
+        let time = filetime::FileTime::from_unix_time(1_577_836_800, nsec);
+        filetime::set_file_times(dir.join(name), time, time).unwrap();
+    }
+    // Orders printed by GNU ls (coreutils 9.1) under LC_ALL=C for the same tree
+    for (flags, expected) in [
+        ("-1t", "d e f a b c"),
+        ("-1rt", "c b a f e d"),
+        ("-1tu", "d e f a b c"),
+        ("-1rtu", "c b a f e d"),
+        ("-1S", "f a b c d e"),
+        ("-1rS", "e d c b a f"),
+        ("-1r", "f e d c b a"),
+    ] {
+        assert_eq!(run(dir, &[flags]).split_whitespace().collect::<Vec<_>>().join(" "), expected, "{}", flags);
+    }
+}